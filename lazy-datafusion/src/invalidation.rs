@@ -0,0 +1,66 @@
+use std::sync::Weak;
+
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::Result;
+
+use crate::{Inner, LazyMemTable};
+
+/// Hook for external components that know when a table's source data has
+/// changed.
+#[async_trait]
+pub trait CacheInvalidation: Send + Sync {
+    /// Drop any cached data, the next query will load it again.
+    async fn invalidate(&self);
+
+    /// Add newly inserted rows to the cached data, avoiding a full reload.
+    /// Does nothing if the data isn't currently cached.
+    async fn append(&self, batches: Vec<RecordBatch>) -> Result<()>;
+
+    /// Forget the most recent load failure, so the next query loads again
+    /// straight away rather than waiting out the failure TTL. For when the
+    /// source is known to have recovered.
+    fn clear_failure(&self);
+}
+
+#[async_trait]
+impl CacheInvalidation for LazyMemTable {
+    async fn invalidate(&self) {
+        self.inner.invalidate().await
+    }
+
+    async fn append(&self, batches: Vec<RecordBatch>) -> Result<()> {
+        self.inner.append(batches).await
+    }
+
+    fn clear_failure(&self) {
+        self.inner.clear_failure()
+    }
+}
+
+#[derive(Clone)]
+pub struct InvalidationHandle {
+    pub(crate) inner: Weak<Inner>,
+}
+
+#[async_trait]
+impl CacheInvalidation for InvalidationHandle {
+    async fn invalidate(&self) {
+        if let Some(inner) = self.inner.upgrade() {
+            inner.invalidate().await
+        }
+    }
+
+    async fn append(&self, batches: Vec<RecordBatch>) -> Result<()> {
+        match self.inner.upgrade() {
+            Some(inner) => inner.append(batches).await,
+            None => Ok(()),
+        }
+    }
+
+    fn clear_failure(&self) {
+        if let Some(inner) = self.inner.upgrade() {
+            inner.clear_failure()
+        }
+    }
+}
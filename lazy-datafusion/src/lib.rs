@@ -1,57 +1,691 @@
+mod invalidation;
+mod memory;
+mod metrics;
+mod strategy;
+
 use std::{
     any::Any,
-    fmt,
+    cmp, fmt,
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use arc_swap::ArcSwap;
 use arrow::{
+    array::Array,
     datatypes::{Field, Schema, SchemaRef},
     error::Result as ArrowResult,
     record_batch::RecordBatch,
 };
 use async_trait::async_trait;
 use datafusion::{
-    datasource::{datasource::Statistics, MemTable, TableProvider},
+    datasource::{
+        datasource::{ColumnStatistics, Statistics, TableProviderFilterPushDown},
+        MemTable, TableProvider,
+    },
     error::{DataFusionError, Result},
-    logical_plan::Expr,
+    logical_plan::{Expr, Operator},
     physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream, SendableRecordBatchStream},
 };
-use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    future::{BoxFuture, FutureExt, FutureObj, RemoteHandle},
+    lock::Mutex as AsyncMutex,
+    stream::{self, Stream, StreamExt},
+    task::{Spawn, SpawnError, SpawnExt},
+};
 use pin_project::pin_project;
+use tokio::runtime::Handle;
+
+use crate::metrics::materialized_size;
+
+pub use crate::{
+    invalidation::{CacheInvalidation, InvalidationHandle},
+    memory::{MemoryLimit, MemoryLimitExceeded},
+    metrics::CacheMetrics,
+    strategy::{CacheStrategy, EagerCache},
+};
+
+// ExecutionConfig's default, used for refreshes before any query has run
+const DEFAULT_BATCH_SIZE: usize = 8192;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// total number of load attempts made before giving up, minimum 1
+    pub max_attempts: usize,
+    /// delay before the first retry, doubled for each subsequent retry
+    pub initial_backoff: Duration,
+    /// upper bound for the delay between retries
+    pub max_backoff: Duration,
+    /// how long a failed load is remembered, queries within this window
+    /// fail immediately rather than starting a new load
+    pub failure_ttl: Duration,
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff: Duration::from_secs(0),
+            max_backoff: Duration::from_secs(0),
+            failure_ttl: Duration::from_secs(0),
+        }
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .checked_mul(2u32.saturating_pow(retry))
+            .unwrap_or(self.max_backoff);
+        cmp::min(backoff, self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            failure_ttl: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LazyMemTableOptions {
+    pub retry_policy: RetryPolicy,
+    /// where load and refresh tasks are run, defaults to `tokio::spawn`
+    pub spawner: Arc<dyn Spawn + Send + Sync>,
+    /// waits between refreshes and load retries, defaults to tokio's timer
+    pub sleep: Arc<dyn Sleep>,
+    pub strategy: Arc<dyn CacheStrategy>,
+    /// number of partitions cached data is split into, so queries against it
+    /// can run in parallel, defaults to the number of CPUs
+    pub partitions: usize,
+    /// only load the rows matching the filters of the query that triggers
+    /// the load, rather than the whole table. Later queries that don't
+    /// include all of those filters bypass the cache
+    pub filter_scoped: bool,
+    /// budget the data loaded by a query counts against, loads exceeding it
+    /// fail without retrying
+    pub memory_limit: Option<Arc<MemoryLimit>>,
+}
+
+impl Default for LazyMemTableOptions {
+    fn default() -> Self {
+        Self {
+            retry_policy: RetryPolicy::default(),
+            spawner: Arc::new(TokioSpawner::new()),
+            sleep: Arc::new(TokioSleep),
+            strategy: Arc::new(EagerCache),
+            partitions: num_cpus::get(),
+            filter_scoped: false,
+            memory_limit: None,
+        }
+    }
+}
 
 pub struct LazyMemTable {
-    inner: Arc<ArcSwap<State>>,
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    provider: Box<dyn TableProvider + Send + Sync>,
+    state: ArcSwap<State>,
+    retry_policy: RetryPolicy,
+    spawner: Arc<dyn Spawn + Send + Sync>,
+    sleep: Arc<dyn Sleep>,
+    strategy: Arc<dyn CacheStrategy>,
+    partitions: usize,
+    filter_scoped: bool,
+    memory_limit: Option<Arc<MemoryLimit>>,
+    // held for the duration of a load, so concurrent queries wait for the
+    // in-progress load rather than all starting their own
+    load_lock: AsyncMutex<()>,
+    last_failure: Mutex<Option<(Instant, String)>>,
+    // batch size of the most recent load, reused for refreshes
+    batch_size: AtomicUsize,
+    metrics: Arc<CacheMetrics>,
 }
 
 enum State {
-    Lazy(Box<dyn TableProvider + Send + Sync>),
-    Loaded(MemTable),
+    Lazy,
+    Loaded(Loaded),
+}
+
+struct Loaded {
+    mem: MemTable,
+    // MemTable doesn't give access to its data, so we keep our own reference
+    // to be able to append to it
+    data: Vec<Vec<RecordBatch>>,
+    statistics: Statistics,
+    loaded_at: Instant,
+    // filters applied when loading, empty unless filter scoped
+    filters: Vec<Expr>,
+}
+
+impl Loaded {
+    fn try_new(schema: SchemaRef, data: Vec<Vec<RecordBatch>>, filters: Vec<Expr>) -> Result<Self> {
+        let statistics = statistics(&schema, &data);
+        let mem = MemTable::try_new(schema, data.clone())?;
+        Ok(Self {
+            mem,
+            data,
+            statistics,
+            loaded_at: Instant::now(),
+            filters,
+        })
+    }
+
+    // whether a query with `filters` can be answered from this data, which
+    // is when the query is at least as selective as the load. DataFusion
+    // reapplies the query's filters, so any extra rows are removed.
+    fn covers(&self, filters: &[Expr]) -> bool {
+        let filters = filters.iter().map(normalize).collect::<Vec<_>>();
+        self.filters
+            .iter()
+            .all(|loaded| filters.contains(&normalize(loaded)))
+    }
+}
+
+// `expr` without aliases, and with comparisons against a literal written
+// column first, so equivalent filters compare equal
+fn normalize(expr: &Expr) -> Expr {
+    match expr {
+        Expr::Alias(expr, _) => normalize(expr),
+        Expr::Not(expr) => Expr::Not(Box::new(normalize(expr))),
+        Expr::BinaryExpr { left, op, right } => {
+            let left = normalize(left);
+            let right = normalize(right);
+            match (&left, flip(op)) {
+                (Expr::Literal(_), Some(op)) if !matches!(right, Expr::Literal(_)) => {
+                    Expr::BinaryExpr {
+                        left: Box::new(right),
+                        op,
+                        right: Box::new(left),
+                    }
+                }
+                _ => Expr::BinaryExpr {
+                    left: Box::new(left),
+                    op: op.clone(),
+                    right: Box::new(right),
+                },
+            }
+        }
+        expr => expr.clone(),
+    }
+}
+
+// the operator for the same comparison with the operands swapped
+fn flip(op: &Operator) -> Option<Operator> {
+    match op {
+        Operator::Eq => Some(Operator::Eq),
+        Operator::NotEq => Some(Operator::NotEq),
+        Operator::Lt => Some(Operator::Gt),
+        Operator::LtEq => Some(Operator::GtEq),
+        Operator::Gt => Some(Operator::Lt),
+        Operator::GtEq => Some(Operator::LtEq),
+        _ => None,
+    }
 }
 
 impl LazyMemTable {
     pub fn new<T>(provider: T) -> LazyMemTable
+    where
+        T: TableProvider + Send + Sync + 'static,
+    {
+        Self::new_with_options(provider, LazyMemTableOptions::default())
+    }
+
+    pub fn new_with_options<T>(provider: T, options: LazyMemTableOptions) -> LazyMemTable
     where
         T: TableProvider + Send + Sync + 'static,
     {
         LazyMemTable {
-            inner: Arc::new(ArcSwap::from_pointee(State::Lazy(Box::new(provider)))),
+            inner: Arc::new(Inner {
+                provider: Box::new(provider),
+                state: ArcSwap::from_pointee(State::Lazy),
+                retry_policy: options.retry_policy,
+                spawner: options.spawner,
+                sleep: options.sleep,
+                strategy: options.strategy,
+                partitions: cmp::max(options.partitions, 1),
+                filter_scoped: options.filter_scoped,
+                memory_limit: options.memory_limit,
+                load_lock: AsyncMutex::new(()),
+                last_failure: Mutex::new(None),
+                batch_size: AtomicUsize::new(DEFAULT_BATCH_SIZE),
+                metrics: Arc::new(CacheMetrics::default()),
+            }),
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<CacheMetrics> {
+        self.inner.metrics.clone()
+    }
+
+    /// The table whose data is cached.
+    pub fn provider(&self) -> &(dyn TableProvider + Send + Sync) {
+        self.inner.provider.as_ref()
+    }
+
+    /// Returns a handle that can be passed to external components, such as
+    /// a change stream listener, to keep the cached data up to date.
+    ///
+    /// The handle doesn't keep the table alive, once the table is dropped
+    /// calls to the handle do nothing.
+    pub fn invalidation_handle(&self) -> InvalidationHandle {
+        InvalidationHandle {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Reload the data from the underlying provider, replacing the current
+    /// data once the reload completes. Does nothing if the table hasn't been
+    /// loaded yet.
+    pub async fn refresh(&self) -> Result<()> {
+        self.inner.refresh().await
+    }
+
+    /// Spawn a background task that refreshes the table every `interval`.
+    ///
+    /// The task ends when the table is dropped. Failed refreshes leave the
+    /// existing data in place to be retried at the next interval. A zero
+    /// interval is an error.
+    pub fn refresh_every(&self, interval: Duration) -> Result<()> {
+        if interval == Duration::from_secs(0) {
+            return Err(DataFusionError::Plan(
+                "refresh interval must be greater than zero".to_owned(),
+            ));
+        }
+        let inner = Arc::downgrade(&self.inner);
+        let sleep = self.inner.sleep.clone();
+        self.inner
+            .spawner
+            .spawn(async move {
+                loop {
+                    sleep.sleep(interval).await;
+                    let inner = match inner.upgrade() {
+                        Some(v) => v,
+                        None => break,
+                    };
+                    if let State::Loaded(ref v) = *inner.state.load_full() {
+                        if !inner.strategy.should_refresh(v.loaded_at, &inner.metrics) {
+                            continue;
+                        }
+                    }
+                    let _ = inner.refresh().await;
+                }
+            })
+            .map_err(|e| DataFusionError::Execution(format!("could not spawn refresh: {}", e)))
+    }
+}
+
+impl Inner {
+    // loads the data, sending each batch to `tx` as it arrives so the query
+    // that triggered the load doesn't have to wait for it to complete
+    async fn load_streaming(
+        &self,
+        batch_size: usize,
+        filters: Vec<Expr>,
+        tx: UnboundedSender<ArrowResult<RecordBatch>>,
+    ) {
+        let _guard = self.load_lock.lock().await;
+
+        // another query may have completed the load while we were waiting
+        if let State::Loaded(ref v) = *self.state.load_full() {
+            if v.covers(&filters) {
+                for batch in v.data.iter().flatten() {
+                    if tx.unbounded_send(Ok(batch.clone())).is_err() {
+                        break;
+                    }
+                }
+            } else {
+                let tee = Tee {
+                    tx,
+                    sent: Arc::new(AtomicBool::new(false)),
+                };
+                let provider = self.provider.as_ref();
+                let spawner = self.spawner.as_ref();
+                let memory_limit = self.memory_limit.as_ref();
+                if let Err(e) = load(
+                    provider,
+                    spawner,
+                    batch_size,
+                    &filters,
+                    Some(&tee),
+                    memory_limit,
+                )
+                .await
+                {
+                    let _ = tee.tx.unbounded_send(Err(e.into_arrow_external_error()));
+                }
+            }
+            return;
+        }
+
+        if let Some((failed_at, ref message)) = *self.last_failure.lock().unwrap() {
+            if failed_at.elapsed() < self.retry_policy.failure_ttl {
+                let e = DataFusionError::Execution(format!(
+                    "load failed {:?} ago, not retrying yet: {}",
+                    failed_at.elapsed(),
+                    message
+                ));
+                let _ = tx.unbounded_send(Err(e.into_arrow_external_error()));
+                return;
+            }
+        }
+
+        let tee = Tee {
+            tx,
+            sent: Arc::new(AtomicBool::new(false)),
+        };
+        match self.load_with_retry(batch_size, filters, Some(&tee)).await {
+            Ok(loaded) => {
+                *self.last_failure.lock().unwrap() = None;
+                self.state.store(Arc::new(loaded));
+            }
+            // the query stopped reading, that's not a failure of the load
+            Err(_) if tee.is_closed() => tracing::info!("cache load cancelled"),
+            Err(e) => {
+                self.metrics.record_load_failure();
+                // a load over the memory limit may succeed straight away
+                // with a higher one, so don't hold off retrying
+                if !self.memory_limit.as_ref().map_or(false, |m| m.exceeded()) {
+                    *self.last_failure.lock().unwrap() = Some((Instant::now(), e.to_string()));
+                }
+                let _ = tee.tx.unbounded_send(Err(e.into_arrow_external_error()));
+            }
+        }
+    }
+
+    async fn refresh(&self) -> Result<()> {
+        let _guard = self.load_lock.lock().await;
+
+        let filters = match *self.state.load_full() {
+            State::Lazy => return Ok(()),
+            State::Loaded(ref v) => v.filters.clone(),
+        };
+
+        tracing::info!("cache refresh started");
+        let loaded = self
+            .load_with_retry(self.batch_size.load(Ordering::Relaxed), filters, None)
+            .await
+            .map_err(|e| {
+                self.metrics.record_load_failure();
+                e
+            })?;
+        // recorded as a load, no data was dropped
+        self.state.store(Arc::new(loaded));
+        Ok(())
+    }
+
+    async fn invalidate(&self) {
+        let _guard = self.load_lock.lock().await;
+        self.evict();
+    }
+
+    fn clear_failure(&self) {
+        *self.last_failure.lock().unwrap() = None;
+    }
+
+    fn evict(&self) {
+        if let State::Loaded(_) = *self.state.swap(Arc::new(State::Lazy)) {
+            tracing::info!("cache evicted");
+            self.metrics.record_eviction();
+            self.metrics.record_size(&[]);
+        }
+    }
+
+    fn should_evict(&self, state: &State) -> bool {
+        match *state {
+            State::Lazy => false,
+            State::Loaded(ref v) => self.strategy.should_evict(v.loaded_at, &self.metrics),
+        }
+    }
+
+    async fn append(&self, batches: Vec<RecordBatch>) -> Result<()> {
+        let _guard = self.load_lock.lock().await;
+
+        let state = self.state.load_full();
+        let loaded = match *state {
+            // nothing cached, the next load will read the new data anyway
+            State::Lazy => return Ok(()),
+            State::Loaded(ref v) => v,
+        };
+
+        let mut data = loaded.data.clone();
+        match data.last_mut() {
+            Some(partition) => partition.extend(batches),
+            None => data.push(batches),
+        }
+        self.metrics.record_size(&data);
+        let mut appended = Loaded::try_new(self.provider.schema(), data, loaded.filters.clone())?;
+        appended.loaded_at = loaded.loaded_at;
+        self.state.store(Arc::new(State::Loaded(appended)));
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "info", name = "cache_load", skip(self, filters, tee))]
+    async fn load_with_retry(
+        &self,
+        batch_size: usize,
+        filters: Vec<Expr>,
+        tee: Option<&Tee>,
+    ) -> Result<State> {
+        self.batch_size.store(batch_size, Ordering::Relaxed);
+        // only loads for a query count against the memory limit, not
+        // refreshes
+        let memory_limit = tee.and(self.memory_limit.as_ref());
+        let mut attempt = 1;
+        loop {
+            tracing::info!(attempt, "cache load started");
+            let start = Instant::now();
+            let provider = self.provider.as_ref();
+            let spawner = self.spawner.as_ref();
+            match load(provider, spawner, batch_size, &filters, tee, memory_limit).await {
+                Ok(data) => {
+                    tracing::info!(
+                        attempt,
+                        elapsed = ?start.elapsed(),
+                        rows = data.iter().flatten().map(|b| b.num_rows()).sum::<usize>(),
+                        "cache loaded"
+                    );
+                    self.metrics.record_load(start.elapsed(), &data);
+                    let data = repartition(data, self.partitions);
+                    return Ok(State::Loaded(Loaded::try_new(
+                        self.provider.schema(),
+                        data,
+                        filters,
+                    )?));
+                }
+                // can't retry once batches have been sent on, as the
+                // receiver would see them twice
+                Err(e)
+                    if attempt < self.retry_policy.max_attempts
+                        && !tee
+                            .map_or(false, |t| t.sent.load(Ordering::Relaxed) || t.is_closed())
+                        && !memory_limit.map_or(false, |m| m.exceeded()) =>
+                {
+                    tracing::warn!(attempt, error = %e, "cache load failed, retrying");
+                    let backoff = self.retry_policy.backoff(attempt as u32 - 1);
+                    self.sleep.sleep(backoff).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    tracing::error!(attempt, error = %e, "cache load failed");
+                    return Err(e);
+                }
+            }
         }
     }
 }
 
+// distributes batches round-robin across `partitions` partitions, or fewer
+// if there aren't enough batches to go round
+fn repartition(data: Vec<Vec<RecordBatch>>, partitions: usize) -> Vec<Vec<RecordBatch>> {
+    let batches = data.into_iter().flatten().collect::<Vec<_>>();
+    let partitions = cmp::max(cmp::min(partitions, batches.len()), 1);
+    let mut repartitioned = vec![Vec::new(); partitions];
+    for (i, batch) in batches.into_iter().enumerate() {
+        repartitioned[i % partitions].push(batch);
+    }
+    repartitioned
+}
+
+#[derive(Clone)]
+struct Tee {
+    tx: UnboundedSender<ArrowResult<RecordBatch>>,
+    sent: Arc<AtomicBool>,
+}
+
+impl Tee {
+    // true once the query the batches are sent to has been dropped
+    fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+async fn load(
+    provider: &(dyn TableProvider + Send + Sync),
+    spawner: &(dyn Spawn + Send + Sync),
+    batch_size: usize,
+    filters: &[Expr],
+    tee: Option<&Tee>,
+    memory_limit: Option<&Arc<MemoryLimit>>,
+) -> Result<Vec<Vec<RecordBatch>>> {
+    // this inlines MemTable::load as the compiler got confused
+    // about the TableProvider not implimenting Send + Sync
+
+    let exec = provider.scan(&None, batch_size, filters)?;
+    let partition_count = exec.output_partitioning().partition_count();
+
+    // dropping a RemoteHandle cancels its task, so if the query is dropped
+    // part way through the load the tasks stop rather than continuing to
+    // pull data that is never used
+    let tasks = (0..partition_count)
+        .map(|part_i| {
+            let exec = exec.clone();
+            let tee = tee.cloned();
+            let memory_limit = memory_limit.cloned();
+            spawner
+                .spawn_with_handle(async move {
+                    let mut stream = exec.execute(part_i).await?;
+                    let mut batches = Vec::new();
+                    while let Some(batch) = stream.next().await {
+                        let batch = batch?;
+                        tracing::trace!(
+                            partition = part_i,
+                            rows = batch.num_rows(),
+                            "batch loaded"
+                        );
+                        if let Some(ref memory_limit) = memory_limit {
+                            memory_limit.reserve(&batch)?;
+                        }
+                        if let Some(ref tee) = tee {
+                            tee.tx.unbounded_send(Ok(batch.clone())).map_err(|_| {
+                                DataFusionError::Execution("load cancelled".to_owned())
+                            })?;
+                            tee.sent.store(true, Ordering::Relaxed);
+                        }
+                        batches.push(batch);
+                    }
+                    Ok::<_, DataFusionError>(batches)
+                })
+                .map_err(|e| DataFusionError::Execution(format!("could not spawn load: {}", e)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut data: Vec<Vec<RecordBatch>> = Vec::with_capacity(partition_count);
+    for task in tasks {
+        data.push(task.await?);
+    }
+
+    Ok(data)
+}
+
+// exact statistics for the materialized data, the source's statistics are
+// often estimates, or just the default of all unknown
+fn statistics(schema: &Schema, data: &[Vec<RecordBatch>]) -> Statistics {
+    let batches = data.iter().flatten();
+    let num_rows = batches.clone().map(|batch| batch.num_rows()).sum();
+    let column_statistics = (0..schema.fields().len())
+        .map(|i| ColumnStatistics {
+            null_count: Some(
+                batches
+                    .clone()
+                    .map(|batch| batch.column(i).null_count())
+                    .sum(),
+            ),
+        })
+        .collect();
+    Statistics {
+        num_rows: Some(num_rows),
+        total_byte_size: Some(materialized_size(data)),
+        column_statistics: Some(column_statistics),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TokioSpawner {
+    handle: Option<Handle>,
+}
+
+impl TokioSpawner {
+    /// Spawn on the runtime the task is running on, as `tokio::spawn`.
+    pub fn new() -> Self {
+        Self { handle: None }
+    }
+
+    /// Spawn on a specific runtime.
+    pub fn with_handle(handle: Handle) -> Self {
+        Self {
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Spawn for TokioSpawner {
+    fn spawn_obj(&self, future: FutureObj<'static, ()>) -> std::result::Result<(), SpawnError> {
+        match self.handle {
+            Some(ref handle) => handle.spawn(future),
+            None => tokio::spawn(future),
+        };
+        Ok(())
+    }
+}
+
+/// A timer, so the table can be used without a tokio runtime along with a
+/// `Spawn` for another executor.
+pub trait Sleep: Send + Sync {
+    /// Returns a future that completes after `duration`.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// Sleeps with `tokio::time::delay_for`, which needs a tokio runtime with
+/// the timer enabled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioSleep;
+
+impl Sleep for TokioSleep {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        tokio::time::delay_for(duration).boxed()
+    }
+}
+
 impl TableProvider for LazyMemTable {
     fn as_any(&self) -> &dyn Any {
         self
     }
 
     fn schema(&self) -> SchemaRef {
-        match **self.inner.load() {
-            State::Lazy(ref v) => v.schema(),
-            State::Loaded(ref v) => v.schema(),
-        }
+        self.inner.provider.schema()
     }
 
     fn scan(
@@ -60,8 +694,20 @@ impl TableProvider for LazyMemTable {
         batch_size: usize,
         filters: &[Expr],
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        match **self.inner.load() {
-            State::Lazy(ref v) => {
+        let mut state = self.inner.state.load_full();
+        if self.inner.should_evict(&state) {
+            self.inner.evict();
+            state = self.inner.state.load_full();
+        }
+
+        match *state {
+            State::Lazy if !self.inner.strategy.should_load(&self.inner.metrics) => {
+                tracing::debug!("cache miss, not loading yet");
+                self.inner.metrics.record_miss();
+                self.inner.provider.scan(projection, batch_size, filters)
+            }
+            State::Lazy => {
+                let v = &self.inner.provider;
                 let projected_schema = match projection {
                     Some(columns) => {
                         let projected_columns: Result<Vec<Field>> = columns
@@ -87,20 +733,40 @@ impl TableProvider for LazyMemTable {
                     scan_args: (projection.clone(), batch_size, filters.to_vec()),
                 }))
             }
-            State::Loaded(ref v) => v.scan(projection, batch_size, filters),
+            State::Loaded(ref v) if !v.covers(filters) => {
+                tracing::debug!("cache miss, filters not covered by cached data");
+                self.inner.metrics.record_miss();
+                self.inner.provider.scan(projection, batch_size, filters)
+            }
+            State::Loaded(ref v) => {
+                tracing::debug!("cache hit");
+                self.inner.metrics.record_hit();
+                v.mem.scan(projection, batch_size, filters)
+            }
+        }
+    }
+
+    fn supports_filter_pushdown(&self, filter: &Expr) -> Result<TableProviderFilterPushDown> {
+        // cached data is returned unfiltered, so at best DataFusion can
+        // treat the filter as a hint
+        match self.inner.provider.supports_filter_pushdown(filter)? {
+            TableProviderFilterPushDown::Unsupported => {
+                Ok(TableProviderFilterPushDown::Unsupported)
+            }
+            _ => Ok(TableProviderFilterPushDown::Inexact),
         }
     }
 
     fn statistics(&self) -> Statistics {
-        match **self.inner.load() {
-            State::Lazy(ref v) => v.statistics(),
-            State::Loaded(ref v) => v.statistics(),
+        match **self.inner.state.load() {
+            State::Lazy => self.inner.provider.statistics(),
+            State::Loaded(ref v) => v.statistics.clone(),
         }
     }
 }
 
 struct LazyExec {
-    parent: Arc<ArcSwap<State>>,
+    parent: Arc<Inner>,
     projected_schema: SchemaRef,
     scan_args: (Option<Vec<usize>>, usize, Vec<Expr>),
 }
@@ -140,41 +806,44 @@ impl ExecutionPlan for LazyExec {
     }
 
     async fn execute(&self, _partition: usize) -> Result<SendableRecordBatchStream> {
-        match **self.parent.load() {
-            State::Lazy(ref v) => {
-                // this inlines MemTable::load as the compiler got confused
-                // about the TableProvider not implimenting Send + Sync
-
-                let exec = v.scan(&None, self.scan_args.1, &[])?;
-                let partition_count = exec.output_partitioning().partition_count();
-
-                let tasks = (0..partition_count)
-                    .map(|part_i| {
-                        let exec = exec.clone();
-                        tokio::spawn(async move {
-                            let stream = exec.execute(part_i).await?;
-                            stream
-                                .try_collect::<Vec<_>>()
-                                .await
-                                .map_err(DataFusionError::from)
-                        })
+        match *self.parent.state.load_full() {
+            State::Lazy => {
+                self.parent.metrics.record_miss();
+                let (tx, rx) = mpsc::unbounded();
+                let parent = self.parent.clone();
+                let batch_size = self.scan_args.1;
+                let filters = if self.parent.filter_scoped {
+                    self.scan_args.2.clone()
+                } else {
+                    Vec::new()
+                };
+                let load = self
+                    .parent
+                    .spawner
+                    .spawn_with_handle(async move {
+                        parent.load_streaming(batch_size, filters, tx).await
                     })
-                    .collect::<Vec<_>>();
-
-                let mut data: Vec<Vec<RecordBatch>> =
-                    Vec::with_capacity(exec.output_partitioning().partition_count());
-                for task in tasks {
-                    let result = task.await.expect("MemTable::load could not join task")?;
-                    data.push(result);
-                }
-
-                let mem = MemTable::try_new(v.schema().clone(), data)?;
-
-                self.parent.swap(Arc::new(State::Loaded(mem)));
-                self.execute(0).await
+                    .map_err(|e| {
+                        DataFusionError::Execution(format!("could not spawn load: {}", e))
+                    })?;
+                Ok(Box::pin(LoadingStream {
+                    schema: self.projected_schema.clone(),
+                    projection: self.scan_args.0.clone(),
+                    _load: load,
+                    inner: rx,
+                }))
             }
             State::Loaded(ref v) => {
-                let exec = v.scan(&self.scan_args.0, self.scan_args.1, &self.scan_args.2)?;
+                let (projection, batch_size, filters) = &self.scan_args;
+                let exec = if v.covers(filters) {
+                    self.parent.metrics.record_hit();
+                    v.mem.scan(projection, *batch_size, filters)?
+                } else {
+                    self.parent.metrics.record_miss();
+                    self.parent
+                        .provider
+                        .scan(projection, *batch_size, filters)?
+                };
                 let partition_count = exec.output_partitioning().partition_count();
 
                 let mut streams = Vec::with_capacity(partition_count);
@@ -217,3 +886,47 @@ where
         self.schema.clone()
     }
 }
+
+#[pin_project]
+struct LoadingStream {
+    schema: SchemaRef,
+    projection: Option<Vec<usize>>,
+    // dropping this cancels the load, it's declared before the receiver so
+    // it's dropped first and the load isn't left sending to nothing
+    _load: RemoteHandle<()>,
+    #[pin]
+    inner: UnboundedReceiver<ArrowResult<RecordBatch>>,
+}
+
+impl Stream for LoadingStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let schema = this.schema;
+        let projection = this.projection;
+        this.inner
+            .poll_next(ctx)
+            .map(|item| item.map(|batch| batch.and_then(|b| project(b, schema, projection))))
+    }
+}
+
+impl RecordBatchStream for LoadingStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+fn project(
+    batch: RecordBatch,
+    schema: &SchemaRef,
+    projection: &Option<Vec<usize>>,
+) -> ArrowResult<RecordBatch> {
+    match projection {
+        Some(columns) => RecordBatch::try_new(
+            schema.clone(),
+            columns.iter().map(|i| batch.column(*i).clone()).collect(),
+        ),
+        None => Ok(batch),
+    }
+}
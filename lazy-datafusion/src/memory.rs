@@ -0,0 +1,85 @@
+use std::{
+    error::Error,
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use arrow::{array::Array, record_batch::RecordBatch};
+use datafusion::error::{DataFusionError, Result};
+
+/// A budget for the data loaded while running a query, shared by all the
+/// tables the query scans, so a query over too much data fails rather than
+/// exhausting the process's memory.
+///
+/// Usage isn't released as loaded data is dropped, call `reset` before each
+/// query.
+#[derive(Debug)]
+pub struct MemoryLimit {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl MemoryLimit {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// The budget, in bytes.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Bytes loaded since the last reset.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.used.store(0, Ordering::Relaxed);
+    }
+
+    pub(crate) fn exceeded(&self) -> bool {
+        self.used() > self.limit
+    }
+
+    // counts batch against the budget, erroring if it takes it over
+    pub(crate) fn reserve(&self, batch: &RecordBatch) -> Result<()> {
+        let size = batch
+            .columns()
+            .iter()
+            .map(|array| array.get_array_memory_size())
+            .sum::<usize>();
+        let used = self.used.fetch_add(size, Ordering::Relaxed) + size;
+        if used > self.limit {
+            return Err(DataFusionError::External(Box::new(MemoryLimitExceeded {
+                used,
+                limit: self.limit,
+            })));
+        }
+        Ok(())
+    }
+}
+
+/// The error a load fails with when it takes a query over its
+/// `MemoryLimit`.
+#[derive(Debug)]
+pub struct MemoryLimitExceeded {
+    /// bytes loaded, including the batch that went over the limit
+    pub used: usize,
+    pub limit: usize,
+}
+
+impl fmt::Display for MemoryLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "memory limit exceeded, {} bytes loaded with a limit of {}",
+            self.used, self.limit
+        )
+    }
+}
+
+impl Error for MemoryLimitExceeded {}
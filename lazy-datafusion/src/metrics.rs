@@ -0,0 +1,95 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use arrow::{array::Array, record_batch::RecordBatch};
+
+#[derive(Debug, Default)]
+pub struct CacheMetrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    loads: AtomicU64,
+    load_failures: AtomicU64,
+    last_load_nanos: AtomicU64,
+    total_load_nanos: AtomicU64,
+    bytes_materialized: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl CacheMetrics {
+    /// Number of queries answered from already materialized data.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of queries that had to wait for data to be loaded.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Number of successful loads, including refreshes.
+    pub fn loads(&self) -> u64 {
+        self.loads.load(Ordering::Relaxed)
+    }
+
+    /// Number of loads that failed after exhausting all retries.
+    pub fn load_failures(&self) -> u64 {
+        self.load_failures.load(Ordering::Relaxed)
+    }
+
+    pub fn last_load_duration(&self) -> Duration {
+        Duration::from_nanos(self.last_load_nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn total_load_duration(&self) -> Duration {
+        Duration::from_nanos(self.total_load_nanos.load(Ordering::Relaxed))
+    }
+
+    /// Size in memory of the currently materialized data.
+    pub fn bytes_materialized(&self) -> u64 {
+        self.bytes_materialized.load(Ordering::Relaxed)
+    }
+
+    /// Number of times materialized data has been dropped or replaced.
+    pub fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_load(&self, duration: Duration, data: &[Vec<RecordBatch>]) {
+        let nanos = duration.as_nanos() as u64;
+        self.loads.fetch_add(1, Ordering::Relaxed);
+        self.last_load_nanos.store(nanos, Ordering::Relaxed);
+        self.total_load_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.record_size(data);
+    }
+
+    pub(crate) fn record_size(&self, data: &[Vec<RecordBatch>]) {
+        self.bytes_materialized
+            .store(materialized_size(data) as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_load_failure(&self) {
+        self.load_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_eviction(&self) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn materialized_size(data: &[Vec<RecordBatch>]) -> usize {
+    data.iter()
+        .flatten()
+        .flat_map(|batch| batch.columns())
+        .map(|array| array.get_array_memory_size())
+        .sum()
+}
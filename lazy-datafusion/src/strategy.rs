@@ -0,0 +1,35 @@
+use std::time::Instant;
+
+use crate::CacheMetrics;
+
+/// Decides when a LazyMemTable loads, evicts, and refreshes its data.
+///
+/// All methods have defaults matching [`EagerCache`], so implementations
+/// only need to override the decisions they want to change.
+pub trait CacheStrategy: Send + Sync {
+    /// Called when a table without cached data is queried. Returning false
+    /// passes the query through to the underlying provider without loading.
+    fn should_load(&self, _metrics: &CacheMetrics) -> bool {
+        true
+    }
+
+    /// Called when a table with cached data is queried. Returning true drops
+    /// the cached data before the query runs, with the query then treated as
+    /// one against a table without cached data.
+    fn should_evict(&self, _loaded_at: Instant, _metrics: &CacheMetrics) -> bool {
+        false
+    }
+
+    /// Called for each scheduled refresh of a table with cached data.
+    /// Returning false skips that refresh.
+    fn should_refresh(&self, _loaded_at: Instant, _metrics: &CacheMetrics) -> bool {
+        true
+    }
+}
+
+/// Load all data on the first query, and keep it until explicitly
+/// invalidated.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EagerCache;
+
+impl CacheStrategy for EagerCache {}
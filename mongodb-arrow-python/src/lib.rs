@@ -0,0 +1,137 @@
+//! Python bindings for mongodb-arrow, reading BSON documents in to pyarrow
+//! record batches with a typed mapping.
+//!
+//! Documents are passed as raw BSON bytes, such as the `raw` attribute of
+//! pymongo's `RawBSONDocument`, so they don't have to be decoded to Python
+//! objects first.
+//!
+//! ```python
+//! import mongodb_arrow_py as mongodb_arrow
+//! from bson.raw_bson import RawBSONDocument
+//!
+//! docs = [d.raw for d in coll.find(document_class=RawBSONDocument)]
+//! schema = mongodb_arrow.Schema.from_json("users", open("users.json").read())
+//! batch = mongodb_arrow.read_documents(docs, schema)
+//! df = batch.to_pandas()
+//! ```
+
+use std::{fmt::Display, str::FromStr};
+
+use arrow::{array::Array, record_batch::RecordBatch};
+use bson::Document;
+use mongodb_arrow::{DocumentsReader, ErrorPolicy, MappedSchema};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyBytes, wrap_pyfunction};
+
+/// A mapping from the fields of a MongoDB collection's documents to Arrow
+/// columns.
+#[pyclass]
+#[derive(Clone)]
+struct Schema {
+    inner: MappedSchema,
+}
+
+#[pymethods]
+impl Schema {
+    /// Reads a schema in the JSON format of bishop's schema files.
+    #[staticmethod]
+    fn from_json(collection: String, json: &str) -> PyResult<Self> {
+        let value = serde_json::from_str(json).map_err(value_error)?;
+        let inner = MappedSchema::from_json(collection, &value).map_err(value_error)?;
+        Ok(Schema { inner })
+    }
+
+    fn to_json(&self) -> String {
+        self.inner.to_json().to_string()
+    }
+
+    #[getter]
+    fn collection(&self) -> &str {
+        self.inner.mongodb_collection()
+    }
+}
+
+/// read_documents(documents, schema, error_policy="error")
+/// --
+///
+/// Converts a list of raw BSON documents to a pyarrow.RecordBatch.
+/// With error_policy "null" values that don't match the schema are read as
+/// null, rather than raising an error.
+#[pyfunction]
+fn read_documents(
+    py: Python,
+    documents: Vec<&PyBytes>,
+    schema: &Schema,
+    error_policy: Option<&str>,
+) -> PyResult<PyObject> {
+    let error_policy = match error_policy {
+        Some(policy) => ErrorPolicy::from_str(policy).map_err(value_error)?,
+        None => ErrorPolicy::default(),
+    };
+    let documents = decode(&documents)?;
+    let batch = DocumentsReader::new_with_error_policy(
+        documents,
+        schema.inner.fields().clone(),
+        error_policy,
+    )
+    .into_record_batch()
+    .map_err(value_error)?;
+    to_pyarrow(py, &batch)
+}
+
+/// infer_schema(collection, documents)
+/// --
+///
+/// Guesses a Schema from a sample of raw BSON documents.
+#[pyfunction]
+fn infer_schema(collection: String, documents: Vec<&PyBytes>) -> PyResult<Schema> {
+    let documents = decode(&documents)?;
+    Ok(Schema {
+        inner: mongodb_arrow::infer_schema(collection, &documents),
+    })
+}
+
+fn decode(documents: &[&PyBytes]) -> PyResult<Vec<Document>> {
+    documents
+        .iter()
+        .map(|bytes| Document::from_reader(&mut bytes.as_bytes()).map_err(value_error))
+        .collect()
+}
+
+// hands each column to pyarrow over the Arrow C Data Interface, so the
+// data isn't copied
+fn to_pyarrow(py: Python, batch: &RecordBatch) -> PyResult<PyObject> {
+    let pyarrow = py.import("pyarrow")?;
+    let arrays = batch
+        .columns()
+        .iter()
+        .map(|column| {
+            let (array, schema) = column.to_raw().map_err(value_error)?;
+            let array = pyarrow
+                .getattr("Array")?
+                .call_method1("_import_from_c", (array as usize, schema as usize))?;
+            Ok(array.to_object(py))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    let names = batch
+        .schema()
+        .fields()
+        .iter()
+        .map(|f| f.name().clone())
+        .collect::<Vec<_>>();
+    let batch = pyarrow
+        .getattr("RecordBatch")?
+        .call_method1("from_arrays", (arrays, names))?;
+    Ok(batch.to_object(py))
+}
+
+fn value_error<E: Display>(e: E) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+#[pymodule]
+fn mongodb_arrow_py(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Schema>()?;
+    m.add_wrapped(wrap_pyfunction!(read_documents))?;
+    m.add_wrapped(wrap_pyfunction!(infer_schema))?;
+    Ok(())
+}
@@ -1,6 +1,6 @@
 use std::iter::Peekable;
 
-use mongodb::bson::{
+use bson::{
     document::{ValueAccessError, ValueAccessResult},
     Bson, Document,
 };
@@ -0,0 +1,35 @@
+use arrow::{datatypes::DataType, error::ArrowError};
+use bson::document::ValueAccessError;
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A document's value for `field` doesn't match the schema.
+    #[error("can't read field {field}: {source}")]
+    Value {
+        field: String,
+        #[source]
+        source: ValueAccessError,
+    },
+    #[error("invalid transform for field {field}: {message}")]
+    Transform { field: String, message: String },
+    #[error("{0} isn't supported by mongodb-arrow")]
+    UnsupportedType(DataType),
+    #[error("expected {expected} columns, got {actual}")]
+    ColumnCount { expected: usize, actual: usize },
+    #[error("{0} is too large for a BSON integer")]
+    OutOfRange(u64),
+    #[error(transparent)]
+    Arrow(#[from] ArrowError),
+}
+
+impl From<Error> for ArrowError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Arrow(e) => e,
+            e => ArrowError::from_external_error(Box::new(e)),
+        }
+    }
+}
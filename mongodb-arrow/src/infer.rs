@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+
+use arrow::datatypes::{DataType, Field, TimeUnit};
+use bson::{spec::BinarySubtype, Binary, Bson, Document};
+
+use crate::{MappedField, MappedSchema};
+
+#[derive(Default)]
+struct FieldStats {
+    // count of each type seen, keyed by the type's Debug string so mixed
+    // types can be resolved to the most common one
+    types: BTreeMap<String, (DataType, usize)>,
+    present: usize,
+}
+
+/// Infers a schema for `mongodb_collection` from a sample of its documents.
+///
+/// Embedded documents are flattened in to fields named with their path,
+/// joined with `_`. Arrays and other types that can't be read are skipped.
+/// Where a field has been seen with more than one type the most common is
+/// used, and fields missing or null in any document are nullable.
+pub fn infer_schema(mongodb_collection: String, documents: &[Document]) -> MappedSchema {
+    // preserve the order fields are first seen in
+    let mut order = Vec::new();
+    let mut stats = BTreeMap::new();
+    for document in documents {
+        observe(document, "", &mut order, &mut stats);
+    }
+
+    let fields = order
+        .into_iter()
+        .filter_map(|path: String| {
+            let field_stats: &FieldStats = &stats[&path];
+            let (data_type, _) = field_stats.types.values().max_by_key(|(_, n)| *n)?;
+            let nullable = field_stats.present < documents.len();
+            let name = path.replace('.', "_");
+            Some(MappedField::new(
+                path,
+                Field::new(&name, data_type.clone(), nullable),
+            ))
+        })
+        .collect();
+
+    MappedSchema::new(mongodb_collection, fields)
+}
+
+fn observe(
+    document: &Document,
+    prefix: &str,
+    order: &mut Vec<String>,
+    stats: &mut BTreeMap<String, FieldStats>,
+) {
+    for (key, value) in document {
+        let path = format!("{}{}", prefix, key);
+        if let Bson::Document(embedded) = value {
+            observe(embedded, &format!("{}.", path), order, stats);
+            continue;
+        }
+        if !stats.contains_key(&path) {
+            order.push(path.clone());
+        }
+        let field_stats = stats.entry(path).or_insert_with(FieldStats::default);
+        if let Bson::Null = value {
+            continue;
+        }
+        field_stats.present += 1;
+        if let Some(data_type) = data_type(value) {
+            let entry = field_stats
+                .types
+                .entry(format!("{:?}", data_type))
+                .or_insert((data_type, 0));
+            entry.1 += 1;
+        }
+    }
+}
+
+fn data_type(value: &Bson) -> Option<DataType> {
+    let data_type = match value {
+        Bson::ObjectId(_) | Bson::String(_) | Bson::Symbol(_) => DataType::Utf8,
+        Bson::Int32(_) => DataType::Int32,
+        Bson::Int64(_) => DataType::Int64,
+        Bson::Double(_) => DataType::Float64,
+        Bson::Boolean(_) => DataType::Boolean,
+        Bson::DateTime(_) => DataType::Timestamp(TimeUnit::Millisecond, None),
+        Bson::Binary(Binary {
+            subtype: BinarySubtype::Generic,
+            ..
+        })
+        | Bson::Binary(Binary {
+            subtype: BinarySubtype::BinaryOld,
+            ..
+        })
+        | Bson::Binary(Binary {
+            subtype: BinarySubtype::UserDefined(_),
+            ..
+        }) => DataType::Binary,
+        _ => return None,
+    };
+    Some(data_type)
+}
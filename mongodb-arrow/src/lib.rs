@@ -1,6 +1,10 @@
 mod bson_ext;
+mod error;
+mod infer;
+mod to_bson;
+mod transform;
 
-use std::{collections::HashMap, convert::TryInto, ops::Deref};
+use std::{borrow::Cow, collections::HashMap, convert::TryInto, fmt, ops::Deref, str::FromStr};
 
 use arrow::{
     array::{
@@ -11,18 +15,25 @@ use arrow::{
         TimestampMillisecondBuilder, TimestampNanosecondBuilder, TimestampSecondBuilder,
     },
     datatypes::{DataType, DateUnit, Field, Schema, TimeUnit},
-    error::ArrowError,
     record_batch::RecordBatch,
 };
+use bson::{document::ValueAccessError, spec::BinarySubtype, Binary, Bson, Document};
 use chrono::Timelike;
-use mongodb::bson::{document::ValueAccessError, spec::BinarySubtype, Binary, Bson, Document};
+use serde_json::{json, Map, Value};
 
-use crate::bson_ext::BsonGetNested;
+pub use crate::{
+    bson_ext::BsonGetNested,
+    error::{Error, Result},
+    infer::infer_schema,
+    to_bson::record_batch_to_documents,
+    transform::Transform,
+};
 
 #[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
 pub struct MappedField {
     field: Field,
     mongodb_field: String,
+    transforms: Vec<Transform>,
 }
 
 impl MappedField {
@@ -30,12 +41,23 @@ impl MappedField {
         Self {
             mongodb_field,
             field,
+            transforms: Vec::new(),
         }
     }
 
+    /// Applies `transforms` in order to the field's values as they're read.
+    pub fn with_transforms(mut self, transforms: Vec<Transform>) -> Self {
+        self.transforms = transforms;
+        self
+    }
+
     pub fn mongodb_field(&self) -> &str {
         &self.mongodb_field
     }
+
+    pub fn transforms(&self) -> &[Transform] {
+        &self.transforms
+    }
 }
 
 impl Deref for MappedField {
@@ -91,6 +113,73 @@ impl MappedSchema {
     pub fn metadata(&self) -> &HashMap<String, String> {
         &self.metadata
     }
+
+    /// Reads a schema in Arrow's JSON format, keeping its metadata. Each
+    /// column's MongoDB field is taken from its `mongodb` metadata, or the
+    /// column name if not set, and any transforms from its `transform`
+    /// metadata.
+    pub fn from_json(mongodb_collection: String, json: &Value) -> Result<Self> {
+        let schema = Schema::from(json)?;
+        let fields = schema
+            .fields()
+            .iter()
+            .map(|f| {
+                let mut field = f.clone();
+                let metadata = field.metadata().clone().unwrap_or_default();
+                let mongodb_field = metadata
+                    .get("mongodb")
+                    .unwrap_or_else(|| field.name())
+                    .to_owned();
+                let transforms = match metadata.get("transform") {
+                    Some(transforms) => {
+                        Transform::parse_list(transforms).map_err(|e| Error::Transform {
+                            field: field.name().clone(),
+                            message: e,
+                        })?
+                    }
+                    None => Vec::new(),
+                };
+                field.set_metadata(None);
+                Ok(MappedField::new(mongodb_field, field).with_transforms(transforms))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self::new_with_metadata(
+            mongodb_collection,
+            fields,
+            schema.metadata().clone(),
+        ))
+    }
+
+    /// The inverse of `from_json`, the MongoDB field is recorded in the
+    /// metadata when it differs from the column name.
+    pub fn to_json(&self) -> Value {
+        let fields = self
+            .fields
+            .iter()
+            .map(|field| {
+                let mut value = field.to_json();
+                let mut metadata = Map::new();
+                if field.mongodb_field() != field.name() {
+                    metadata.insert("mongodb".to_owned(), field.mongodb_field().into());
+                }
+                if !field.transforms().is_empty() {
+                    let transforms = Transform::format_list(field.transforms());
+                    metadata.insert("transform".to_owned(), transforms.into());
+                }
+                if !metadata.is_empty() {
+                    if let Value::Object(ref mut map) = value {
+                        map.insert("metadata".to_owned(), Value::Object(metadata));
+                    }
+                }
+                value
+            })
+            .collect::<Vec<_>>();
+        if self.metadata.is_empty() {
+            json!({ "fields": fields })
+        } else {
+            json!({ "fields": fields, "metadata": self.metadata })
+        }
+    }
 }
 
 impl From<MappedSchema> for Schema {
@@ -107,6 +196,22 @@ struct FieldInfo {
     mongodb_field: String,
     data_type: DataType,
     is_nullable: bool,
+    transforms: Vec<Transform>,
+}
+
+impl FieldInfo {
+    // the field's value in `doc`, transformed if the field has transforms
+    fn value<'a>(&self, doc: &'a Document) -> Result<Cow<'a, Bson>, ValueAccessError> {
+        let value = doc.get_nested(&self.mongodb_field)?;
+        if self.transforms.is_empty() {
+            return Ok(Cow::Borrowed(value));
+        }
+        let value = self
+            .transforms
+            .iter()
+            .fold(value.clone(), |value, t| t.apply(value, &self.data_type));
+        Ok(Cow::Owned(value))
+    }
 }
 
 pub struct DocumentBuilder {
@@ -121,32 +226,70 @@ pub struct DocumentBuilder {
 // message it's a bug and proper error handling needs to be implemented.
 static INFALLIBLE: &str = "builder result expected to always be Ok(())";
 
+// appends the value of $field in $doc, or null, recording the first value
+// that doesn't match the schema in $error
 macro_rules! append_value {
-    ($builder_type:ty, $struct_builder:expr, $field:ident, $doc:ident, $errors:ident { $($p:pat => $e:expr,)+ }) => {
+    ($builder_type:ty, $struct_builder:expr, $field:ident, $doc:ident, $error:ident { $($p:pat => $e:expr,)+ }) => {
         {
             let builder = $struct_builder
                 .field_builder::<$builder_type>($field.index)
                 .expect("incorrect builder type for field");
-            match $doc.get_nested(&$field.mongodb_field) {
-                $(Ok($p) => builder.append_value($e).expect(INFALLIBLE),)+
-                Ok(Bson::Null) | Err(ValueAccessError::NotPresent) if $field.is_nullable => {
+            match $field.value(&$doc) {
+                Ok(value) => match &*value {
+                    $($p => builder.append_value($e).expect(INFALLIBLE),)+
+                    Bson::Null if $field.is_nullable => builder.append_null().expect(INFALLIBLE),
+                    _ => {
+                        builder.append_null().expect(INFALLIBLE);
+                        $error.get_or_insert_with(|| Error::Value {
+                            field: $field.mongodb_field.clone(),
+                            source: ValueAccessError::UnexpectedType,
+                        });
+                    }
+                },
+                Err(ValueAccessError::NotPresent) if $field.is_nullable => {
                     builder.append_null().expect(INFALLIBLE)
                 }
-                Ok(_) => {
-                    builder.append_null().expect(INFALLIBLE);
-                    $errors.push(ArrowError::from_external_error(Box::new(ValueAccessError::UnexpectedType)));
-                }
                 Err(e) => {
                     builder.append_null().expect(INFALLIBLE);
-                    $errors.push(ArrowError::from_external_error(Box::new(e)));
+                    $error.get_or_insert_with(|| Error::Value {
+                        field: $field.mongodb_field.clone(),
+                        source: e,
+                    });
                 }
             }
         }
     };
 }
 
+/// Returns true if values of `data_type` can be read from BSON by
+/// `DocumentBuilder`.
+pub fn is_supported(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Utf8
+            | DataType::LargeUtf8
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::Float64
+            | DataType::Boolean
+            | DataType::Timestamp(_, _)
+            | DataType::Date32(DateUnit::Day)
+            | DataType::Date64(DateUnit::Millisecond)
+            | DataType::Time32(TimeUnit::Second)
+            | DataType::Time32(TimeUnit::Millisecond)
+            | DataType::Time64(TimeUnit::Microsecond)
+            | DataType::Time64(TimeUnit::Nanosecond)
+            | DataType::Binary
+            | DataType::LargeBinary
+    )
+}
+
 impl DocumentBuilder {
-    pub fn new(fields: Vec<MappedField>, capacity: usize) -> DocumentBuilder {
+    /// Errors if any of `fields` has a type that can't be read from BSON.
+    pub fn new(fields: Vec<MappedField>, capacity: usize) -> Result<DocumentBuilder> {
+        if let Some(field) = fields.iter().find(|f| !is_supported(f.data_type())) {
+            return Err(Error::UnsupportedType(field.data_type().clone()));
+        }
         let (fields, field_info) = fields
             .into_iter()
             .enumerate()
@@ -156,87 +299,90 @@ impl DocumentBuilder {
                     mongodb_field: mapped_field.mongodb_field,
                     data_type: mapped_field.field.data_type().clone(),
                     is_nullable: mapped_field.field.is_nullable(),
+                    transforms: mapped_field.transforms,
                 };
                 (mapped_field.field, info)
             })
             .unzip();
         let builder = StructBuilder::from_fields(fields, capacity);
-        DocumentBuilder {
+        Ok(DocumentBuilder {
             builder,
             field_info,
-        }
+        })
     }
 
-    pub fn append_value(&mut self, doc: Document) -> Result<(), Vec<ArrowError>> {
-        let mut errors = Vec::new();
+    /// Appends `doc` as a row. Values that don't match the schema are
+    /// appended as null, and the first is returned as an error.
+    pub fn append_value(&mut self, doc: Document) -> Result<()> {
+        let mut error = None;
 
         for field in self.field_info.iter() {
             match field.data_type {
-                DataType::Utf8 => append_value!(StringBuilder, self.builder, field, doc, errors {
+                DataType::Utf8 => append_value!(StringBuilder, self.builder, field, doc, error {
                     Bson::ObjectId(oid) => &oid.to_string(),
                     Bson::String(val) => &val,
                     Bson::Symbol(val) => &val,
                 }),
                 DataType::LargeUtf8 => {
-                    append_value!(LargeStringBuilder, self.builder, field, doc, errors {
+                    append_value!(LargeStringBuilder, self.builder, field, doc, error {
                         Bson::ObjectId(oid) => &oid.to_string(),
                         Bson::String(val) => &val,
                         Bson::Symbol(val) => &val,
                     })
                 }
-                DataType::Int32 => append_value!(Int32Builder, self.builder, field, doc, errors {
+                DataType::Int32 => append_value!(Int32Builder, self.builder, field, doc, error {
                     Bson::Int32(val) => *val,
                 }),
-                DataType::Int64 => append_value!(Int64Builder, self.builder, field, doc, errors {
+                DataType::Int64 => append_value!(Int64Builder, self.builder, field, doc, error {
                     Bson::Int64(val) => *val,
                 }),
                 DataType::Float64 => {
-                    append_value!(Float64Builder, self.builder, field, doc, errors {
+                    append_value!(Float64Builder, self.builder, field, doc, error {
                         Bson::Double(val) => *val,
                     })
                 }
                 DataType::Boolean => {
-                    append_value!(BooleanBuilder, self.builder, field, doc, errors {
+                    append_value!(BooleanBuilder, self.builder, field, doc, error {
                         Bson::Boolean(val) => *val,
                     })
                 }
                 DataType::Timestamp(TimeUnit::Second, _) => {
-                    append_value!(TimestampSecondBuilder, self.builder, field, doc, errors {
+                    append_value!(TimestampSecondBuilder, self.builder, field, doc, error {
                         Bson::DateTime(val) => val.timestamp(),
                     })
                 }
                 DataType::Timestamp(TimeUnit::Millisecond, _) => {
-                    append_value!(TimestampMillisecondBuilder, self.builder, field, doc, errors {
+                    append_value!(TimestampMillisecondBuilder, self.builder, field, doc, error {
                         Bson::DateTime(val) => val.timestamp_millis(),
                     })
                 }
                 DataType::Timestamp(TimeUnit::Microsecond, _) => {
-                    append_value!(TimestampMicrosecondBuilder, self.builder, field, doc, errors {
+                    append_value!(TimestampMicrosecondBuilder, self.builder, field, doc, error {
                         Bson::DateTime(val) => val.timestamp_nanos() / 1_000,
                     })
                 }
                 DataType::Timestamp(TimeUnit::Nanosecond, _) => {
-                    append_value!(TimestampNanosecondBuilder, self.builder, field, doc, errors {
+                    append_value!(TimestampNanosecondBuilder, self.builder, field, doc, error {
                         Bson::DateTime(val) => val.timestamp_nanos(),
                     })
                 }
                 DataType::Date32(DateUnit::Day) => {
-                    append_value!(Date32Builder, self.builder, field, doc, errors {
+                    append_value!(Date32Builder, self.builder, field, doc, error {
                         Bson::DateTime(val) => (val.timestamp() / 86_400).try_into().expect("days since epoch shouldn't overflow"),
                     })
                 }
                 DataType::Date64(DateUnit::Millisecond) => {
-                    append_value!(Date64Builder, self.builder, field, doc, errors {
+                    append_value!(Date64Builder, self.builder, field, doc, error {
                         Bson::DateTime(val) => (val.timestamp() / 86_400) * 1_000,
                     })
                 }
                 DataType::Time32(TimeUnit::Second) => {
-                    append_value!(Time32SecondBuilder, self.builder, field, doc, errors {
+                    append_value!(Time32SecondBuilder, self.builder, field, doc, error {
                         Bson::DateTime(val) => val.time().num_seconds_from_midnight().try_into().expect("seconds since midnight shouldn't overflow"),
                     })
                 }
                 DataType::Time32(TimeUnit::Millisecond) => {
-                    append_value!(Time32MillisecondBuilder, self.builder, field, doc, errors {
+                    append_value!(Time32MillisecondBuilder, self.builder, field, doc, error {
                         Bson::DateTime(val) => {
                             let t = val.time();
                             ((t.num_seconds_from_midnight() * 1_000) + (t.nanosecond() / 1_000_000)).try_into().expect("milliseconds since midnight shouldn't overflow")
@@ -244,7 +390,7 @@ impl DocumentBuilder {
                     })
                 }
                 DataType::Time64(TimeUnit::Microsecond) => {
-                    append_value!(Time64MicrosecondBuilder, self.builder, field, doc, errors {
+                    append_value!(Time64MicrosecondBuilder, self.builder, field, doc, error {
                         Bson::DateTime(val) => {
                             let t = val.time();
                             ((t.num_seconds_from_midnight() * 1_000_000) + (t.nanosecond() / 1_000)).try_into().expect("microseconds since midnight shouldn't overflow")
@@ -252,7 +398,7 @@ impl DocumentBuilder {
                     })
                 }
                 DataType::Time64(TimeUnit::Nanosecond) => {
-                    append_value!(Time64NanosecondBuilder, self.builder, field, doc, errors {
+                    append_value!(Time64NanosecondBuilder, self.builder, field, doc, error {
                         Bson::DateTime(val) => {
                             let t = val.time();
                             ((t.num_seconds_from_midnight() * 1_000_000_000) + t.nanosecond()).try_into().expect("nanoseconds since midnight shouldn't overflow")
@@ -260,31 +406,26 @@ impl DocumentBuilder {
                     })
                 }
                 DataType::Binary => {
-                    append_value!(BinaryBuilder, self.builder, field, doc, errors {
+                    append_value!(BinaryBuilder, self.builder, field, doc, error {
                         Bson::Binary(Binary { subtype: BinarySubtype::Generic, bytes }) => &bytes,
                         Bson::Binary(Binary { subtype: BinarySubtype::BinaryOld, bytes }) => &bytes,
                         Bson::Binary(Binary { subtype: BinarySubtype::UserDefined(_), bytes }) => &bytes,
                     })
                 }
                 DataType::LargeBinary => {
-                    append_value!(LargeBinaryBuilder, self.builder, field, doc, errors {
+                    append_value!(LargeBinaryBuilder, self.builder, field, doc, error {
                         Bson::Binary(Binary { subtype: BinarySubtype::Generic, bytes }) => &bytes,
                         Bson::Binary(Binary { subtype: BinarySubtype::BinaryOld, bytes }) => &bytes,
                         Bson::Binary(Binary { subtype: BinarySubtype::UserDefined(_), bytes }) => &bytes,
                     })
                 }
-                ref data_type => panic!(
-                    "{} not supported in mongodb_arrow::DocumentBuilder",
-                    data_type
-                ),
+                ref data_type => unreachable!("{} rejected by DocumentBuilder::new", data_type),
             }
         }
-        let success = errors.is_empty();
-        self.builder.append(success).expect(INFALLIBLE);
-        if success {
-            Ok(())
-        } else {
-            Err(errors)
+        self.builder.append(error.is_none()).expect(INFALLIBLE);
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
         }
     }
 
@@ -301,22 +442,77 @@ impl DocumentBuilder {
     }
 }
 
+/// What to do when a document has a value that doesn't match the schema.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// fail the whole batch
+    Error,
+    /// read the value as null
+    Null,
+}
+
+impl Default for ErrorPolicy {
+    fn default() -> Self {
+        ErrorPolicy::Error
+    }
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(ErrorPolicy::Error),
+            "null" => Ok(ErrorPolicy::Null),
+            _ => Err(format!(
+                "unknown error policy {:?}, expected error or null",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorPolicy::Error => f.write_str("error"),
+            ErrorPolicy::Null => f.write_str("null"),
+        }
+    }
+}
+
 pub struct DocumentsReader {
     documents: Vec<Document>,
     fields: Vec<MappedField>,
+    error_policy: ErrorPolicy,
 }
 
 impl DocumentsReader {
     pub fn new(documents: Vec<Document>, fields: Vec<MappedField>) -> DocumentsReader {
-        DocumentsReader { documents, fields }
+        Self::new_with_error_policy(documents, fields, ErrorPolicy::default())
     }
 
-    pub fn into_record_batch(self) -> Result<RecordBatch, ArrowError> {
-        let mut builder = DocumentBuilder::new(self.fields, self.documents.len());
+    pub fn new_with_error_policy(
+        documents: Vec<Document>,
+        fields: Vec<MappedField>,
+        error_policy: ErrorPolicy,
+    ) -> DocumentsReader {
+        DocumentsReader {
+            documents,
+            fields,
+            error_policy,
+        }
+    }
+
+    pub fn into_record_batch(self) -> Result<RecordBatch> {
+        let mut builder = DocumentBuilder::new(self.fields, self.documents.len())?;
         for document in self.documents {
-            builder
-                .append_value(document)
-                .map_err(|errors| errors.into_iter().next().expect("empty errors"))?;
+            match builder.append_value(document) {
+                Ok(()) => (),
+                // the builder has already appended nulls for the bad values
+                Err(_) if self.error_policy == ErrorPolicy::Null => (),
+                Err(e) => return Err(e),
+            }
         }
         Ok(RecordBatch::from(&builder.finish()))
     }
@@ -0,0 +1,143 @@
+use std::convert::TryFrom;
+
+use arrow::{
+    array::{
+        as_boolean_array, as_largestring_array, as_primitive_array, as_string_array, Array,
+        ArrayRef, BinaryArray, LargeBinaryArray,
+    },
+    datatypes::{
+        DataType, Date32Type, Date64Type, Float32Type, Float64Type, Int16Type, Int32Type,
+        Int64Type, Int8Type, TimeUnit, TimestampMicrosecondType, TimestampMillisecondType,
+        TimestampNanosecondType, TimestampSecondType, UInt16Type, UInt32Type, UInt64Type,
+        UInt8Type,
+    },
+    record_batch::RecordBatch,
+};
+use bson::{oid::ObjectId, spec::BinarySubtype, Binary, Bson, Document};
+use chrono::{TimeZone, Utc};
+
+use crate::{Error, MappedField, Result};
+
+/// Converts each row of `batch` to a document, with column `i` written to
+/// the MongoDB field of `fields[i]`.
+///
+/// Dotted field names are written as embedded documents and null values
+/// are left out. Strings written to `_id` that are valid ObjectIds are
+/// written as ObjectIds, the reverse of how they are read.
+pub fn record_batch_to_documents(
+    batch: &RecordBatch,
+    fields: &[MappedField],
+) -> Result<Vec<Document>> {
+    if batch.num_columns() != fields.len() {
+        return Err(Error::ColumnCount {
+            expected: fields.len(),
+            actual: batch.num_columns(),
+        });
+    }
+
+    let mut documents = vec![Document::new(); batch.num_rows()];
+    for (column, field) in batch.columns().iter().zip(fields) {
+        for (i, document) in documents.iter_mut().enumerate() {
+            if column.is_null(i) {
+                continue;
+            }
+            let mut value = to_bson(column, i)?;
+            if field.mongodb_field() == "_id" {
+                if let Bson::String(ref s) = value {
+                    if let Ok(oid) = ObjectId::with_string(s) {
+                        value = Bson::ObjectId(oid);
+                    }
+                }
+            }
+            insert_nested(document, field.mongodb_field(), value);
+        }
+    }
+    Ok(documents)
+}
+
+fn insert_nested(document: &mut Document, key: &str, value: Bson) {
+    match key.find('.') {
+        Some(i) => {
+            let entry = document
+                .entry(key[..i].to_owned())
+                .or_insert(Bson::Document(Document::new()));
+            if !matches!(entry, Bson::Document(_)) {
+                *entry = Bson::Document(Document::new());
+            }
+            if let Bson::Document(embedded) = entry {
+                insert_nested(embedded, &key[i + 1..], value);
+            }
+        }
+        None => {
+            document.insert(key, value);
+        }
+    }
+}
+
+fn to_bson(array: &ArrayRef, i: usize) -> Result<Bson> {
+    let value = match array.data_type() {
+        DataType::Boolean => Bson::Boolean(as_boolean_array(array).value(i)),
+        DataType::Int8 => Bson::Int32(as_primitive_array::<Int8Type>(array).value(i).into()),
+        DataType::Int16 => Bson::Int32(as_primitive_array::<Int16Type>(array).value(i).into()),
+        DataType::Int32 => Bson::Int32(as_primitive_array::<Int32Type>(array).value(i)),
+        DataType::Int64 => Bson::Int64(as_primitive_array::<Int64Type>(array).value(i)),
+        DataType::UInt8 => Bson::Int32(as_primitive_array::<UInt8Type>(array).value(i).into()),
+        DataType::UInt16 => Bson::Int32(as_primitive_array::<UInt16Type>(array).value(i).into()),
+        DataType::UInt32 => Bson::Int64(as_primitive_array::<UInt32Type>(array).value(i).into()),
+        DataType::UInt64 => {
+            let v = as_primitive_array::<UInt64Type>(array).value(i);
+            Bson::Int64(i64::try_from(v).map_err(|_| Error::OutOfRange(v))?)
+        }
+        DataType::Float32 => Bson::Double(as_primitive_array::<Float32Type>(array).value(i).into()),
+        DataType::Float64 => Bson::Double(as_primitive_array::<Float64Type>(array).value(i)),
+        DataType::Utf8 => Bson::String(as_string_array(array).value(i).to_owned()),
+        DataType::LargeUtf8 => Bson::String(as_largestring_array(array).value(i).to_owned()),
+        DataType::Timestamp(TimeUnit::Second, _) => {
+            let v = as_primitive_array::<TimestampSecondType>(array).value(i);
+            Bson::DateTime(Utc.timestamp(v, 0))
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            let v = as_primitive_array::<TimestampMillisecondType>(array).value(i);
+            Bson::DateTime(Utc.timestamp_millis(v))
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            let v = as_primitive_array::<TimestampMicrosecondType>(array).value(i);
+            Bson::DateTime(Utc.timestamp_nanos(v * 1_000))
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            let v = as_primitive_array::<TimestampNanosecondType>(array).value(i);
+            Bson::DateTime(Utc.timestamp_nanos(v))
+        }
+        DataType::Date32(_) => {
+            let days = as_primitive_array::<Date32Type>(array).value(i);
+            Bson::DateTime(Utc.timestamp(i64::from(days) * 86_400, 0))
+        }
+        DataType::Date64(_) => {
+            let v = as_primitive_array::<Date64Type>(array).value(i);
+            Bson::DateTime(Utc.timestamp_millis(v))
+        }
+        DataType::Binary => {
+            let array = array
+                .as_any()
+                .downcast_ref::<BinaryArray>()
+                .expect("binary array");
+            generic_binary(array.value(i))
+        }
+        DataType::LargeBinary => {
+            let array = array
+                .as_any()
+                .downcast_ref::<LargeBinaryArray>()
+                .expect("large binary array");
+            generic_binary(array.value(i))
+        }
+        data_type => return Err(Error::UnsupportedType(data_type.clone())),
+    };
+    Ok(value)
+}
+
+fn generic_binary(bytes: &[u8]) -> Bson {
+    Bson::Binary(Binary {
+        subtype: BinarySubtype::Generic,
+        bytes: bytes.to_vec(),
+    })
+}
@@ -0,0 +1,166 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use arrow::datatypes::DataType;
+use bson::Bson;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+/// A cleanup applied to a field's value before it's converted to its
+/// column's type.
+///
+/// Written in a schema file's `transform` field metadata, several separated
+/// by `|` are applied in order, e.g. `"trim|lowercase"`. Values a transform
+/// doesn't apply to, such as a number given to `trim`, are left unchanged.
+#[derive(Clone, Debug)]
+pub enum Transform {
+    /// `lowercase`
+    Lowercase,
+    /// `uppercase`
+    Uppercase,
+    /// `trim`, removes leading and trailing whitespace
+    Trim,
+    /// `multiply:n`, for unit conversions
+    Multiply(f64),
+    /// `divide:n`, for unit conversions
+    Divide(f64),
+    /// `timestamp:format`, parses a string with a strftime style format,
+    /// as UTC if the format has no offset
+    Timestamp(String),
+}
+
+impl Transform {
+    /// Parses a list of transforms separated by `|`.
+    pub fn parse_list(s: &str) -> Result<Vec<Transform>, String> {
+        s.split('|').map(str::parse).collect()
+    }
+
+    /// Formats a list of transforms as read by `parse_list`.
+    pub fn format_list(transforms: &[Transform]) -> String {
+        transforms
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("|")
+    }
+
+    /// Applies the transform to a value for a column of `data_type`.
+    pub fn apply(&self, value: Bson, data_type: &DataType) -> Bson {
+        match (self, value) {
+            (Transform::Lowercase, Bson::String(s)) => Bson::String(s.to_lowercase()),
+            (Transform::Uppercase, Bson::String(s)) => Bson::String(s.to_uppercase()),
+            (Transform::Trim, Bson::String(s)) => Bson::String(s.trim().to_owned()),
+            (Transform::Multiply(n), value) => scale(value, |v| v * n, data_type),
+            (Transform::Divide(n), value) => scale(value, |v| v / n, data_type),
+            (Transform::Timestamp(format), Bson::String(s)) => match parse_timestamp(&s, format) {
+                Some(datetime) => Bson::DateTime(datetime),
+                None => Bson::String(s),
+            },
+            (_, value) => value,
+        }
+    }
+
+    // compared by a key of the variant and its argument, as f64 isn't Ord
+    fn key(&self) -> (u8, u64, &str) {
+        match self {
+            Transform::Lowercase => (0, 0, ""),
+            Transform::Uppercase => (1, 0, ""),
+            Transform::Trim => (2, 0, ""),
+            Transform::Multiply(n) => (3, n.to_bits(), ""),
+            Transform::Divide(n) => (4, n.to_bits(), ""),
+            Transform::Timestamp(format) => (5, 0, format),
+        }
+    }
+}
+
+// the scaled number, as the column's integer type if it's a whole number
+// that fits, otherwise a double
+fn scale(value: Bson, f: impl Fn(f64) -> f64, data_type: &DataType) -> Bson {
+    let scaled = match value {
+        Bson::Int32(v) => f(f64::from(v)),
+        Bson::Int64(v) => f(v as f64),
+        Bson::Double(v) => f(v),
+        value => return value,
+    };
+    let whole = scaled.fract() == 0.0;
+    match data_type {
+        DataType::Int32 if whole && scaled.abs() <= f64::from(i32::MAX) => {
+            Bson::Int32(scaled as i32)
+        }
+        DataType::Int64 if whole && scaled.abs() < i64::MAX as f64 => Bson::Int64(scaled as i64),
+        _ => Bson::Double(scaled),
+    }
+}
+
+fn parse_timestamp(s: &str, format: &str) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_str(s, format) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    let naive = NaiveDateTime::parse_from_str(s, format)
+        .or_else(|_| NaiveDate::parse_from_str(s, format).map(|d| d.and_hms(0, 0, 0)))
+        .ok()?;
+    Some(DateTime::from_utc(naive, Utc))
+}
+
+impl FromStr for Transform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.find(':') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+        let number = |arg: Option<&str>| {
+            arg.and_then(|a| a.trim().parse::<f64>().ok())
+                .filter(|n| n.is_finite() && *n != 0.0)
+                .ok_or_else(|| format!("expected {}:n with a non-zero number", name))
+        };
+        match (name.trim().to_ascii_lowercase().as_str(), arg) {
+            ("lowercase", None) => Ok(Transform::Lowercase),
+            ("uppercase", None) => Ok(Transform::Uppercase),
+            ("trim", None) => Ok(Transform::Trim),
+            ("multiply", arg) => number(arg).map(Transform::Multiply),
+            ("divide", arg) => number(arg).map(Transform::Divide),
+            ("timestamp", Some(format)) if !format.is_empty() => {
+                Ok(Transform::Timestamp(format.to_owned()))
+            }
+            ("timestamp", _) => Err("expected timestamp:format".to_owned()),
+            _ => Err(format!(
+                "unknown transform {:?}, expected lowercase, uppercase, trim, multiply:n, \
+                 divide:n, or timestamp:format",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transform::Lowercase => f.write_str("lowercase"),
+            Transform::Uppercase => f.write_str("uppercase"),
+            Transform::Trim => f.write_str("trim"),
+            Transform::Multiply(n) => write!(f, "multiply:{}", n),
+            Transform::Divide(n) => write!(f, "divide:{}", n),
+            Transform::Timestamp(format) => write!(f, "timestamp:{}", format),
+        }
+    }
+}
+
+impl PartialEq for Transform {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Transform {}
+
+impl PartialOrd for Transform {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Transform {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
@@ -2,58 +2,193 @@ use std::{
     any::Any,
     future::Future,
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
     task::{Context, Poll},
+    time::Instant,
 };
 
 use arrow::{datatypes::SchemaRef, error::Result as ArrowResult, record_batch::RecordBatch};
 use async_trait::async_trait;
 use datafusion::{
-    datasource::{datasource::Statistics, TableProvider},
+    datasource::{
+        datasource::{Statistics, TableProviderFilterPushDown},
+        TableProvider,
+    },
     error::{DataFusionError, Result},
     logical_plan::Expr,
     physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream, SendableRecordBatchStream},
 };
-use futures::stream::{Fuse, Stream, StreamExt};
+use futures::stream::{self, Fuse, Stream, StreamExt, TryStreamExt};
 use mongodb::{
-    bson::{Bson, Document},
-    options::FindOptions,
-    Collection, Cursor,
+    bson::{doc, Bson, Document, Timestamp},
+    options::{AggregateOptions, FindOptions},
+    Client, Collection, Database,
 };
-use mongodb_arrow::{DocumentsReader, MappedField, MappedSchema};
-use tokio::sync::Mutex as TokioMutex;
+use mongodb_arrow::{DocumentsReader, ErrorPolicy, MappedField, MappedSchema};
+use tokio::sync::{Mutex as TokioMutex, OwnedSemaphorePermit};
+
+use crate::{filter::to_mongodb_filter, throttle::Throttle, Error};
+
+static DOCUMENTS_READ: AtomicU64 = AtomicU64::new(0);
+
+/// The number of documents read from MongoDB so far by this process, for
+/// reporting progress.
+pub fn documents_read() -> u64 {
+    DOCUMENTS_READ.load(Ordering::Relaxed)
+}
+
+/// A client that can be replaced, for example after the connection has been
+/// lost, with collections created from it picking up the replacement.
+#[derive(Clone, Debug)]
+pub struct SharedClient {
+    client: Arc<RwLock<Client>>,
+}
+
+impl SharedClient {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client: Arc::new(RwLock::new(client)),
+        }
+    }
+
+    pub fn get(&self) -> Client {
+        self.client.read().expect("client lock poisoned").clone()
+    }
+
+    pub fn replace(&self, client: Client) {
+        *self.client.write().expect("client lock poisoned") = client;
+    }
+}
+
+/// The cluster time reads are made at, shared so it can be changed between
+/// queries. Reads are at the latest data when not set.
+#[derive(Clone, Debug, Default)]
+pub struct SnapshotTime {
+    time: Arc<RwLock<Option<Timestamp>>>,
+}
+
+impl SnapshotTime {
+    pub fn get(&self) -> Option<Timestamp> {
+        *self.time.read().expect("snapshot time lock poisoned")
+    }
+
+    pub fn set(&self, time: Option<Timestamp>) {
+        *self.time.write().expect("snapshot time lock poisoned") = time;
+    }
+}
+
+enum Source {
+    Collection(Collection),
+    Shared { client: SharedClient, db: String },
+}
+
+impl Source {
+    fn collection(&self, name: &str) -> Collection {
+        match self {
+            Source::Collection(collection) => collection.clone(),
+            Source::Shared { client, db } => client.get().database(db).collection(name),
+        }
+    }
+
+    // only known for a shared client, a collection doesn't expose its
+    // database
+    fn database(&self) -> Option<Database> {
+        match self {
+            Source::Collection(_) => None,
+            Source::Shared { client, db } => Some(client.get().database(db)),
+        }
+    }
+}
 
 pub struct MongoDbCollection {
-    collection: Collection,
+    source: Source,
     mapped_schema: MappedSchema,
     schema: SchemaRef,
+    error_policy: Arc<RwLock<ErrorPolicy>>,
+    sample: Option<i64>,
+    snapshot_time: SnapshotTime,
+    throttle: Throttle,
 }
 
 impl MongoDbCollection {
     pub fn new(collection: Collection, mapped_schema: MappedSchema) -> Self {
+        Self::new_with_error_policy(collection, mapped_schema, Default::default())
+    }
+
+    /// The error policy is read each time the collection is scanned, so can
+    /// be shared and changed between queries.
+    pub fn new_with_error_policy(
+        collection: Collection,
+        mapped_schema: MappedSchema,
+        error_policy: Arc<RwLock<ErrorPolicy>>,
+    ) -> Self {
         Self {
-            collection,
+            source: Source::Collection(collection),
             mapped_schema: mapped_schema.clone(),
             schema: Arc::new(mapped_schema.into()),
+            error_policy,
+            sample: None,
+            snapshot_time: SnapshotTime::default(),
+            throttle: Throttle::default(),
         }
     }
-}
 
-impl TableProvider for MongoDbCollection {
-    fn as_any(&self) -> &dyn Any {
+    /// Reads the collection named by `mapped_schema` from database `db`,
+    /// using whichever client `client` holds when the collection is scanned.
+    pub fn new_with_shared_client(
+        client: SharedClient,
+        db: String,
+        mapped_schema: MappedSchema,
+        error_policy: Arc<RwLock<ErrorPolicy>>,
+    ) -> Self {
+        Self {
+            source: Source::Shared { client, db },
+            mapped_schema: mapped_schema.clone(),
+            schema: Arc::new(mapped_schema.into()),
+            error_policy,
+            sample: None,
+            snapshot_time: SnapshotTime::default(),
+            throttle: Throttle::default(),
+        }
+    }
+
+    /// Limits scans to a random sample of at most `size` documents, for
+    /// exploring large collections quickly.
+    pub fn with_sample(mut self, size: i64) -> Self {
+        self.sample = Some(size);
         self
     }
 
-    fn schema(&self) -> SchemaRef {
-        self.schema.clone()
+    /// Reads at the cluster time `snapshot_time` holds when the collection
+    /// is scanned, with snapshot read concern. Only supported for
+    /// collections read with a shared client.
+    pub fn with_snapshot_time(mut self, snapshot_time: SnapshotTime) -> Self {
+        self.snapshot_time = snapshot_time;
+        self
     }
 
-    fn scan(
+    /// Applies `throttle`'s limits to scans, shared with any other tables
+    /// given the same throttle.
+    pub fn with_throttle(mut self, throttle: Throttle) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// The MongoDB command a scan with `projection` and `filters` would
+    /// run, for showing what a query will do without running it.
+    pub fn query(&self, projection: &Option<Vec<usize>>, filters: &[Expr]) -> Result<Document> {
+        Ok(self.exec(projection, 0, filters)?.command())
+    }
+
+    fn exec(
         &self,
         projection: &Option<Vec<usize>>,
         batch_size: usize,
-        _filters: &[Expr],
-    ) -> Result<Arc<dyn ExecutionPlan>> {
+        filters: &[Expr],
+    ) -> Result<MongoExec> {
         let mapped_schema = match projection {
             Some(columns) => {
                 let projected_columns: Result<Vec<MappedField>> = columns
@@ -77,25 +212,96 @@ impl TableProvider for MongoDbCollection {
             None => self.mapped_schema.clone(),
         };
 
-        Ok(Arc::new(MongoExec {
-            collection: self.collection.clone(),
+        let error_policy = self.error_policy();
+        let mut filters = filters
+            .iter()
+            .filter_map(|f| to_mongodb_filter(f, &self.mapped_schema, error_policy))
+            .collect::<Vec<_>>();
+        let filter = match filters.len() {
+            0 => None,
+            1 => filters.pop(),
+            _ => Some(doc! { "$and": filters }),
+        };
+
+        let snapshot = match self.snapshot_time.get() {
+            Some(time) => {
+                let db = self.source.database().ok_or_else(|| {
+                    DataFusionError::Plan(
+                        "snapshot reads need a collection read with a shared client".to_owned(),
+                    )
+                })?;
+                Some((db, time))
+            }
+            None => None,
+        };
+
+        Ok(MongoExec {
+            collection: self
+                .source
+                .collection(self.mapped_schema.mongodb_collection()),
+            snapshot,
             mapped_schema: Arc::new(mapped_schema.clone()),
             schema: Arc::new(mapped_schema.into()),
+            filter,
             batch_size,
-        }))
+            sample: self.sample,
+            throttle: self.throttle.clone(),
+            error_policy,
+        })
+    }
+
+    fn error_policy(&self) -> ErrorPolicy {
+        *self
+            .error_policy
+            .read()
+            .expect("error policy lock poisoned")
+    }
+}
+
+impl TableProvider for MongoDbCollection {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        batch_size: usize,
+        filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(self.exec(projection, batch_size, filters)?))
     }
 
     fn statistics(&self) -> Statistics {
         Default::default()
     }
+
+    fn supports_filter_pushdown(&self, filter: &Expr) -> Result<TableProviderFilterPushDown> {
+        // the MongoDB query can be less selective than the filter, so
+        // DataFusion still needs to apply it
+        match to_mongodb_filter(filter, &self.mapped_schema, self.error_policy()) {
+            Some(_) => Ok(TableProviderFilterPushDown::Inexact),
+            None => Ok(TableProviderFilterPushDown::Unsupported),
+        }
+    }
 }
 
 #[derive(Debug)]
 struct MongoExec {
     collection: Collection,
+    // the database to run commands on, and the cluster time to read at
+    snapshot: Option<(Database, Timestamp)>,
     mapped_schema: Arc<MappedSchema>,
     schema: SchemaRef,
+    filter: Option<Document>,
     batch_size: usize,
+    sample: Option<i64>,
+    throttle: Throttle,
+    error_policy: ErrorPolicy,
 }
 
 #[async_trait]
@@ -124,31 +330,197 @@ impl ExecutionPlan for MongoExec {
     }
 
     async fn execute(&self, _partition: usize) -> Result<SendableRecordBatchStream> {
-        let filter = None;
-        let options = FindOptions::builder()
-            .projection(Some(mongodb_projection(self.mapped_schema.clone())))
-            .batch_size(Some(self.batch_size as u32))
-            .build();
-        Ok(Box::pin(MongoStream {
-            cursor: TokioMutex::new(
-                self.collection
-                    .find(filter, options)
-                    .await
-                    .map_err(|e| DataFusionError::Execution(e.to_string()))?
-                    .fuse(),
-            ),
+        let filter = self.filter.clone();
+        let projection = mongodb_projection(&self.mapped_schema);
+        tracing::debug!(
+            collection = self.collection.name(),
+            filter = ?filter,
+            sample = ?self.sample,
+            "find issued"
+        );
+        let permit = self.throttle.cursor().await;
+        if let Some((ref db, time)) = self.snapshot {
+            let mut command = self.command();
+            command.insert(
+                "readConcern",
+                doc! { "level": "snapshot", "atClusterTime": Bson::Timestamp(time) },
+            );
+            if command.contains_key("aggregate") {
+                command.insert("cursor", doc! { "batchSize": self.batch_size as i64 });
+            } else {
+                command.insert("batchSize", self.batch_size as i64);
+            }
+            let documents = command_cursor(
+                db.clone(),
+                self.collection.name().to_owned(),
+                command,
+                self.batch_size,
+                self.throttle.clone(),
+            );
+            return Ok(self.stream(documents, permit));
+        }
+        let cursor = match self.sample {
+            Some(size) => {
+                let pipeline = sample_pipeline(filter, size, projection);
+                let options = AggregateOptions::builder()
+                    .batch_size(Some(self.batch_size as u32))
+                    .max_time(self.throttle.max_time())
+                    .build();
+                self.collection.aggregate(pipeline, options).await
+            }
+            None => {
+                let options = FindOptions::builder()
+                    .projection(Some(projection))
+                    .batch_size(Some(self.batch_size as u32))
+                    .max_time(self.throttle.max_time())
+                    .build();
+                self.collection.find(filter, options).await
+            }
+        };
+        let documents = Box::pin(cursor.map_err(Error::from)?.map_err(Error::from));
+        let documents = if self.throttle.limits_get_more() {
+            throttle_batches(documents, self.throttle.clone(), self.batch_size)
+        } else {
+            documents
+        };
+        Ok(self.stream(documents, permit))
+    }
+}
+
+impl MongoExec {
+    fn stream(
+        &self,
+        documents: Documents,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> SendableRecordBatchStream {
+        Box::pin(MongoStream {
+            cursor: TokioMutex::new(documents.fuse()),
+            _permit: permit,
             mapped_schema: self.mapped_schema.clone(),
             schema: self.schema.clone(),
             batch_size: self.batch_size,
-        }))
+            error_policy: self.error_policy,
+        })
+    }
+
+    // the database command equivalent to what execute sends
+    fn command(&self) -> Document {
+        let filter = self.filter.clone();
+        let projection = mongodb_projection(&self.mapped_schema);
+        let collection = self.collection.name();
+        let mut command = match self.sample {
+            Some(size) => doc! {
+                "aggregate": collection,
+                "pipeline": sample_pipeline(filter, size, projection),
+            },
+            None => doc! {
+                "find": collection,
+                "filter": filter.unwrap_or_default(),
+                "projection": projection,
+            },
+        };
+        if let Some(max_time) = self.throttle.max_time() {
+            command.insert("maxTimeMS", max_time.as_millis() as i64);
+        }
+        command
+    }
+}
+
+fn sample_pipeline(filter: Option<Document>, size: i64, projection: Document) -> Vec<Document> {
+    let mut pipeline = Vec::with_capacity(3);
+    if let Some(filter) = filter {
+        pipeline.push(doc! { "$match": filter });
+    }
+    pipeline.push(doc! { "$sample": { "size": size } });
+    pipeline.push(doc! { "$project": projection });
+    pipeline
+}
+
+type Documents = Pin<Box<dyn Stream<Item = Result<Document, Error>> + Send>>;
+
+// the documents of a cursor opened by running `command`, read with getMore
+// commands rather than the driver's cursor so the command can include
+// options the driver doesn't support
+fn command_cursor(
+    db: Database,
+    collection: String,
+    command: Document,
+    batch_size: usize,
+    throttle: Throttle,
+) -> Documents {
+    enum Next {
+        Command(Document),
+        GetMore(i64),
+        Done,
     }
+
+    let batches = stream::try_unfold(Next::Command(command), move |next| {
+        let db = db.clone();
+        let collection = collection.clone();
+        let throttle = throttle.clone();
+        async move {
+            let command = match next {
+                Next::Command(command) => command,
+                Next::GetMore(id) => {
+                    throttle.get_more().await;
+                    doc! {
+                        "getMore": id,
+                        "collection": collection,
+                        "batchSize": batch_size as i64,
+                    }
+                }
+                Next::Done => return Ok(None),
+            };
+            let response = db.run_command(command, None).await?;
+            let cursor = response
+                .get_document("cursor")
+                .map_err(|_| Error::Response(format!("no cursor in {}", response)))?;
+            let batch = cursor
+                .get_array("firstBatch")
+                .or_else(|_| cursor.get_array("nextBatch"))
+                .map_err(|_| Error::Response(format!("no batch in {}", cursor)))?
+                .iter()
+                .filter_map(|d| d.as_document().cloned())
+                .collect::<Vec<_>>();
+            let next = match cursor.get_i64("id") {
+                Ok(0) | Err(_) => Next::Done,
+                Ok(id) => Next::GetMore(id),
+            };
+            Ok::<_, Error>(Some((batch, next)))
+        }
+    });
+    Box::pin(
+        batches
+            .map_ok(|batch| stream::iter(batch.into_iter().map(Ok)))
+            .try_flatten(),
+    )
+}
+
+// waits on the throttle before each batch after the first, as the driver
+// fetches the next batch with getMore once `batch_size` documents are read
+fn throttle_batches(documents: Documents, throttle: Throttle, batch_size: usize) -> Documents {
+    let mut read = 0;
+    Box::pin(documents.then(move |document| {
+        read += 1;
+        let wait = read > batch_size && (read - 1) % batch_size == 0;
+        let throttle = throttle.clone();
+        async move {
+            if wait {
+                throttle.get_more().await;
+            }
+            document
+        }
+    }))
 }
 
 struct MongoStream {
-    cursor: TokioMutex<Fuse<Cursor>>,
+    cursor: TokioMutex<Fuse<Documents>>,
+    // limits the cursors open at once, released when the stream is dropped
+    _permit: Option<OwnedSemaphorePermit>,
     mapped_schema: Arc<MappedSchema>,
     schema: SchemaRef,
     batch_size: usize,
+    error_policy: ErrorPolicy,
 }
 
 impl Stream for MongoStream {
@@ -168,39 +540,45 @@ impl Stream for MongoStream {
         loop {
             match Pin::new(&mut *guard).poll_next(ctx) {
                 Poll::Pending if documents.is_empty() => break Poll::Pending,
-                Poll::Pending => {
-                    break Poll::Ready(Some(
-                        DocumentsReader::new(documents, self.mapped_schema.fields().clone())
-                            .into_record_batch(),
-                    ));
-                }
-                Poll::Ready(Some(Ok(val))) => documents.push(val),
-                Poll::Ready(Some(Err(e))) => {
-                    break Poll::Ready(Some(Err(
-                        DataFusionError::Execution(e.to_string()).into_arrow_external_error()
-                    )))
+                Poll::Pending => break Poll::Ready(Some(self.convert(documents))),
+                Poll::Ready(Some(Ok(val))) => {
+                    DOCUMENTS_READ.fetch_add(1, Ordering::Relaxed);
+                    documents.push(val);
                 }
+                Poll::Ready(Some(Err(e))) => break Poll::Ready(Some(Err(e.into()))),
                 Poll::Ready(None) if documents.is_empty() => {
                     break Poll::Ready(None);
                 }
-                Poll::Ready(None) => {
-                    break Poll::Ready(Some(
-                        DocumentsReader::new(documents, self.mapped_schema.fields().clone())
-                            .into_record_batch(),
-                    ));
-                }
+                Poll::Ready(None) => break Poll::Ready(Some(self.convert(documents))),
             }
         }
     }
 }
 
+impl MongoStream {
+    fn convert(&self, documents: Vec<Document>) -> ArrowResult<RecordBatch> {
+        tracing::trace!(documents = documents.len(), "cursor batch received");
+        let start = Instant::now();
+        let batch = DocumentsReader::new_with_error_policy(
+            documents,
+            self.mapped_schema.fields().clone(),
+            self.error_policy,
+        )
+        .into_record_batch()
+        .map_err(Error::from)?;
+        tracing::trace!(rows = batch.num_rows(), elapsed = ?start.elapsed(), "batch converted");
+        Ok(batch)
+    }
+}
+
 impl RecordBatchStream for MongoStream {
     fn schema(&self) -> SchemaRef {
         self.schema.clone()
     }
 }
 
-fn mongodb_projection(schema: Arc<MappedSchema>) -> Document {
+/// The projection reading just the fields of `schema`.
+pub fn mongodb_projection(schema: &MappedSchema) -> Document {
     let mut projection: Document = schema
         .fields()
         .iter()
@@ -0,0 +1,45 @@
+use arrow::error::ArrowError;
+use datafusion::error::DataFusionError;
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("MongoDB error: {0}")]
+    MongoDb(#[from] mongodb::error::Error),
+    #[error(transparent)]
+    Conversion(#[from] mongodb_arrow::Error),
+    #[error("unexpected response from MongoDB: {0}")]
+    Response(String),
+    #[cfg(feature = "regexp")]
+    #[error("invalid regex option {0:?}")]
+    RegexOption(char),
+    #[cfg(feature = "regexp")]
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+    #[error(transparent)]
+    Arrow(#[from] ArrowError),
+    #[error(transparent)]
+    DataFusion(#[from] DataFusionError),
+}
+
+// DataFusion can only carry other errors boxed inside an ArrowError, so
+// these keep the original error available to downcast
+impl From<Error> for DataFusionError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::DataFusion(e) => e,
+            e => DataFusionError::ArrowError(e.into()),
+        }
+    }
+}
+
+impl From<Error> for ArrowError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Arrow(e) => e,
+            e => ArrowError::from_external_error(Box::new(e)),
+        }
+    }
+}
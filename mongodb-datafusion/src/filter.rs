@@ -0,0 +1,266 @@
+use std::convert::TryFrom;
+
+use arrow::datatypes::{DataType, TimeUnit};
+use chrono::{TimeZone, Utc};
+use datafusion::{
+    logical_plan::{Expr, Operator},
+    scalar::ScalarValue,
+};
+use mongodb::bson::{oid::ObjectId, Bson, Document};
+use mongodb_arrow::{ErrorPolicy, MappedField, MappedSchema};
+
+#[cfg(feature = "regexp")]
+use crate::regexp;
+
+// Translates a DataFusion filter expression to a MongoDB query filter.
+//
+// DataFusion still applies the filter to the results, so the query may match
+// more documents than the expression would, but it must never match fewer.
+// Anything that can't be translated under that rule returns None.
+pub(crate) fn to_mongodb_filter(
+    expr: &Expr,
+    schema: &MappedSchema,
+    error_policy: ErrorPolicy,
+) -> Option<Document> {
+    match expr {
+        Expr::BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        } => match (
+            to_mongodb_filter(left, schema, error_policy),
+            to_mongodb_filter(right, schema, error_policy),
+        ) {
+            (Some(l), Some(r)) => Some(single("$and", vec![Bson::from(l), Bson::from(r)])),
+            // matching on only one side of an AND is a superset of both
+            (Some(v), None) | (None, Some(v)) => Some(v),
+            (None, None) => None,
+        },
+        Expr::BinaryExpr {
+            left,
+            op: Operator::Or,
+            right,
+        } => {
+            let l = to_mongodb_filter(left, schema, error_policy)?;
+            let r = to_mongodb_filter(right, schema, error_policy)?;
+            Some(single("$or", vec![Bson::from(l), Bson::from(r)]))
+        }
+        Expr::BinaryExpr { left, op, right } => match (left.as_ref(), right.as_ref()) {
+            (Expr::Column(name), Expr::Literal(value)) => {
+                comparison(field(schema, name)?, op, value)
+            }
+            (Expr::Literal(value), Expr::Column(name)) => {
+                comparison(field(schema, name)?, &flip(op)?, value)
+            }
+            _ => None,
+        },
+        // values of the wrong type are read as null under the null policy,
+        // which {field: null} wouldn't match
+        Expr::IsNull(expr) if error_policy != ErrorPolicy::Null => {
+            let field = field(schema, column(expr)?)?;
+            Some(single(field.mongodb_field(), Bson::Null))
+        }
+        Expr::IsNotNull(expr) => {
+            let field = field(schema, column(expr)?)?;
+            Some(single(field.mongodb_field(), single("$ne", Bson::Null)))
+        }
+        Expr::InList {
+            expr,
+            list,
+            negated: false,
+        } => {
+            let field = field(schema, column(expr)?)?;
+            let values = list
+                .iter()
+                .map(|e| match e {
+                    Expr::Literal(value) => eq_values(field, value),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?
+                .concat();
+            Some(single(field.mongodb_field(), single("$in", values)))
+        }
+        Expr::Between {
+            expr,
+            negated: false,
+            low,
+            high,
+        } => {
+            let field = field(schema, column(expr)?)?;
+            let low = match low.as_ref() {
+                Expr::Literal(value) => comparison(field, &Operator::GtEq, value)?,
+                _ => return None,
+            };
+            let high = match high.as_ref() {
+                Expr::Literal(value) => comparison(field, &Operator::LtEq, value)?,
+                _ => return None,
+            };
+            Some(single("$and", vec![Bson::from(low), Bson::from(high)]))
+        }
+        #[cfg(feature = "regexp")]
+        Expr::ScalarUDF { fun, args } if fun.name == regexp::NAME => match args.as_slice() {
+            [expr, Expr::Literal(ScalarValue::Utf8(Some(pattern))), Expr::Literal(ScalarValue::Utf8(Some(options)))]
+                if regexp::valid_options(options) =>
+            {
+                let field = field(schema, column(expr)?)?;
+                if !is_utf8(field.data_type()) {
+                    return None;
+                }
+                let mut regex = single("$regex", pattern.as_str());
+                regex.insert("$options", options.as_str());
+                let matches = single(field.mongodb_field(), regex);
+                // as with comparisons, values read as strings from other
+                // types are left to DataFusion
+                let other = single(
+                    field.mongodb_field(),
+                    single("$not", single("$type", "string")),
+                );
+                Some(single("$or", vec![Bson::from(matches), Bson::from(other)]))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn comparison(field: &MappedField, op: &Operator, value: &ScalarValue) -> Option<Document> {
+    let key = field.mongodb_field();
+    match op {
+        Operator::Eq => Some(single(key, single("$in", eq_values(field, value)?))),
+        Operator::NotEq => Some(single(
+            key,
+            single("$ne", to_bson(field.data_type(), value)?),
+        )),
+        Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq => {
+            let op = match op {
+                Operator::Lt => "$lt",
+                Operator::LtEq => "$lte",
+                Operator::Gt => "$gt",
+                _ => "$gte",
+            };
+            let range = single(key, single(op, to_bson(field.data_type(), value)?));
+            if is_utf8(field.data_type()) {
+                // string columns can be read from ObjectIds and symbols too,
+                // which MongoDB doesn't compare with strings, so always
+                // include them and leave it to DataFusion
+                let other = single(key, single("$not", single("$type", "string")));
+                Some(single("$or", vec![Bson::from(range), Bson::from(other)]))
+            } else {
+                Some(range)
+            }
+        }
+        _ => None,
+    }
+}
+
+// values that equal `value` once read from MongoDB
+fn eq_values(field: &MappedField, value: &ScalarValue) -> Option<Vec<Bson>> {
+    let bson = to_bson(field.data_type(), value)?;
+    let mut values = Vec::with_capacity(2);
+    if let Bson::String(ref s) = bson {
+        if let Ok(oid) = ObjectId::with_string(s) {
+            values.push(Bson::ObjectId(oid));
+        }
+    }
+    values.push(bson);
+    Some(values)
+}
+
+fn to_bson(data_type: &DataType, value: &ScalarValue) -> Option<Bson> {
+    match data_type {
+        DataType::Int32 | DataType::Int64 | DataType::Float64 => match value {
+            ScalarValue::Int8(Some(v)) => Some(Bson::Int32(i32::from(*v))),
+            ScalarValue::Int16(Some(v)) => Some(Bson::Int32(i32::from(*v))),
+            ScalarValue::Int32(Some(v)) => Some(Bson::Int32(*v)),
+            ScalarValue::Int64(Some(v)) => Some(Bson::Int64(*v)),
+            ScalarValue::UInt8(Some(v)) => Some(Bson::Int32(i32::from(*v))),
+            ScalarValue::UInt16(Some(v)) => Some(Bson::Int32(i32::from(*v))),
+            ScalarValue::UInt32(Some(v)) => Some(Bson::Int64(i64::from(*v))),
+            ScalarValue::UInt64(Some(v)) => i64::try_from(*v).ok().map(Bson::Int64),
+            ScalarValue::Float32(Some(v)) => Some(Bson::Double(f64::from(*v))),
+            ScalarValue::Float64(Some(v)) => Some(Bson::Double(*v)),
+            _ => None,
+        },
+        DataType::Boolean => match value {
+            ScalarValue::Boolean(Some(v)) => Some(Bson::Boolean(*v)),
+            _ => None,
+        },
+        DataType::Utf8 | DataType::LargeUtf8 => match value {
+            ScalarValue::Utf8(Some(v)) | ScalarValue::LargeUtf8(Some(v)) => {
+                Some(Bson::String(v.clone()))
+            }
+            _ => None,
+        },
+        // second precision timestamps are truncated when read, so comparisons
+        // against the untruncated MongoDB value could exclude matches
+        DataType::Timestamp(TimeUnit::Millisecond, _)
+        | DataType::Timestamp(TimeUnit::Microsecond, _)
+        | DataType::Timestamp(TimeUnit::Nanosecond, _) => match value {
+            ScalarValue::TimestampNanosecond(Some(v)) => {
+                Some(Bson::DateTime(Utc.timestamp_nanos(*v)))
+            }
+            ScalarValue::TimestampMicrosecond(Some(v)) => {
+                Some(Bson::DateTime(Utc.timestamp_nanos(v.checked_mul(1_000)?)))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns true if filters comparing a column of `data_type` with a literal
+/// can be sent to MongoDB.
+pub fn supports_pushdown(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int32
+            | DataType::Int64
+            | DataType::Float64
+            | DataType::Boolean
+            | DataType::Utf8
+            | DataType::LargeUtf8
+            | DataType::Timestamp(TimeUnit::Millisecond, _)
+            | DataType::Timestamp(TimeUnit::Microsecond, _)
+            | DataType::Timestamp(TimeUnit::Nanosecond, _)
+    )
+}
+
+fn is_utf8(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Utf8 | DataType::LargeUtf8)
+}
+
+// MongoDB compares the stored value, so filters on transformed fields
+// can't be sent
+fn field<'a>(schema: &'a MappedSchema, name: &str) -> Option<&'a MappedField> {
+    schema
+        .fields()
+        .iter()
+        .find(|f| f.name() == name)
+        .filter(|f| f.transforms().is_empty())
+}
+
+fn column(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Column(name) => Some(name.as_str()),
+        _ => None,
+    }
+}
+
+// the operator for the same comparison with the operands swapped
+fn flip(op: &Operator) -> Option<Operator> {
+    match op {
+        Operator::Eq => Some(Operator::Eq),
+        Operator::NotEq => Some(Operator::NotEq),
+        Operator::Lt => Some(Operator::Gt),
+        Operator::LtEq => Some(Operator::GtEq),
+        Operator::Gt => Some(Operator::Lt),
+        Operator::GtEq => Some(Operator::LtEq),
+        _ => None,
+    }
+}
+
+fn single<V: Into<Bson>>(key: &str, value: V) -> Document {
+    let mut document = Document::new();
+    document.insert(key, value);
+    document
+}
@@ -1 +1,10 @@
 pub mod datasource;
+mod error;
+mod filter;
+#[cfg(feature = "regexp")]
+pub mod regexp;
+pub mod throttle;
+pub mod wildcard;
+
+pub use error::{Error, Result};
+pub use filter::supports_pushdown;
@@ -0,0 +1,93 @@
+//! A `regexp_match` function matching MongoDB's `$regex` query operator.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, ArrayRef, BooleanArray, StringArray},
+    datatypes::DataType,
+};
+use datafusion::{
+    error::{DataFusionError, Result},
+    physical_plan::{functions::make_scalar_function, udf::ScalarUDF},
+    prelude::create_udf,
+};
+use regex::{Regex, RegexBuilder};
+
+use crate::Error;
+
+/// The name `regexp_match` is registered under.
+pub const NAME: &str = "regexp_match";
+
+/// `regexp_match(string, pattern, options)` is true where `pattern` matches
+/// anywhere in `string`, with the MongoDB `$regex` options `i`, `m`, `s`, and
+/// `x`.
+///
+/// Filters using it on a MongoDB column are pushed down to the query as
+/// `$regex`.
+pub fn regexp_match() -> ScalarUDF {
+    create_udf(
+        NAME,
+        vec![DataType::Utf8, DataType::Utf8, DataType::Utf8],
+        Arc::new(DataType::Boolean),
+        make_scalar_function(regexp_match_impl),
+    )
+}
+
+fn regexp_match_impl(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let strings = string_arg(&args[0])?;
+    let patterns = string_arg(&args[1])?;
+    let options = string_arg(&args[2])?;
+
+    // the pattern is almost always a literal, so only compile it when it
+    // changes
+    let mut compiled: Option<(&str, &str, Regex)> = None;
+    let mut builder = BooleanArray::builder(strings.len());
+    for i in 0..strings.len() {
+        if strings.is_null(i) || patterns.is_null(i) {
+            builder.append_null()?;
+            continue;
+        }
+        let pattern = patterns.value(i);
+        let opts = if options.is_null(i) {
+            ""
+        } else {
+            options.value(i)
+        };
+        let stale = match compiled {
+            Some((p, o, _)) => p != pattern || o != opts,
+            None => true,
+        };
+        if stale {
+            compiled = Some((pattern, opts, build(pattern, opts)?));
+        }
+        let regex = &compiled.as_ref().unwrap().2;
+        builder.append_value(regex.is_match(strings.value(i)))?;
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+fn build(pattern: &str, options: &str) -> Result<Regex> {
+    let mut builder = RegexBuilder::new(pattern);
+    for option in options.chars() {
+        match option {
+            'i' => builder.case_insensitive(true),
+            'm' => builder.multi_line(true),
+            's' => builder.dot_matches_new_line(true),
+            'x' => builder.ignore_whitespace(true),
+            _ => return Err(Error::RegexOption(option).into()),
+        };
+    }
+    Ok(builder.build().map_err(Error::from)?)
+}
+
+/// Whether `options` are all understood by both MongoDB and `regexp_match`.
+pub(crate) fn valid_options(options: &str) -> bool {
+    options.chars().all(|c| matches!(c, 'i' | 'm' | 's' | 'x'))
+}
+
+fn string_arg(array: &ArrayRef) -> Result<&StringArray> {
+    array
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .ok_or_else(|| DataFusionError::Execution("expected a string argument".to_owned()))
+}
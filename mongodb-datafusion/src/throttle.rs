@@ -0,0 +1,100 @@
+//! Limits on the load queries put on a cluster.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{self, Instant},
+};
+
+/// Limits shared by every table reading from a cluster. The default sets no
+/// limits.
+#[derive(Clone, Default)]
+pub struct Throttle {
+    max_time: Option<Duration>,
+    cursors: Option<Arc<Semaphore>>,
+    get_more: Option<Arc<RateLimit>>,
+}
+
+impl fmt::Debug for Throttle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Throttle")
+            .field("max_time", &self.max_time)
+            .field(
+                "cursors",
+                &self.cursors.as_ref().map(|s| s.available_permits()),
+            )
+            .field("get_more", &self.get_more.as_ref().map(|r| r.interval))
+            .finish()
+    }
+}
+
+impl Throttle {
+    /// Sends every find and aggregate with `maxTimeMS`, so the server stops
+    /// queries running longer than `max_time`.
+    pub fn with_max_time(mut self, max_time: Duration) -> Self {
+        self.max_time = Some(max_time);
+        self
+    }
+
+    /// Waits for a cursor to finish before opening more than `max` at once.
+    pub fn with_max_cursors(mut self, max: usize) -> Self {
+        self.cursors = Some(Arc::new(Semaphore::new(max)));
+        self
+    }
+
+    /// Fetches at most `per_second` batches after the first, across all
+    /// cursors.
+    pub fn with_get_more_rate(mut self, per_second: u32) -> Self {
+        self.get_more = Some(Arc::new(RateLimit {
+            interval: Duration::from_secs(1) / per_second.max(1),
+            next: Mutex::new(Instant::now()),
+        }));
+        self
+    }
+
+    pub fn max_time(&self) -> Option<Duration> {
+        self.max_time
+    }
+
+    // held for as long as a cursor is open, None if cursors aren't limited
+    pub(crate) async fn cursor(&self) -> Option<OwnedSemaphorePermit> {
+        match self.cursors {
+            Some(ref cursors) => Some(cursors.clone().acquire_owned().await),
+            None => None,
+        }
+    }
+
+    // waits until another getMore is allowed
+    pub(crate) async fn get_more(&self) {
+        if let Some(ref rate) = self.get_more {
+            rate.wait().await;
+        }
+    }
+
+    pub(crate) fn limits_get_more(&self) -> bool {
+        self.get_more.is_some()
+    }
+}
+
+struct RateLimit {
+    interval: Duration,
+    // when the next call is allowed
+    next: Mutex<Instant>,
+}
+
+impl RateLimit {
+    async fn wait(&self) {
+        let at = {
+            let mut next = self.next.lock().expect("rate limit lock poisoned");
+            let at = (*next).max(Instant::now());
+            *next = at + self.interval;
+            at
+        };
+        time::delay_until(at).await;
+    }
+}
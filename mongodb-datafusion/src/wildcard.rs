@@ -0,0 +1,548 @@
+use std::{
+    any::Any,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+};
+
+use arrow::{
+    array::{ArrayRef, StringArray},
+    datatypes::{Schema, SchemaRef},
+    error::{ArrowError, Result as ArrowResult},
+    record_batch::RecordBatch,
+};
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use datafusion::{
+    datasource::{
+        datasource::{Statistics, TableProviderFilterPushDown},
+        TableProvider,
+    },
+    error::{DataFusionError, Result},
+    logical_plan::{Expr, Operator},
+    physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream, SendableRecordBatchStream},
+    scalar::ScalarValue,
+};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use mongodb::bson::doc;
+use mongodb_arrow::{ErrorPolicy, MappedSchema};
+
+use crate::{
+    datasource::{MongoDbCollection, SharedClient, SnapshotTime},
+    filter::to_mongodb_filter,
+    throttle::Throttle,
+    Error,
+};
+
+/// Mapping a column to this MongoDB field fills it with the name of the
+/// collection each row was read from.
+pub const COLLECTION_FIELD: &str = "$collection";
+
+/// How the collections matching a pattern divide up their documents by
+/// time, so those outside a query's time range can be skipped.
+#[derive(Clone, Debug)]
+pub struct TimePartitions {
+    /// timestamp column the collections are split on
+    pub column: String,
+    /// strftime style format of the part of the collection name matched by
+    /// `*`, e.g. `%Y_%m` for `events_2024_01`
+    pub format: String,
+}
+
+/// The collections matching a pattern with a single `*`, such as
+/// `events_*`, read as one table.
+///
+/// Each collection is taken to hold the documents from the time in its name
+/// up to the time in the next collection's name, so with `TimePartitions`
+/// set filters on the partition column skip collections that can't match.
+pub struct MongoDbWildcard {
+    table: WildcardTable,
+    // all columns, including any collection column
+    mapped_schema: MappedSchema,
+    schema: SchemaRef,
+}
+
+impl MongoDbWildcard {
+    /// Reads the collections in `db` matching `mapped_schema`'s collection
+    /// name, which must contain exactly one `*`.
+    pub fn new(
+        client: SharedClient,
+        db: String,
+        mapped_schema: MappedSchema,
+        error_policy: Arc<RwLock<ErrorPolicy>>,
+    ) -> Result<Self> {
+        let pattern = mapped_schema.mongodb_collection();
+        let mut parts = pattern.splitn(2, '*');
+        let prefix = parts.next().unwrap_or("").to_owned();
+        let suffix = match parts.next() {
+            Some(suffix) if !suffix.contains('*') => suffix.to_owned(),
+            _ => {
+                return Err(DataFusionError::Plan(format!(
+                    "collection pattern {:?} must contain exactly one *",
+                    pattern
+                )))
+            }
+        };
+        let inner_fields = mapped_schema
+            .fields()
+            .iter()
+            .filter(|f| f.mongodb_field() != COLLECTION_FIELD)
+            .cloned()
+            .collect::<Vec<_>>();
+        if inner_fields.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "{} has no columns read from MongoDB",
+                pattern
+            )));
+        }
+        let inner_schema = MappedSchema::new_with_metadata(
+            pattern.to_owned(),
+            inner_fields,
+            mapped_schema.metadata().clone(),
+        );
+        Ok(Self {
+            table: WildcardTable {
+                client,
+                db,
+                prefix,
+                suffix,
+                inner_schema,
+                error_policy,
+                sample: None,
+                snapshot_time: SnapshotTime::default(),
+                throttle: Throttle::default(),
+                partitions: None,
+            },
+            schema: Arc::new(mapped_schema.clone().into()),
+            mapped_schema,
+        })
+    }
+
+    /// Limits scans of each collection to a random sample of at most `size`
+    /// documents.
+    pub fn with_sample(mut self, size: i64) -> Self {
+        self.table.sample = Some(size);
+        self
+    }
+
+    /// Reads every collection at the cluster time `snapshot_time` holds.
+    pub fn with_snapshot_time(mut self, snapshot_time: SnapshotTime) -> Self {
+        self.table.snapshot_time = snapshot_time;
+        self
+    }
+
+    /// Applies `throttle`'s limits to the scan of each collection.
+    pub fn with_throttle(mut self, throttle: Throttle) -> Self {
+        self.table.throttle = throttle;
+        self
+    }
+
+    /// Skips collections outside the time range filtered on.
+    pub fn with_partitions(mut self, partitions: TimePartitions) -> Self {
+        self.table.partitions = Some(partitions);
+        self
+    }
+}
+
+impl TableProvider for MongoDbWildcard {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        batch_size: usize,
+        filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let projection = projection
+            .clone()
+            .unwrap_or_else(|| (0..self.mapped_schema.fields().len()).collect());
+        let mut inner_projection = Vec::new();
+        let mut columns = Vec::with_capacity(projection.len());
+        for &i in &projection {
+            let field = self.mapped_schema.fields().get(i).ok_or_else(|| {
+                DataFusionError::Internal("Projection index out of range".to_owned())
+            })?;
+            if field.mongodb_field() == COLLECTION_FIELD {
+                columns.push(Column::Collection);
+            } else {
+                columns.push(Column::Inner(inner_projection.len()));
+                inner_projection.push(inner_index(&self.mapped_schema, i));
+            }
+        }
+        // a batch needs at least one column to have rows
+        if inner_projection.is_empty() {
+            inner_projection.push(0);
+        }
+        let schema = Schema::new_with_metadata(
+            projection
+                .iter()
+                .map(|&i| self.schema.field(i).clone())
+                .collect(),
+            self.schema.metadata().clone(),
+        );
+
+        let range = match self.table.partitions {
+            Some(ref partitions) => time_range(&partitions.column, filters),
+            None => (None, None),
+        };
+        Ok(Arc::new(WildcardExec {
+            table: self.table.clone(),
+            inner_projection,
+            columns,
+            schema: Arc::new(schema),
+            filters: filters.to_vec(),
+            batch_size,
+            range,
+        }))
+    }
+
+    fn statistics(&self) -> Statistics {
+        Default::default()
+    }
+
+    fn supports_filter_pushdown(&self, filter: &Expr) -> Result<TableProviderFilterPushDown> {
+        let error_policy = *self
+            .table
+            .error_policy
+            .read()
+            .expect("error policy lock poisoned");
+        match to_mongodb_filter(filter, &self.table.inner_schema, error_policy) {
+            Some(_) => Ok(TableProviderFilterPushDown::Inexact),
+            None => Ok(TableProviderFilterPushDown::Unsupported),
+        }
+    }
+}
+
+// the index in the inner schema of column `i`, skipping collection columns
+fn inner_index(schema: &MappedSchema, i: usize) -> usize {
+    schema.fields()[..i]
+        .iter()
+        .filter(|f| f.mongodb_field() != COLLECTION_FIELD)
+        .count()
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Column {
+    // index in the batches read from each collection
+    Inner(usize),
+    Collection,
+}
+
+// what's needed to scan the matching collections, cloned in to the plan
+#[derive(Clone, Debug)]
+struct WildcardTable {
+    client: SharedClient,
+    db: String,
+    prefix: String,
+    suffix: String,
+    // the columns read from MongoDB
+    inner_schema: MappedSchema,
+    error_policy: Arc<RwLock<ErrorPolicy>>,
+    sample: Option<i64>,
+    snapshot_time: SnapshotTime,
+    throttle: Throttle,
+    partitions: Option<TimePartitions>,
+}
+
+impl WildcardTable {
+    // the part of `name` matched by `*`, if it matches the pattern
+    fn matches<'a>(&self, name: &'a str) -> Option<&'a str> {
+        let rest = name.strip_prefix(self.prefix.as_str())?;
+        rest.strip_suffix(self.suffix.as_str())
+    }
+}
+
+#[derive(Debug)]
+struct WildcardExec {
+    table: WildcardTable,
+    inner_projection: Vec<usize>,
+    columns: Vec<Column>,
+    schema: SchemaRef,
+    filters: Vec<Expr>,
+    batch_size: usize,
+    // milliseconds since the epoch the partition column is filtered to
+    range: (Option<i64>, Option<i64>),
+}
+
+impl WildcardExec {
+    // the collections to read, in name order
+    async fn collections(&self) -> Result<Vec<String>> {
+        let table = &self.table;
+        let mut names = table
+            .client
+            .get()
+            .database(&table.db)
+            .list_collection_names(doc! { "type": "collection" })
+            .await
+            .map_err(Error::from)?
+            .into_iter()
+            .filter(|name| table.matches(name).is_some())
+            .collect::<Vec<_>>();
+        names.sort();
+
+        let partitions = match table.partitions {
+            Some(ref partitions) => partitions,
+            None => return Ok(names),
+        };
+        let (min, max) = self.range;
+        // collections without a time in their name are always read
+        let (mut timed, untimed): (Vec<_>, Vec<_>) = names
+            .into_iter()
+            .map(|name| {
+                let start = table
+                    .matches(&name)
+                    .and_then(|part| partition_start(part, &partitions.format));
+                (start, name)
+            })
+            .partition(|(start, _)| start.is_some());
+        timed.sort();
+
+        let mut keep = untimed.into_iter().map(|(_, n)| n).collect::<Vec<_>>();
+        for (i, (start, name)) in timed.iter().enumerate() {
+            let start = start.expect("partitioned on start");
+            let end = timed.get(i + 1).and_then(|(next, _)| *next);
+            let before_max = max.map_or(true, |max| start <= max);
+            let after_min = min.map_or(true, |min| end.map_or(true, |end| end > min));
+            if before_max && after_min {
+                keep.push(name.clone());
+            } else {
+                tracing::debug!(collection = %name, "collection outside filtered time range");
+            }
+        }
+        keep.sort();
+        Ok(keep)
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for WildcardExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(1)
+    }
+
+    fn with_new_children(&self, _: Vec<Arc<dyn ExecutionPlan>>) -> Result<Arc<dyn ExecutionPlan>> {
+        Err(DataFusionError::Internal(format!(
+            "Children cannot be replaced in {:?}",
+            self
+        )))
+    }
+
+    async fn execute(&self, _partition: usize) -> Result<SendableRecordBatchStream> {
+        let collections = self.collections().await?;
+        tracing::debug!(?collections, "reading matching collections");
+
+        let mut plans = Vec::with_capacity(collections.len());
+        for name in collections {
+            let table = &self.table;
+            let schema = MappedSchema::new_with_metadata(
+                name.clone(),
+                table.inner_schema.fields().clone(),
+                table.inner_schema.metadata().clone(),
+            );
+            let collection = MongoDbCollection::new_with_shared_client(
+                table.client.clone(),
+                table.db.clone(),
+                schema,
+                table.error_policy.clone(),
+            )
+            .with_snapshot_time(table.snapshot_time.clone())
+            .with_throttle(table.throttle.clone());
+            let collection = match table.sample {
+                Some(size) => collection.with_sample(size),
+                None => collection,
+            };
+            let plan = collection.scan(
+                &Some(self.inner_projection.clone()),
+                self.batch_size,
+                &self.filters,
+            )?;
+            plans.push((name, plan));
+        }
+
+        // one collection at a time, in name order
+        let schema = self.schema.clone();
+        let columns = self.columns.clone();
+        let batches = stream::iter(plans)
+            .then(|(name, plan)| async move {
+                let stream = plan
+                    .execute(0)
+                    .await
+                    .map_err(|e| ArrowError::from(Error::from(e)))?;
+                Ok::<_, ArrowError>((name, stream))
+            })
+            .map_ok(move |(name, stream)| {
+                let schema = schema.clone();
+                let columns = columns.clone();
+                stream.map(move |batch| with_collection(&schema, &columns, &name, batch?))
+            })
+            .try_flatten();
+        Ok(Box::pin(WildcardStream {
+            schema: self.schema.clone(),
+            batches: Box::pin(batches),
+        }))
+    }
+}
+
+// arranges a batch read from `collection` in to the scan's columns
+fn with_collection(
+    schema: &SchemaRef,
+    columns: &[Column],
+    collection: &str,
+    batch: RecordBatch,
+) -> ArrowResult<RecordBatch> {
+    let arrays = columns
+        .iter()
+        .map(|column| match column {
+            Column::Inner(i) => batch.column(*i).clone(),
+            Column::Collection => {
+                Arc::new(StringArray::from(vec![collection; batch.num_rows()])) as ArrayRef
+            }
+        })
+        .collect();
+    RecordBatch::try_new(schema.clone(), arrays)
+}
+
+struct WildcardStream {
+    schema: SchemaRef,
+    batches: Pin<Box<dyn Stream<Item = ArrowResult<RecordBatch>> + Send>>,
+}
+
+impl Stream for WildcardStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.batches.as_mut().poll_next(cx)
+    }
+}
+
+impl RecordBatchStream for WildcardStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+// the time, in milliseconds since the epoch, in the part of a collection
+// name matched by `*`. Formats without a day, or month, start at the first
+fn partition_start(part: &str, format: &str) -> Option<i64> {
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(part, format) {
+        return Some(datetime.timestamp_millis());
+    }
+    let date = NaiveDate::parse_from_str(part, format)
+        .or_else(|_| {
+            NaiveDate::parse_from_str(&format!("{}\t01", part), &format!("{}\t%d", format))
+        })
+        .or_else(|_| {
+            NaiveDate::parse_from_str(&format!("{}\t01\t01", part), &format!("{}\t%m\t%d", format))
+        })
+        .ok()?;
+    Some(date.and_hms(0, 0, 0).timestamp_millis())
+}
+
+// the inclusive range, in milliseconds since the epoch, that filters limit
+// `column` to
+fn time_range(column: &str, filters: &[Expr]) -> (Option<i64>, Option<i64>) {
+    let mut range = (None, None);
+    for filter in filters {
+        narrow(column, filter, &mut range);
+    }
+    range
+}
+
+fn narrow(column: &str, expr: &Expr, range: &mut (Option<i64>, Option<i64>)) {
+    match expr {
+        Expr::BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        } => {
+            narrow(column, left, range);
+            narrow(column, right, range);
+        }
+        Expr::BinaryExpr { left, op, right } => {
+            let (op, value) = match (left.as_ref(), right.as_ref()) {
+                (Expr::Column(name), Expr::Literal(value)) if name == column => (op.clone(), value),
+                (Expr::Literal(value), Expr::Column(name)) if name == column => {
+                    let op = match op {
+                        Operator::Lt => Operator::Gt,
+                        Operator::LtEq => Operator::GtEq,
+                        Operator::Gt => Operator::Lt,
+                        Operator::GtEq => Operator::LtEq,
+                        op => op.clone(),
+                    };
+                    (op, value)
+                }
+                _ => return,
+            };
+            let millis = match timestamp_millis(value) {
+                Some(millis) => millis,
+                None => return,
+            };
+            // strict comparisons are widened, the range only has to include
+            // every match
+            match op {
+                Operator::Eq => {
+                    raise_min(range, millis);
+                    lower_max(range, millis);
+                }
+                Operator::Gt | Operator::GtEq => raise_min(range, millis),
+                Operator::Lt | Operator::LtEq => lower_max(range, millis),
+                _ => (),
+            }
+        }
+        Expr::Between {
+            expr,
+            negated: false,
+            low,
+            high,
+        } => {
+            if !matches!(expr.as_ref(), Expr::Column(name) if name == column) {
+                return;
+            }
+            if let Expr::Literal(value) = low.as_ref() {
+                if let Some(millis) = timestamp_millis(value) {
+                    raise_min(range, millis);
+                }
+            }
+            if let Expr::Literal(value) = high.as_ref() {
+                if let Some(millis) = timestamp_millis(value) {
+                    lower_max(range, millis);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+fn raise_min(range: &mut (Option<i64>, Option<i64>), millis: i64) {
+    range.0 = Some(range.0.map_or(millis, |min| min.max(millis)));
+}
+
+fn lower_max(range: &mut (Option<i64>, Option<i64>), millis: i64) {
+    range.1 = Some(range.1.map_or(millis, |max| max.min(millis)));
+}
+
+fn timestamp_millis(value: &ScalarValue) -> Option<i64> {
+    match value {
+        ScalarValue::TimestampNanosecond(Some(v)) => Some(v.div_euclid(1_000_000)),
+        ScalarValue::TimestampMicrosecond(Some(v)) => Some(v.div_euclid(1_000)),
+        _ => None,
+    }
+}
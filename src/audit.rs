@@ -0,0 +1,116 @@
+use std::{collections::HashMap, io, sync::Arc};
+
+use arrow::{
+    array::{ArrayRef, StringBuilder, UInt64Builder},
+    datatypes::{DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, document::ValueAccessError},
+    options::FindOptions,
+    Collection,
+};
+use mongodb_arrow::{BsonGetNested, DocumentBuilder, MappedSchema};
+use mongodb_datafusion::datasource::mongodb_projection;
+
+use crate::output::{self, DisplayOptions, Format};
+
+// rows converted before each field's builder is emptied, so a full scan
+// doesn't hold the whole collection in memory
+const CHUNK_SIZE: usize = 1024;
+
+/// Reads `schema`'s collection, a random sample of `sample` documents or
+/// all of it if None, and writes for each column the BSON types found in
+/// its field, with how many documents had each type and how many of those
+/// would fail to convert to the column's type.
+pub async fn run(
+    collection: Collection,
+    schema: &MappedSchema,
+    sample: Option<i64>,
+    format: Format,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let projection = mongodb_projection(schema);
+    let mut cursor = match sample {
+        Some(size) => {
+            let pipeline = vec![
+                doc! { "$sample": { "size": size } },
+                doc! { "$project": projection },
+            ];
+            collection.aggregate(pipeline, None).await?
+        }
+        None => {
+            let options = FindOptions::builder().projection(Some(projection)).build();
+            collection.find(None, options).await?
+        }
+    };
+
+    // a single field builder per column, so each failure is counted rather
+    // than just the first in a document
+    let mut builders = schema
+        .fields()
+        .iter()
+        .map(|f| DocumentBuilder::new(vec![f.clone()], CHUNK_SIZE))
+        .collect::<Result<Vec<_>, _>>()?;
+    // per column, BSON type -> (documents, failures)
+    let mut counts = vec![HashMap::<String, (u64, u64)>::new(); builders.len()];
+    let mut documents = 0;
+
+    while let Some(document) = cursor.try_next().await? {
+        documents += 1;
+        for (i, field) in schema.fields().iter().enumerate() {
+            let bson_type = match document.get_nested(field.mongodb_field()) {
+                Ok(value) => format!("{:?}", value.element_type()),
+                Err(ValueAccessError::NotPresent) => "missing".to_owned(),
+                Err(_) => "missing, parent not a document".to_owned(),
+            };
+            let failed = builders[i].append_value(document.clone()).is_err();
+            let count = counts[i].entry(bson_type).or_default();
+            count.0 += 1;
+            count.1 += u64::from(failed);
+        }
+        if documents % CHUNK_SIZE == 0 {
+            for builder in &mut builders {
+                builder.finish();
+            }
+        }
+    }
+
+    let mut columns = StringBuilder::new(builders.len());
+    let mut mongodb_fields = StringBuilder::new(builders.len());
+    let mut bson_types = StringBuilder::new(builders.len());
+    let mut totals = UInt64Builder::new(builders.len());
+    let mut failures = UInt64Builder::new(builders.len());
+    for (field, counts) in schema.fields().iter().zip(counts) {
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        // most common first
+        counts.sort_by(|(a, (a_n, _)), (b, (b_n, _))| b_n.cmp(a_n).then_with(|| a.cmp(b)));
+        for (bson_type, (total, failed)) in counts {
+            columns.append_value(field.name())?;
+            mongodb_fields.append_value(field.mongodb_field())?;
+            bson_types.append_value(&bson_type)?;
+            totals.append_value(total)?;
+            failures.append_value(failed)?;
+        }
+    }
+
+    let schema = Schema::new(vec![
+        Field::new("column", DataType::Utf8, false),
+        Field::new("mongodb_field", DataType::Utf8, false),
+        Field::new("bson_type", DataType::Utf8, false),
+        Field::new("documents", DataType::UInt64, false),
+        Field::new("failed", DataType::UInt64, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(columns.finish()),
+        Arc::new(mongodb_fields.finish()),
+        Arc::new(bson_types.finish()),
+        Arc::new(totals.finish()),
+        Arc::new(failures.finish()),
+    ];
+    let batch = RecordBatch::try_new(Arc::new(schema), columns)?;
+    let stdout = io::stdout();
+    output::write_display(format, &DisplayOptions::default(), &[batch], stdout.lock())?;
+    eprintln!("{} documents read", documents);
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+
+use arrow::datatypes::SchemaRef;
+use rustyline::{completion::Completer, Context};
+use rustyline_derive::{Helper, Highlighter, Hinter, Validator};
+
+static KEYWORDS: &[&str] = &[
+    "ALL", "AND", "AS", "ASC", "AVG", "BETWEEN", "BY", "CASE", "CAST", "COLUMNS", "COPY", "COUNT",
+    "DESC", "DESCRIBE", "DISTINCT", "ELSE", "END", "EXPLAIN", "FALSE", "FORMAT", "FROM", "FULL",
+    "GROUP", "HAVING", "IN", "INNER", "IS", "JOIN", "LEFT", "LIKE", "LIMIT", "MAX", "MIN", "NOT",
+    "NULL", "ON", "OR", "ORDER", "OUTER", "RIGHT", "SELECT", "SHOW", "SUM", "THEN", "TO", "TRUE",
+    "UNION", "VALUES", "WHEN", "WHERE", "WITH",
+];
+
+#[derive(Default, Helper, Highlighter, Hinter, Validator)]
+pub struct SqlHelper {
+    /// registered tables, updated by the REPL before reading each line
+    pub tables: BTreeMap<String, SchemaRef>,
+}
+
+impl Completer for SqlHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let word = &line[start..pos];
+
+        // table.column
+        if let Some(dot) = word.rfind('.') {
+            let (table, prefix) = (&word[..dot], &word[dot + 1..]);
+            let candidates = match self.tables.get(table) {
+                Some(schema) => schema
+                    .fields()
+                    .iter()
+                    .map(|f| f.name())
+                    .filter(|name| name.starts_with(prefix))
+                    .map(|name| format!("{}.{}", table, name))
+                    .collect(),
+                None => Vec::new(),
+            };
+            return Ok((start, candidates));
+        }
+
+        if word.is_empty() {
+            return Ok((start, Vec::new()));
+        }
+
+        let lowercase = word.chars().all(|c| !c.is_uppercase());
+        let mut candidates = KEYWORDS
+            .iter()
+            .filter(|k| starts_with_ignore_case(k, word))
+            .map(|k| {
+                if lowercase {
+                    k.to_lowercase()
+                } else {
+                    (*k).to_owned()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        candidates.extend(
+            self.tables
+                .keys()
+                .filter(|name| name.starts_with(word))
+                .cloned(),
+        );
+
+        // columns of any table mentioned in the line
+        let words = line
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .collect::<Vec<_>>();
+        for (table, schema) in self.tables.iter() {
+            if words.contains(&table.as_str()) {
+                candidates.extend(
+                    schema
+                        .fields()
+                        .iter()
+                        .map(|f| f.name())
+                        .filter(|name| name.starts_with(word))
+                        .cloned(),
+                );
+            }
+        }
+
+        candidates.sort();
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
+
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    s.len() >= prefix.len()
+        && s.is_char_boundary(prefix.len())
+        && s[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
@@ -0,0 +1,138 @@
+use std::{collections::HashMap, env, fs, io, path::PathBuf};
+
+use datafusion::scalar::ScalarValue;
+use serde::Deserialize;
+
+use crate::prepare;
+
+/// Contents of ~/.config/bishop/config.toml
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    #[serde(default)]
+    pub queries: HashMap<String, SavedQuery>,
+    /// REPL shortcuts, see `\alias`
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+}
+
+/// Named connection settings, any left unset fall back to the defaults.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub mongodb: Option<String>,
+    pub db: Option<String>,
+    pub schema: Option<PathBuf>,
+    pub format: Option<String>,
+    pub max_rows: Option<usize>,
+    pub expanded: Option<bool>,
+    pub null: Option<String>,
+    pub timing: Option<bool>,
+    pub readonly: Option<bool>,
+    pub query_log: Option<PathBuf>,
+    /// sent as maxTimeMS with every find and aggregate
+    pub max_time_ms: Option<u64>,
+    /// the most cursors open at once, per cluster
+    pub max_cursors: Option<usize>,
+    /// the most getMore calls per second, per cluster
+    pub get_more_per_second: Option<u32>,
+    pub tls_ca_file: Option<PathBuf>,
+    pub tls_certificate_key_file: Option<PathBuf>,
+    pub tls_allow_invalid_certificates: Option<bool>,
+    pub auth_mechanism: Option<String>,
+    pub auth_source: Option<String>,
+    #[serde(default)]
+    pub auth_mechanism_properties: HashMap<String, String>,
+}
+
+/// A query run by name with `\run name [param=value ...]`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SavedQuery {
+    /// SQL with `$name` placeholders for parameters
+    pub sql: String,
+    /// default parameter values
+    #[serde(default)]
+    pub params: HashMap<String, toml::Value>,
+}
+
+impl SavedQuery {
+    /// Returns the SQL with the `name=value` pairs in `args` substituted,
+    /// falling back to the defaults for any not given.
+    pub fn bind(&self, args: &str) -> Result<String, String> {
+        let mut params = self
+            .params
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), scalar(name, value)?)))
+            .collect::<Result<HashMap<_, _>, String>>()?;
+        params.extend(prepare::named_args(args)?);
+        prepare::bind_named(&self.sql, &params)
+    }
+}
+
+fn scalar(name: &str, value: &toml::Value) -> Result<ScalarValue, String> {
+    match value {
+        toml::Value::String(s) => Ok(ScalarValue::Utf8(Some(s.clone()))),
+        toml::Value::Integer(i) => Ok(ScalarValue::Int64(Some(*i))),
+        toml::Value::Float(f) => Ok(ScalarValue::Float64(Some(*f))),
+        toml::Value::Boolean(b) => Ok(ScalarValue::Boolean(Some(*b))),
+        _ => Err(format!("unsupported default for parameter {}", name)),
+    }
+}
+
+impl Config {
+    /// Reads the config file, an empty config is returned if it doesn't exist.
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = match path() {
+            Some(p) => p,
+            None => return Ok(Config::default()),
+        };
+        match fs::read_to_string(&path) {
+            Ok(s) => toml::from_str(&s)
+                .map_err(|e| format!("error reading {}: {}", path.display(), e).into()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns the profile `name`, or the one named "default" if `name` is
+    /// None.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile, String> {
+        let profile = match name {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("no profile named {:?}", name))?,
+            None => self.profiles.get("default").cloned().unwrap_or_default(),
+        };
+        profile.validate()?;
+        Ok(profile)
+    }
+}
+
+impl Profile {
+    // zero would stop every query, rather than not limiting them
+    fn validate(&self) -> Result<(), String> {
+        if self.max_time_ms == Some(0) {
+            return Err("max_time_ms must be greater than 0".to_owned());
+        }
+        if self.max_cursors == Some(0) {
+            return Err("max_cursors must be at least 1".to_owned());
+        }
+        if self.get_more_per_second == Some(0) {
+            return Err("get_more_per_second must be at least 1".to_owned());
+        }
+        Ok(())
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => dirs::home_dir()?.join(".config"),
+    };
+    Some(dir.join("bishop").join("config.toml"))
+}
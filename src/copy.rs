@@ -0,0 +1,60 @@
+use std::path::Path;
+
+use crate::{
+    output::Format,
+    statements::{keyword, parenthesised, quoted},
+};
+
+/// A parsed `COPY (query) TO 'path' [WITH] [(FORMAT name)]` statement.
+#[derive(Debug, PartialEq)]
+pub struct CopyTo<'a> {
+    pub query: &'a str,
+    pub path: String,
+    pub format: Format,
+}
+
+/// Returns None if `sql` isn't a COPY statement, or an error if it is but
+/// it's malformed.
+pub fn parse(sql: &str) -> Option<Result<CopyTo<'_>, String>> {
+    let sql = sql.trim();
+    let keyword = sql.get(..4)?;
+    if !keyword.eq_ignore_ascii_case("copy") {
+        return None;
+    }
+    let rest = &sql[4..];
+    if !rest.starts_with(char::is_whitespace) && !rest.starts_with('(') {
+        return None;
+    }
+    Some(parse_copy(rest.trim_start()))
+}
+
+fn parse_copy(sql: &str) -> Result<CopyTo<'_>, String> {
+    let (query, rest) = parenthesised(sql).ok_or("expected COPY (query) TO 'path'")?;
+    let rest = keyword(rest, "to").ok_or("expected TO after COPY (query)")?;
+    let (path, rest) = quoted(rest).ok_or("expected quoted path after TO")?;
+
+    let rest = keyword(rest, "with").unwrap_or(rest);
+    let format = if rest.trim().is_empty() {
+        format_for_path(&path)
+    } else {
+        let (options, rest) = parenthesised(rest).ok_or("expected (FORMAT name)")?;
+        if !rest.trim().is_empty() {
+            return Err(format!("unexpected {:?} after COPY options", rest.trim()));
+        }
+        let format = keyword(options, "format").ok_or("expected (FORMAT name)")?;
+        format.trim().trim_matches('\'').parse()?
+    };
+
+    Ok(CopyTo {
+        query: query.trim(),
+        path,
+        format,
+    })
+}
+
+pub fn format_for_path(path: &str) -> Format {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(ext) => ext.parse().unwrap_or(Format::Csv),
+        None => Format::Csv,
+    }
+}
@@ -0,0 +1,228 @@
+use std::time::Duration;
+
+use arrow::datatypes::{DataType, DateUnit, Field, TimeUnit};
+use mongodb_arrow::{MappedField, MappedSchema};
+
+use crate::statements::{identifier, keyword, parenthesised, quoted, split_list};
+
+/// A parsed
+/// `CREATE EXTERNAL TABLE name (column TYPE ['mongodb=field'] [NOT NULL], ...)
+/// STORED AS MONGODB LOCATION '[db.]collection'` statement.
+#[derive(Debug)]
+pub struct CreateExternalTable<'a> {
+    pub name: &'a str,
+    /// None for the default database
+    pub db: Option<String>,
+    pub schema: MappedSchema,
+}
+
+/// Returns None if `sql` isn't a CREATE EXTERNAL TABLE ... STORED AS MONGODB
+/// statement, or an error if it is but it's malformed.
+///
+/// Other CREATE EXTERNAL TABLE statements are left for DataFusion.
+pub fn parse(sql: &str) -> Option<Result<CreateExternalTable<'_>, String>> {
+    let rest = keyword(sql.trim(), "create")?;
+    let rest = keyword(rest, "external")?;
+    let rest = keyword(rest, "table")?;
+    if !stored_as_mongodb(rest) {
+        return None;
+    }
+    Some(parse_create(rest))
+}
+
+/// A parsed `CREATE TEMP[ORARY] TABLE name AS query` statement.
+#[derive(Debug, PartialEq)]
+pub struct CreateTempTable<'a> {
+    pub name: &'a str,
+    pub query: &'a str,
+}
+
+/// Returns None if `sql` isn't a CREATE TEMP TABLE statement, or an error if
+/// it is but it's malformed.
+pub fn parse_temp_table(sql: &str) -> Option<Result<CreateTempTable<'_>, String>> {
+    let rest = keyword(sql.trim(), "create")?;
+    let rest = keyword(rest, "temp").or_else(|| keyword(rest, "temporary"))?;
+    let rest = keyword(rest, "table")?;
+    Some(parse_create_temp(rest))
+}
+
+fn parse_create_temp(sql: &str) -> Result<CreateTempTable<'_>, String> {
+    let (name, rest) = identifier(sql).ok_or("expected CREATE TEMP TABLE name AS query")?;
+    let query = keyword(rest, "as").ok_or("expected AS after CREATE TEMP TABLE name")?;
+    Ok(CreateTempTable {
+        name,
+        query: query.trim(),
+    })
+}
+
+fn stored_as_mongodb(sql: &str) -> bool {
+    let words = sql
+        .split_whitespace()
+        .map(|w| w.to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    words
+        .windows(3)
+        .any(|w| w[0] == "stored" && w[1] == "as" && w[2] == "mongodb")
+}
+
+fn parse_create(sql: &str) -> Result<CreateExternalTable<'_>, String> {
+    let (name, rest) = identifier(sql).ok_or("expected table name")?;
+    let (columns, rest) = parenthesised(rest).ok_or("expected (column TYPE, ...)")?;
+    let rest = keyword(rest, "stored").ok_or("expected STORED AS MONGODB")?;
+    let rest = keyword(rest, "as").ok_or("expected STORED AS MONGODB")?;
+    let rest = keyword(rest, "mongodb").ok_or("expected STORED AS MONGODB")?;
+    let rest = keyword(rest, "location").ok_or("expected LOCATION '[db.]collection'")?;
+    let (location, rest) = quoted(rest).ok_or("expected LOCATION '[db.]collection'")?;
+    if !rest.trim().is_empty() {
+        return Err(format!("unexpected {:?} after LOCATION", rest.trim()));
+    }
+
+    // database names can't contain '.', but collection names can
+    let (db, collection) = match location.find('.') {
+        Some(i) => (Some(location[..i].to_owned()), location[i + 1..].to_owned()),
+        None => (None, location),
+    };
+
+    let fields = split_list(columns)
+        .into_iter()
+        .map(column)
+        .collect::<Result<_, _>>()?;
+
+    Ok(CreateExternalTable {
+        name,
+        db,
+        schema: MappedSchema::new(collection, fields),
+    })
+}
+
+// parses "name TYPE ['mongodb=field'] [NOT NULL | NULL]"
+fn column(sql: &str) -> Result<MappedField, String> {
+    let (name, rest) =
+        identifier(sql).ok_or_else(|| format!("expected column name in {:?}", sql))?;
+    let (type_name, rest) =
+        identifier(rest).ok_or_else(|| format!("expected type for column {}", name))?;
+    let data_type = data_type(type_name)?;
+    // ignore any length or precision, as in VARCHAR(255)
+    let rest = parenthesised(rest).map_or(rest, |(_, rest)| rest);
+
+    let (mongodb_field, rest) = match quoted(rest) {
+        Some((option, rest)) => match option.strip_prefix("mongodb=") {
+            Some(field) => (field.to_owned(), rest),
+            None => return Err(format!("unknown column option {:?}", option)),
+        },
+        None => (name.to_owned(), rest),
+    };
+
+    let nullable = if let Some(rest) = keyword(rest, "not") {
+        keyword(rest, "null")
+            .filter(|rest| rest.trim().is_empty())
+            .ok_or_else(|| format!("expected NOT NULL for column {}", name))?;
+        false
+    } else if let Some(rest) = keyword(rest, "null") {
+        if !rest.trim().is_empty() {
+            return Err(format!("unexpected {:?} for column {}", rest.trim(), name));
+        }
+        true
+    } else if rest.trim().is_empty() {
+        true
+    } else {
+        return Err(format!("unexpected {:?} for column {}", rest.trim(), name));
+    };
+
+    Ok(MappedField::new(
+        mongodb_field,
+        Field::new(name, data_type, nullable),
+    ))
+}
+
+pub fn data_type(name: &str) -> Result<DataType, String> {
+    let data_type = match name.to_ascii_lowercase().as_str() {
+        "varchar" | "char" | "text" | "string" => DataType::Utf8,
+        "int" | "integer" => DataType::Int32,
+        "bigint" => DataType::Int64,
+        "double" | "float" | "real" => DataType::Float64,
+        "boolean" | "bool" => DataType::Boolean,
+        "timestamp" => DataType::Timestamp(TimeUnit::Millisecond, None),
+        "date" => DataType::Date32(DateUnit::Day),
+        "bytea" | "binary" | "varbinary" => DataType::Binary,
+        _ => return Err(format!("unsupported type {}", name)),
+    };
+    Ok(data_type)
+}
+
+/// A parsed
+/// `CREATE MATERIALIZED VIEW name AS query [REFRESH EVERY n unit]` statement.
+#[derive(Debug, PartialEq)]
+pub struct CreateMaterializedView<'a> {
+    pub name: &'a str,
+    pub query: &'a str,
+    /// None if only refreshed when created
+    pub refresh: Option<Duration>,
+}
+
+/// Returns None if `sql` isn't a CREATE MATERIALIZED VIEW statement, or an
+/// error if it is but it's malformed.
+pub fn parse_materialized_view(sql: &str) -> Option<Result<CreateMaterializedView<'_>, String>> {
+    let rest = keyword(sql.trim(), "create")?;
+    let rest = keyword(rest, "materialized")?;
+    let rest = keyword(rest, "view")?;
+    Some(parse_create_materialized_view(rest))
+}
+
+/// Returns the view name if `sql` is a `DROP MATERIALIZED VIEW name`
+/// statement, or an error if it's malformed.
+pub fn parse_drop_materialized_view(sql: &str) -> Option<Result<&str, String>> {
+    let rest = keyword(sql.trim(), "drop")?;
+    let rest = keyword(rest, "materialized")?;
+    let rest = keyword(rest, "view")?;
+    Some(match identifier(rest) {
+        Some((name, rest)) if rest.trim().is_empty() => Ok(name),
+        _ => Err("expected DROP MATERIALIZED VIEW name".to_owned()),
+    })
+}
+
+fn parse_create_materialized_view(sql: &str) -> Result<CreateMaterializedView<'_>, String> {
+    let (name, rest) = identifier(sql).ok_or("expected CREATE MATERIALIZED VIEW name AS query")?;
+    let query = keyword(rest, "as").ok_or("expected AS after CREATE MATERIALIZED VIEW name")?;
+
+    // REFRESH EVERY n unit is always the last four words
+    let words = query.split_whitespace().rev().take(4).collect::<Vec<_>>();
+    if words.len() < 4
+        || !words[3].eq_ignore_ascii_case("refresh")
+        || !words[2].eq_ignore_ascii_case("every")
+    {
+        return Ok(CreateMaterializedView {
+            name,
+            query: query.trim(),
+            refresh: None,
+        });
+    }
+    let n = words[1]
+        .parse::<u64>()
+        .map_err(|_| format!("expected REFRESH EVERY n, not {:?}", words[1]))?;
+    let unit = match words[0].to_ascii_lowercase().trim_end_matches('s') {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "expected SECONDS, MINUTES, HOURS, or DAYS, not {:?}",
+                words[0]
+            ))
+        }
+    };
+    if n == 0 {
+        return Err("REFRESH EVERY must be more than 0".to_owned());
+    }
+    // ascii lowercasing keeps byte offsets the same
+    let end = query
+        .to_ascii_lowercase()
+        .rfind("refresh")
+        .expect("REFRESH found above");
+    Ok(CreateMaterializedView {
+        name,
+        query: query[..end].trim(),
+        refresh: Some(Duration::from_secs(n * unit)),
+    })
+}
@@ -0,0 +1,21 @@
+use crate::statements::{identifier, keyword};
+
+/// Returns the table name if `sql` is a `DESCRIBE table`, `DESC table`, or
+/// `SHOW COLUMNS FROM table` statement, or an error if it is but it's
+/// malformed.
+pub fn parse(sql: &str) -> Option<Result<&str, String>> {
+    let sql = sql.trim();
+    let rest = if let Some(rest) = keyword(sql, "describe").or_else(|| keyword(sql, "desc")) {
+        rest
+    } else {
+        let rest = keyword(keyword(sql, "show")?, "columns")?;
+        match keyword(rest, "from").or_else(|| keyword(rest, "in")) {
+            Some(rest) => rest,
+            None => return Some(Err("expected SHOW COLUMNS FROM table".to_owned())),
+        }
+    };
+    Some(match identifier(rest) {
+        Some((name, rest)) if rest.trim().is_empty() => Ok(name),
+        _ => Err("expected a table name".to_owned()),
+    })
+}
@@ -0,0 +1,213 @@
+use std::{
+    fs::{self, File},
+    io::ErrorKind,
+    path::Path,
+    sync::Arc,
+};
+
+use arrow::datatypes::SchemaRef;
+use futures::{
+    stream::{self, StreamExt},
+    TryStreamExt,
+};
+use mongodb::{
+    bson::{doc, Bson, Document},
+    options::FindOptions,
+    Collection,
+};
+use mongodb_arrow::{DocumentsReader, ErrorPolicy, MappedSchema};
+use mongodb_datafusion::datasource::mongodb_projection;
+use parquet::arrow::ArrowWriter;
+
+// records the partitions of a dump, so a resumed dump reads the same ranges
+const MANIFEST: &str = "_manifest.bson";
+const BATCH_SIZE: usize = 8192;
+
+/// How a dump splits up its collection.
+pub struct Dump<'a> {
+    pub dir: &'a Path,
+    /// MongoDB field the partitions are ranges of
+    pub field: String,
+    pub partitions: u32,
+    /// partitions read at once, each with its own cursor
+    pub parallel: usize,
+    pub error_policy: ErrorPolicy,
+}
+
+/// Writes all of `schema`'s collection to a directory of Parquet files, one
+/// per range of `dump.field` plus one for documents in none of the ranges,
+/// read in parallel.
+///
+/// Each file is written under a temporary name and renamed once complete,
+/// so running the same dump again after a failure only reads the
+/// partitions that are missing.
+pub async fn run(
+    collection: Collection,
+    schema: MappedSchema,
+    dump: Dump<'_>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(dump.dir)?;
+    let ranges = ranges(&collection, &dump).await?;
+
+    let schema = Arc::new(schema);
+    let last = ranges.len().saturating_sub(1);
+    let mut filters = ranges
+        .into_iter()
+        .enumerate()
+        .map(|(i, (min, max))| {
+            // $bucketAuto's last bucket includes its max, the others don't
+            let mut range = doc! { "$gte": min };
+            range.insert(if i == last { "$lte" } else { "$lt" }, max);
+            let mut filter = Document::new();
+            filter.insert(dump.field.as_str(), range);
+            filter
+        })
+        .collect::<Vec<_>>();
+    // comparisons only match values of the same type, so documents with the
+    // field missing, null, or of a type other than a range's bounds go in a
+    // last partition of everything the ranges don't match
+    let rest = if filters.is_empty() {
+        Document::new()
+    } else {
+        doc! { "$nor": filters.clone() }
+    };
+    filters.push(rest);
+    let pending = filters
+        .into_iter()
+        .enumerate()
+        .map(|(i, filter)| (dump.dir.join(format!("part-{:05}.parquet", i)), filter))
+        .filter(|(path, _)| !path.exists())
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        eprintln!("{} already complete", dump.dir.display());
+        return Ok(());
+    }
+
+    let total = pending.len();
+    let rows = stream::iter(pending)
+        .map(|(path, filter)| {
+            let collection = collection.clone();
+            let schema = schema.clone();
+            let error_policy = dump.error_policy;
+            async move {
+                let rows = write_partition(&collection, &schema, filter, &path, error_policy)
+                    .await
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                eprintln!("wrote {} rows to {}", rows, path.display());
+                Ok::<_, String>(rows)
+            }
+        })
+        .buffer_unordered(dump.parallel.max(1))
+        .try_fold(0, |total, rows| async move { Ok(total + rows) })
+        .await?;
+    eprintln!("dumped {} rows in {} partitions", rows, total);
+    Ok(())
+}
+
+// the [min, max) ranges of the partition field, the last including its
+// max. Read from the manifest if resuming a dump, otherwise found with
+// $bucketAuto and saved to the manifest
+async fn ranges(
+    collection: &Collection,
+    dump: &Dump<'_>,
+) -> Result<Vec<(Bson, Bson)>, Box<dyn std::error::Error>> {
+    let path = dump.dir.join(MANIFEST);
+    let manifest = match File::open(&path) {
+        Ok(mut file) => Some(Document::from_reader(&mut file)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(manifest) = manifest {
+        if manifest.get_str("collection")? != collection.name()
+            || manifest.get_str("field")? != dump.field
+        {
+            return Err(format!(
+                "{} holds a dump of {} by {}",
+                dump.dir.display(),
+                manifest.get_str("collection")?,
+                manifest.get_str("field")?
+            )
+            .into());
+        }
+        return Ok(manifest
+            .get_array("ranges")?
+            .iter()
+            .filter_map(|range| {
+                let range = range.as_document()?;
+                Some((range.get("min")?.clone(), range.get("max")?.clone()))
+            })
+            .collect());
+    }
+
+    let pipeline = vec![
+        doc! { "$project": { "_id": 0, "key": format!("${}", dump.field) } },
+        doc! { "$bucketAuto": { "groupBy": "$key", "buckets": i64::from(dump.partitions) } },
+    ];
+    let buckets = collection
+        .aggregate(pipeline, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let ranges = buckets
+        .iter()
+        .filter_map(|bucket| {
+            let bounds = bucket.get_document("_id").ok()?;
+            Some((bounds.get("min")?.clone(), bounds.get("max")?.clone()))
+        })
+        .collect::<Vec<_>>();
+
+    let manifest = doc! {
+        "collection": collection.name(),
+        "field": &dump.field,
+        "ranges": ranges
+            .iter()
+            .map(|(min, max)| Bson::Document(doc! { "min": min.clone(), "max": max.clone() }))
+            .collect::<Vec<_>>(),
+    };
+    let mut file = File::create(&path)?;
+    manifest.to_writer(&mut file)?;
+    Ok(ranges)
+}
+
+// writes the documents matching `filter` to a Parquet file at `path`,
+// returning the number of rows
+async fn write_partition(
+    collection: &Collection,
+    schema: &MappedSchema,
+    filter: Document,
+    path: &Path,
+    error_policy: ErrorPolicy,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let options = FindOptions::builder()
+        .projection(Some(mongodb_projection(schema)))
+        .batch_size(Some(BATCH_SIZE as u32))
+        .build();
+    let mut cursor = collection.find(filter, options).await?;
+
+    let tmp = path.with_extension("parquet.tmp");
+    let arrow_schema: SchemaRef = Arc::new(schema.clone().into());
+    let mut writer = ArrowWriter::try_new(File::create(&tmp)?, arrow_schema, None)?;
+    let mut rows = 0;
+    let mut documents = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        let document = cursor.try_next().await?;
+        let done = document.is_none();
+        documents.extend(document);
+        if documents.len() == BATCH_SIZE || (done && !documents.is_empty()) {
+            let batch = DocumentsReader::new_with_error_policy(
+                std::mem::replace(&mut documents, Vec::with_capacity(BATCH_SIZE)),
+                schema.fields().clone(),
+                error_policy,
+            )
+            .into_record_batch()?;
+            rows += batch.num_rows();
+            writer.write(&batch)?;
+        }
+        if done {
+            break;
+        }
+    }
+    writer.close()?;
+    fs::rename(&tmp, path)?;
+    Ok(rows)
+}
@@ -0,0 +1,477 @@
+use std::{
+    any::Any,
+    fmt::{self, Write},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use arrow::{datatypes::SchemaRef, error::Result as ArrowResult, record_batch::RecordBatch};
+use async_trait::async_trait;
+use datafusion::{
+    datasource::TableProvider,
+    error::DataFusionError,
+    execution::context::ExecutionContext,
+    logical_plan::{Expr, LogicalPlan, PlanVisitor},
+    physical_plan::{
+        collect, ExecutionPlan, Partitioning, RecordBatchStream, SendableRecordBatchStream,
+    },
+};
+use futures::Stream;
+use lazy_datafusion::LazyMemTable;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Database,
+};
+use mongodb_datafusion::datasource::MongoDbCollection;
+
+use crate::{
+    output::{paint, GREEN},
+    statements::keyword,
+};
+
+/// A parsed `EXPLAIN [ANALYZE] query` statement.
+#[derive(Debug, PartialEq)]
+pub struct Explain<'a> {
+    pub analyze: bool,
+    pub query: &'a str,
+}
+
+/// Returns None if `sql` isn't an EXPLAIN statement handled here, or an
+/// error if it is but it's malformed. `EXPLAIN VERBOSE` is left to
+/// DataFusion.
+pub fn parse(sql: &str) -> Option<Result<Explain<'_>, String>> {
+    let rest = keyword(sql.trim(), "explain")?;
+    if keyword(rest, "verbose").is_some() {
+        return None;
+    }
+    let (analyze, query) = match keyword(rest, "analyze") {
+        Some(query) => (true, query.trim()),
+        None => (false, rest.trim()),
+    };
+    if query.is_empty() {
+        return Some(Err("expected a query after EXPLAIN".to_owned()));
+    }
+    Some(Ok(Explain { analyze, query }))
+}
+
+/// Renders the logical and physical plans for `explain`, noting which
+/// filters are sent to MongoDB and which are evaluated by DataFusion, and
+/// the command each MongoDB table would run. Nothing is sent to MongoDB
+/// unless it's an ANALYZE.
+///
+/// With ANALYZE the query is run and the physical plan includes the rows
+/// each operator produced and the time spent in it, including its inputs.
+pub async fn run(
+    context: &ExecutionContext,
+    explain: &Explain<'_>,
+    is_mongodb: impl Fn(&str) -> bool,
+    color: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let plan = context.create_logical_plan(explain.query)?;
+    let plan = context.optimize(&plan)?;
+    let physical = context.create_physical_plan(&plan)?;
+
+    let mut out = String::new();
+    writeln!(out, "Logical plan:")?;
+    for line in plan.display_indent().to_string().lines() {
+        writeln!(out, "  {}", line)?;
+    }
+
+    let mut pushdown = Pushdown {
+        is_mongodb: &is_mongodb,
+        pushed: Vec::new(),
+        local: Vec::new(),
+        queries: Vec::new(),
+    };
+    plan.accept(&mut pushdown)?;
+    if !pushdown.pushed.is_empty() {
+        writeln!(out, "Sent to MongoDB:")?;
+        for line in &pushdown.pushed {
+            writeln!(out, "  {}", paint(color, GREEN, line))?;
+        }
+    }
+    if !pushdown.local.is_empty() {
+        writeln!(out, "Evaluated in DataFusion:")?;
+        for line in &pushdown.local {
+            writeln!(out, "  {}", line)?;
+        }
+    }
+
+    if !pushdown.queries.is_empty() {
+        writeln!(out, "MongoDB queries:")?;
+        for (table, query) in &pushdown.queries {
+            writeln!(out, "  {}: {}", table, query)?;
+        }
+    }
+
+    writeln!(out, "Physical plan:")?;
+    if explain.analyze {
+        let (physical, node) = instrument(physical)?;
+        let start = Instant::now();
+        collect(physical).await?;
+        let elapsed = start.elapsed();
+        node.write(&mut out, 1)?;
+        writeln!(out, "Total time: {:.3}ms", millis(elapsed))?;
+    } else {
+        write_physical(&mut out, &physical, 1)?;
+    }
+    Ok(out)
+}
+
+// collects the filters and limits in a plan, split by where they're applied
+struct Pushdown<'a> {
+    is_mongodb: &'a dyn Fn(&str) -> bool,
+    pushed: Vec<String>,
+    local: Vec<String>,
+    queries: Vec<(String, Document)>,
+}
+
+impl PlanVisitor for Pushdown<'_> {
+    type Error = DataFusionError;
+
+    fn pre_visit(&mut self, plan: &LogicalPlan) -> Result<bool, Self::Error> {
+        match plan {
+            LogicalPlan::TableScan {
+                table_name,
+                source,
+                projection,
+                filters,
+                ..
+            } if (self.is_mongodb)(table_name) => {
+                for filter in filters {
+                    self.pushed
+                        .push(format!("{}: filter {:?}", table_name, filter));
+                }
+                if let Some(query) = mongodb_query(source.as_ref(), projection, filters) {
+                    self.queries.push((table_name.clone(), query?));
+                }
+            }
+            // MongoDB filters can match extra documents, so DataFusion
+            // always reapplies them
+            LogicalPlan::Filter { predicate, .. } => {
+                self.local.push(format!("filter {:?}", predicate))
+            }
+            LogicalPlan::Limit { n, .. } => self.local.push(format!("limit {}", n)),
+            _ => (),
+        }
+        Ok(true)
+    }
+}
+
+/// The command each MongoDB table scanned by `query` would send, by table.
+pub fn mongodb_queries(
+    context: &ExecutionContext,
+    query: &str,
+    is_mongodb: impl Fn(&str) -> bool,
+) -> Result<Vec<(String, Document)>, DataFusionError> {
+    let plan = context.create_logical_plan(query)?;
+    let plan = context.optimize(&plan)?;
+    let mut pushdown = Pushdown {
+        is_mongodb: &is_mongodb,
+        pushed: Vec::new(),
+        local: Vec::new(),
+        queries: Vec::new(),
+    };
+    plan.accept(&mut pushdown)?;
+    Ok(pushdown.queries)
+}
+
+/// The work MongoDB does to run a command, from its executionStats.
+#[derive(Debug)]
+pub struct ServerWork {
+    pub keys_examined: i64,
+    pub docs_examined: i64,
+    pub returned: i64,
+    /// indexes scanned, empty for a collection scan
+    pub indexes: Vec<String>,
+}
+
+impl fmt::Display for ServerWork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} documents and {} index keys examined, {} returned, ",
+            self.docs_examined, self.keys_examined, self.returned
+        )?;
+        if self.indexes.is_empty() {
+            write!(f, "no index used")
+        } else {
+            write!(f, "using {}", self.indexes.join(", "))
+        }
+    }
+}
+
+/// Runs `command` with explain, which runs it again on the server, and
+/// returns the work it took.
+pub async fn server_work(
+    db: &Database,
+    command: Document,
+) -> Result<ServerWork, Box<dyn std::error::Error>> {
+    let explained = db
+        .run_command(
+            doc! { "explain": command, "verbosity": "executionStats" },
+            None,
+        )
+        .await?;
+    // aggregates nest the stats under their first stage's $cursor
+    let stats =
+        find_document(&explained, "executionStats").ok_or("no executionStats in explain output")?;
+    let mut indexes = Vec::new();
+    if let Some(plan) = find_document(&explained, "winningPlan") {
+        index_names(plan, &mut indexes);
+    }
+    Ok(ServerWork {
+        keys_examined: count(stats, "totalKeysExamined"),
+        docs_examined: count(stats, "totalDocsExamined"),
+        returned: count(stats, "nReturned"),
+        indexes,
+    })
+}
+
+// the first document under `key`, searching depth first
+fn find_document<'a>(document: &'a Document, key: &str) -> Option<&'a Document> {
+    if let Ok(found) = document.get_document(key) {
+        return Some(found);
+    }
+    document.values().find_map(|value| find_in(value, key))
+}
+
+fn find_in<'a>(value: &'a Bson, key: &str) -> Option<&'a Document> {
+    match value {
+        Bson::Document(document) => find_document(document, key),
+        Bson::Array(array) => array.iter().find_map(|v| find_in(v, key)),
+        _ => None,
+    }
+}
+
+// the indexes of every IXSCAN stage in a plan
+fn index_names(plan: &Document, names: &mut Vec<String>) {
+    if let Ok(name) = plan.get_str("indexName") {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_owned());
+        }
+    }
+    for value in plan.values() {
+        match value {
+            Bson::Document(stage) => index_names(stage, names),
+            Bson::Array(stages) => {
+                for stage in stages {
+                    if let Bson::Document(stage) = stage {
+                        index_names(stage, names);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn count(stats: &Document, key: &str) -> i64 {
+    match stats.get(key) {
+        Some(Bson::Int32(n)) => i64::from(*n),
+        Some(Bson::Int64(n)) => *n,
+        Some(Bson::Double(n)) => *n as i64,
+        _ => 0,
+    }
+}
+
+// the command a scan of a MongoDB table would send, looking through the
+// cache MongoDB tables are normally wrapped in
+fn mongodb_query(
+    source: &dyn TableProvider,
+    projection: &Option<Vec<usize>>,
+    filters: &[Expr],
+) -> Option<Result<Document, DataFusionError>> {
+    let source = match source.as_any().downcast_ref::<LazyMemTable>() {
+        Some(table) => table.provider().as_any(),
+        None => source.as_any(),
+    };
+    let collection = source.downcast_ref::<MongoDbCollection>()?;
+    Some(collection.query(projection, filters))
+}
+
+fn write_physical(out: &mut String, plan: &Arc<dyn ExecutionPlan>, depth: usize) -> fmt::Result {
+    writeln!(
+        out,
+        "{:width$}{}",
+        "",
+        name(plan.as_ref()),
+        width = depth * 2
+    )?;
+    for child in plan.children() {
+        write_physical(out, &child, depth + 1)?;
+    }
+    Ok(())
+}
+
+// DataFusion doesn't give operators a display name, so take it from the
+// derived Debug output, e.g. `ProjectionExec { .. }`
+fn name(plan: &dyn ExecutionPlan) -> String {
+    let debug = format!("{:?}", plan);
+    debug
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// an operator in an instrumented plan, for reporting after it's run
+struct Node {
+    name: String,
+    metrics: Arc<Metrics>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn write(&self, out: &mut String, depth: usize) -> fmt::Result {
+        writeln!(
+            out,
+            "{:width$}{} (rows={}, time={:.3}ms)",
+            "",
+            self.name,
+            self.metrics.rows.load(Ordering::Relaxed),
+            millis(Duration::from_nanos(
+                self.metrics.nanos.load(Ordering::Relaxed)
+            )),
+            width = depth * 2,
+        )?;
+        for child in &self.children {
+            child.write(out, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    rows: AtomicUsize,
+    nanos: AtomicU64,
+}
+
+impl Metrics {
+    fn add_time(&self, elapsed: Duration) {
+        self.nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+// wraps every operator in `plan` to record its output rows and run time
+fn instrument(
+    plan: Arc<dyn ExecutionPlan>,
+) -> Result<(Arc<dyn ExecutionPlan>, Node), DataFusionError> {
+    let children = plan.children();
+    let (plan, children) = if children.is_empty() {
+        (plan, Vec::new())
+    } else {
+        let (wrapped, nodes): (Vec<_>, Vec<_>) = children
+            .into_iter()
+            .map(instrument)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .unzip();
+        (plan.with_new_children(wrapped)?, nodes)
+    };
+    let metrics = Arc::new(Metrics::default());
+    let node = Node {
+        name: name(plan.as_ref()),
+        metrics: metrics.clone(),
+        children,
+    };
+    Ok((
+        Arc::new(MeasuredExec {
+            input: plan,
+            metrics,
+        }),
+        node,
+    ))
+}
+
+#[derive(Debug)]
+struct MeasuredExec {
+    input: Arc<dyn ExecutionPlan>,
+    metrics: Arc<Metrics>,
+}
+
+#[async_trait]
+impl ExecutionPlan for MeasuredExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        match children.as_slice() {
+            [input] => Ok(Arc::new(MeasuredExec {
+                input: input.clone(),
+                metrics: self.metrics.clone(),
+            })),
+            _ => Err(DataFusionError::Internal(
+                "MeasuredExec wrong number of children".to_owned(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<SendableRecordBatchStream, DataFusionError> {
+        // some operators do all their work before returning a stream
+        let start = Instant::now();
+        let input = self.input.execute(partition).await;
+        self.metrics.add_time(start.elapsed());
+        Ok(Box::pin(MeasuredStream {
+            input: input?,
+            metrics: self.metrics.clone(),
+        }))
+    }
+}
+
+struct MeasuredStream {
+    input: SendableRecordBatchStream,
+    metrics: Arc<Metrics>,
+}
+
+impl Stream for MeasuredStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let start = Instant::now();
+        let poll = self.input.as_mut().poll_next(cx);
+        self.metrics.add_time(start.elapsed());
+        if let Poll::Ready(Some(Ok(ref batch))) = poll {
+            self.metrics
+                .rows
+                .fetch_add(batch.num_rows(), Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+impl RecordBatchStream for MeasuredStream {
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+}
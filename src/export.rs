@@ -0,0 +1,147 @@
+use std::{
+    fs::{self, File},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use arrow::{
+    datatypes::{DataType, TimeUnit},
+    util::display::array_value_to_string,
+};
+use chrono::{DateTime, NaiveDateTime};
+use datafusion::{
+    datasource::MemTable,
+    execution::context::ExecutionContext,
+    logical_plan::{col, max, Expr},
+    scalar::ScalarValue,
+};
+use mongodb::Collection;
+use mongodb_arrow::MappedSchema;
+use mongodb_datafusion::datasource::MongoDbCollection;
+
+use crate::{copy::format_for_path, output};
+
+/// Where the rows exported are written, and how the new ones are found.
+pub struct Export<'a> {
+    pub to: &'a Path,
+    /// column the rows are ordered by, e.g. an insertion timestamp
+    pub column: &'a str,
+    /// only export rows with `column` greater than this, rather than the
+    /// value saved by the last export
+    pub since: Option<String>,
+    /// where the watermark is saved, defaults to `to` with a `.watermark`
+    /// extension
+    pub state: Option<PathBuf>,
+}
+
+/// Writes the rows of `schema`'s collection added since the last export,
+/// those with `export.column` greater than the saved watermark, then saves
+/// the greatest value exported as the watermark for the next run. The
+/// watermark filter is sent to MongoDB, so only new documents are read.
+pub async fn run(
+    collection: Collection,
+    schema: MappedSchema,
+    export: Export<'_>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let field = schema
+        .fields()
+        .iter()
+        .find(|f| f.name() == export.column)
+        .ok_or_else(|| format!("no column {} in {}", export.column, collection.name()))?;
+    let data_type = field.data_type().clone();
+    if !field.transforms().is_empty() {
+        return Err(format!(
+            "{} can't be used as a watermark, as filters on transformed columns can't be sent to MongoDB",
+            export.column
+        )
+        .into());
+    }
+    if !mongodb_datafusion::supports_pushdown(&data_type) {
+        return Err(format!(
+            "{} can't be used as a watermark, as filters on {} columns can't be sent to MongoDB",
+            export.column, data_type
+        )
+        .into());
+    }
+
+    let state = export
+        .state
+        .unwrap_or_else(|| export.to.with_extension("watermark"));
+    let since = match export.since {
+        Some(since) => Some(since),
+        None => match fs::read_to_string(&state) {
+            Ok(saved) => Some(saved.trim().to_owned()),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(format!("can't read {}: {}", state.display(), e).into()),
+        },
+    };
+
+    let mut context = ExecutionContext::new();
+    let mut df = context.read_table(Arc::new(MongoDbCollection::new(collection, schema)))?;
+    if let Some(ref since) = since {
+        df = df.filter(col(export.column).gt(literal(&data_type, since)?))?;
+    }
+    let schema = df.to_logical_plan().schema().clone();
+    let batches = df.collect().await?;
+    let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+
+    output::write_batches(
+        format_for_path(&export.to.to_string_lossy()),
+        &schema,
+        &batches,
+        File::create(export.to)?,
+    )?;
+
+    if rows > 0 {
+        let schema = batches[0].schema();
+        let exported = MemTable::try_new(schema, vec![batches])?;
+        let watermark = context
+            .read_table(Arc::new(exported))?
+            .aggregate(vec![], vec![max(col(export.column))])?
+            .collect()
+            .await?;
+        let watermark = array_value_to_string(watermark[0].column(0), 0)?;
+        fs::write(&state, format!("{}\n", watermark))
+            .map_err(|e| format!("can't write {}: {}", state.display(), e))?;
+        eprintln!(
+            "exported {} rows to {}, up to {}",
+            rows,
+            export.to.display(),
+            watermark
+        );
+    } else {
+        eprintln!("no rows since {}", since.as_deref().unwrap_or("the start"));
+    }
+    Ok(())
+}
+
+// the watermark as a value comparable with columns of `data_type`, the
+// literal types MongoDB filters can be made from
+fn literal(data_type: &DataType, value: &str) -> Result<Expr, Box<dyn std::error::Error>> {
+    let invalid = |e: &dyn std::fmt::Display| format!("invalid watermark {:?}: {}", value, e);
+    let scalar = match data_type {
+        DataType::Int32 | DataType::Int64 => {
+            ScalarValue::Int64(Some(value.parse().map_err(|e| invalid(&e))?))
+        }
+        DataType::Float64 => ScalarValue::Float64(Some(value.parse().map_err(|e| invalid(&e))?)),
+        DataType::Boolean => ScalarValue::Boolean(Some(value.parse().map_err(|e| invalid(&e))?)),
+        DataType::Timestamp(TimeUnit::Microsecond, _) => ScalarValue::TimestampMicrosecond(Some(
+            timestamp_nanos(value).map_err(|e| invalid(&e))? / 1_000,
+        )),
+        DataType::Timestamp(_, _) => {
+            ScalarValue::TimestampNanosecond(Some(timestamp_nanos(value).map_err(|e| invalid(&e))?))
+        }
+        _ => ScalarValue::Utf8(Some(value.to_owned())),
+    };
+    Ok(Expr::Literal(scalar))
+}
+
+// accepts RFC 3339, or the UTC date and time saved as a watermark
+fn timestamp_nanos(value: &str) -> Result<i64, chrono::ParseError> {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(datetime) => Ok(datetime.timestamp_nanos()),
+        Err(_) => NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+            .map(|d| d.timestamp_nanos()),
+    }
+}
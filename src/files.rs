@@ -0,0 +1,74 @@
+use std::{fs::File, io::BufReader, path::Path, str::FromStr};
+
+use arrow::json;
+use datafusion::datasource::{
+    csv::{CsvFile, CsvReadOptions},
+    parquet::ParquetTable,
+    MemTable, TableProvider,
+};
+
+const JSON_INFER_RECORDS: usize = 1000;
+
+/// A local file to register as a table, parsed from `PATH [AS NAME]`. The
+/// name defaults to the file name without its extension.
+#[derive(Debug)]
+pub struct FileTable {
+    pub path: String,
+    pub name: String,
+}
+
+impl FromStr for FileTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = s.split_whitespace().collect::<Vec<_>>();
+        let (path, name) = match words.as_slice() {
+            [path] => {
+                let name = Path::new(path)
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .ok_or_else(|| format!("can't get table name from {:?}", path))?;
+                (*path, name)
+            }
+            [path, as_, name] if as_.eq_ignore_ascii_case("as") => (*path, *name),
+            _ => return Err(format!("expected PATH [AS NAME], got {:?}", s)),
+        };
+        Ok(FileTable {
+            path: path.to_owned(),
+            name: name.to_owned(),
+        })
+    }
+}
+
+impl FileTable {
+    pub fn parquet(
+        &self,
+    ) -> Result<Box<dyn TableProvider + Send + Sync>, Box<dyn std::error::Error>> {
+        Ok(Box::new(ParquetTable::try_new(
+            &self.path,
+            num_cpus::get(),
+        )?))
+    }
+
+    pub fn csv(&self) -> Result<Box<dyn TableProvider + Send + Sync>, Box<dyn std::error::Error>> {
+        Ok(Box::new(CsvFile::try_new(
+            &self.path,
+            CsvReadOptions::new(),
+        )?))
+    }
+
+    // DataFusion has no line delimited JSON provider, so the file is read in
+    // to memory
+    pub fn json(&self) -> Result<Box<dyn TableProvider + Send + Sync>, Box<dyn std::error::Error>> {
+        let file = BufReader::new(File::open(&self.path)?);
+        let mut reader = json::ReaderBuilder::new()
+            .infer_schema(Some(JSON_INFER_RECORDS))
+            .build(file)?;
+        let schema = reader.schema();
+        let mut batches = Vec::new();
+        while let Some(batch) = reader.next()? {
+            batches.push(batch);
+        }
+        Ok(Box::new(MemTable::try_new(schema, vec![batches])?))
+    }
+}
@@ -0,0 +1,52 @@
+use std::{fs::OpenOptions, path::Path};
+
+use futures::TryStreamExt;
+use mongodb::{bson::doc, Database};
+
+use crate::schema_dir::write_schema;
+
+pub async fn run(
+    database: &Database,
+    schema_dir: &Path,
+    collection: &str,
+    sample: i64,
+    yaml: bool,
+    force: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let documents = database
+        .collection(collection)
+        .aggregate(vec![doc! {"$sample": {"size": sample}}], None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    if documents.is_empty() {
+        return Err(format!("no documents found in {}", collection).into());
+    }
+
+    let schema = mongodb_arrow::infer_schema(collection.to_owned(), &documents);
+
+    let path = schema_dir.join(format!(
+        "{}.{}",
+        collection,
+        if yaml { "yaml" } else { "json" }
+    ));
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let mut file = options
+        .open(&path)
+        .map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    write_schema(&mut file, &schema, yaml)?;
+
+    eprintln!(
+        "wrote {} with {} fields from {} documents",
+        path.display(),
+        schema.fields().len(),
+        documents.len()
+    );
+    Ok(())
+}
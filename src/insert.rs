@@ -0,0 +1,41 @@
+use crate::statements::{identifier, keyword, parenthesised, split_list};
+
+/// A parsed `INSERT INTO table [(column, ...)] query` statement.
+#[derive(Debug, PartialEq)]
+pub struct InsertInto<'a> {
+    pub table: &'a str,
+    /// the columns the query's columns are written to, all of the table's
+    /// columns in order if None
+    pub columns: Option<Vec<&'a str>>,
+    pub query: &'a str,
+}
+
+/// Returns None if `sql` isn't an INSERT statement, or an error if it is but
+/// it's malformed.
+pub fn parse(sql: &str) -> Option<Result<InsertInto<'_>, String>> {
+    let rest = keyword(sql.trim(), "insert")?;
+    Some(parse_insert(rest))
+}
+
+fn parse_insert(sql: &str) -> Result<InsertInto<'_>, String> {
+    let rest = keyword(sql, "into").ok_or("expected INSERT INTO table query")?;
+    let (table, rest) = identifier(rest).ok_or("expected table name after INSERT INTO")?;
+
+    // a parenthesised query would also start with (, so only treat it as a
+    // column list if it isn't followed by the end of the statement
+    let (columns, query) = match parenthesised(rest) {
+        Some((columns, query)) if !query.trim().is_empty() => {
+            (Some(split_list(columns)), query.trim())
+        }
+        _ => (None, rest.trim()),
+    };
+    if query.is_empty() {
+        return Err("expected a query after INSERT INTO table".to_owned());
+    }
+
+    Ok(InsertInto {
+        table,
+        columns,
+        query,
+    })
+}
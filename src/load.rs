@@ -0,0 +1,135 @@
+use std::{path::Path, sync::Arc};
+
+use datafusion::{datasource::TableProvider, execution::context::ExecutionContext};
+use futures::stream::{self, StreamExt};
+use mongodb::{
+    bson::Document,
+    options::{InsertManyOptions, ReplaceOptions},
+    Collection,
+};
+use mongodb_arrow::{BsonGetNested, MappedField, MappedSchema};
+
+use crate::files::FileTable;
+
+// replacements sent at once when upserting unordered
+const MAX_CONCURRENT_UPSERTS: usize = 16;
+
+/// How loaded rows are written.
+pub struct Load<'a> {
+    /// stop at the first document that fails to write, otherwise carry on
+    /// and report the failures at the end
+    pub ordered: bool,
+    /// MongoDB fields identifying a document, matching documents are
+    /// replaced rather than a new one inserted
+    pub upsert_key: &'a [String],
+}
+
+/// Reads each of `paths`, Parquet, CSV, or line delimited JSON by their
+/// extension, and writes the rows to `collection`. Columns are written to
+/// the MongoDB field given by `schema` if they're in it, otherwise to a
+/// field of the same name.
+pub async fn run(
+    collection: Collection,
+    schema: Option<MappedSchema>,
+    paths: &[String],
+    load: Load<'_>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut written = 0;
+    for path in paths {
+        let table = read(path)?;
+        let fields = table
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| {
+                let mongodb_field = schema
+                    .as_ref()
+                    .and_then(|s| s.fields().iter().find(|f| f.name() == field.name()))
+                    .map_or(field.name().as_str(), |f| f.mongodb_field());
+                MappedField::new(mongodb_field.to_owned(), field.clone())
+            })
+            .collect::<Vec<_>>();
+
+        let mut context = ExecutionContext::new();
+        let batches = context.read_table(Arc::from(table))?.collect().await?;
+        let mut documents = Vec::new();
+        for batch in &batches {
+            documents.extend(mongodb_arrow::record_batch_to_documents(batch, &fields)?);
+        }
+        let count = documents.len();
+
+        if load.upsert_key.is_empty() {
+            let options = InsertManyOptions::builder()
+                .ordered(Some(load.ordered))
+                .build();
+            if !documents.is_empty() {
+                collection.insert_many(documents, options).await?;
+            }
+        } else {
+            upsert(&collection, documents, &load).await?;
+        }
+        eprintln!("loaded {} rows from {}", count, path);
+        written += count;
+    }
+    if paths.len() > 1 {
+        eprintln!("loaded {} rows in total", written);
+    }
+    Ok(())
+}
+
+fn read(path: &str) -> Result<Box<dyn TableProvider + Send + Sync>, Box<dyn std::error::Error>> {
+    let file = path.parse::<FileTable>()?;
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("parquet") => file.parquet(),
+        Some("csv") => file.csv(),
+        Some("json") | Some("jsonl") | Some("ndjson") => file.json(),
+        _ => Err(format!(
+            "can't tell the format of {}, expected .parquet, .csv, or .json",
+            path
+        )
+        .into()),
+    }
+}
+
+// replaces the document matching each of `documents` on the upsert key,
+// inserting it if there's no match
+async fn upsert(
+    collection: &Collection,
+    documents: Vec<Document>,
+    load: &Load<'_>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let options = ReplaceOptions::builder().upsert(Some(true)).build();
+    let replacements = documents
+        .into_iter()
+        .map(|document| {
+            let mut filter = Document::new();
+            for key in load.upsert_key {
+                let value = document
+                    .get_nested(key)
+                    .map_err(|_| format!("row without upsert key {}: {}", key, document))?;
+                filter.insert(key.as_str(), value.clone());
+            }
+            Ok((filter, document))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if load.ordered {
+        for (filter, document) in replacements {
+            collection
+                .replace_one(filter, document, options.clone())
+                .await?;
+        }
+        return Ok(());
+    }
+
+    let failures = stream::iter(replacements)
+        .map(|(filter, document)| collection.replace_one(filter, document, options.clone()))
+        .buffer_unordered(MAX_CONCURRENT_UPSERTS)
+        .filter_map(|result| async move { result.err() })
+        .collect::<Vec<_>>()
+        .await;
+    if let Some(first) = failures.first() {
+        return Err(format!("{} rows failed to write, first: {}", failures.len(), first).into());
+    }
+    Ok(())
+}
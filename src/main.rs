@@ -1,115 +1,737 @@
+mod audit;
+mod completion;
+mod config;
+mod copy;
+mod ddl;
+mod describe;
+mod dump;
+mod explain;
+mod export;
+mod files;
+mod infer_schema;
+mod insert;
+mod load;
+mod mapping;
+mod output;
+mod plugin;
+mod prepare;
+mod progress;
+mod query_log;
+mod repl;
+mod result_cache;
+mod schema_dir;
+mod session;
+mod set;
+mod statements;
+mod transcript;
+mod udf;
+
 use std::{
-    fs::File,
-    io::BufReader,
-    path::{Path, PathBuf},
+    env, fmt,
+    io::{self, Read},
+    path::PathBuf,
+    process,
+    sync::Arc,
+    time::Duration,
 };
 
-use arrow::{datatypes::Schema, record_batch::RecordBatch};
 use datafusion::execution::context::ExecutionContext;
-use lazy_datafusion::LazyMemTable;
-use mongodb_arrow::{MappedField, MappedSchema};
-use mongodb_datafusion::datasource::MongoDbCollection;
-use rustyline::{error::ReadlineError, Editor};
+use futures::stream::{self, StreamExt};
+use lazy_datafusion::MemoryLimit;
+use mongodb::{
+    bson::Document,
+    options::{AuthMechanism, ClientOptions, Tls, TlsOptions},
+};
+use mongodb_arrow::{ErrorPolicy, MappedSchema};
+use mongodb_datafusion::throttle::Throttle;
 use structopt::StructOpt;
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    config::Config,
+    files::FileTable,
+    output::Format,
+    query_log::QueryLog,
+    result_cache::ResultCache,
+    schema_dir::{self, SchemaDir},
+    session::Session,
+};
 
 #[derive(StructOpt, Debug)]
 pub struct Opts {
-    /// MongoDB connection string
-    #[structopt(default_value = "mongodb://localhost:27017", value_name = "URL")]
-    pub mongodb: String,
-    /// MongoDB database
-    #[structopt(long, default_value = "test", value_name = "NAME")]
-    pub db: String,
-    /// Schmea directory
-    #[structopt(short, long, default_value = "schema", value_name = "DIR")]
-    pub schema: PathBuf,
+    /// MongoDB connection string, defaults to that of the --profile given,
+    /// the BISHOP_MONGODB or MONGODB_URI environment variables, the default
+    /// profile's, then mongodb://localhost:27017
+    #[structopt(value_name = "URL")]
+    pub mongodb: Option<String>,
+    /// MongoDB database [default: test]
+    #[structopt(long, value_name = "NAME")]
+    pub db: Option<String>,
+    /// Schmea directory [default: schema]
+    #[structopt(short, long, value_name = "DIR")]
+    pub schema: Option<PathBuf>,
+    /// Read schemas from the MongoDB collection NAME rather than the schema
+    /// directory, publishing changes back with \publish
+    #[structopt(long, value_name = "NAME")]
+    pub schema_collection: Option<String>,
+    /// Profile from ~/.config/bishop/config.toml to take settings from
+    #[structopt(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Reload cached tables every SECS seconds
+    #[structopt(long, value_name = "SECS")]
+    pub refresh: Option<u64>,
+    /// Reuse the results of a query run again within SECS seconds, unless
+    /// its tables have been reloaded
+    #[structopt(long, value_name = "SECS")]
+    pub result_cache: Option<u64>,
+    /// Fail queries that load more than MB megabytes from MongoDB, rather
+    /// than running out of memory
+    #[structopt(long, value_name = "MB")]
+    pub max_memory: Option<usize>,
+    /// Read a random sample of at most N documents from each MongoDB table,
+    /// for quickly trying out queries on large collections
+    #[structopt(long, value_name = "N")]
+    pub sample: Option<i64>,
+    /// Also register the schema directory's tables from another cluster,
+    /// given as "NAME=URL", with the tables named NAME.collection
+    #[structopt(long, value_name = "NAME=URL", number_of_values = 1)]
+    pub attach: Vec<String>,
+    /// Run SQL and exit, rather than starting an interactive session
+    #[structopt(short = "c", long = "command", value_name = "SQL")]
+    pub command: Option<String>,
+    /// Output format: table, csv, json, jsonl, parquet, arrow, markdown, or html
+    /// [default: table]
+    #[structopt(long, value_name = "FORMAT")]
+    pub format: Option<Format>,
+    /// Register a local Parquet file as a table, given as "PATH [AS NAME]"
+    #[structopt(long, value_name = "PATH", number_of_values = 1)]
+    pub parquet: Vec<FileTable>,
+    /// Register a local CSV file as a table, given as "PATH [AS NAME]"
+    #[structopt(long, value_name = "PATH", number_of_values = 1)]
+    pub csv: Vec<FileTable>,
+    /// Register a local line delimited JSON file as a table, given as
+    /// "PATH [AS NAME]"
+    #[structopt(long, value_name = "PATH", number_of_values = 1)]
+    pub json: Vec<FileTable>,
+    /// Wait up to SECS seconds for MongoDB to respond before starting
+    #[structopt(long, value_name = "SECS")]
+    pub wait: Option<u64>,
+    /// CA certificates to verify the server against, as a PEM file
+    #[structopt(long, value_name = "PATH")]
+    pub tls_ca_file: Option<PathBuf>,
+    /// Client certificate and private key, together in one PEM file
+    #[structopt(long, value_name = "PATH")]
+    pub tls_certificate_key_file: Option<PathBuf>,
+    /// Don't verify the server's certificate, this includes its hostname as
+    /// the driver can't check one without the other
+    #[structopt(long)]
+    pub tls_allow_invalid_certificates: bool,
+    /// Authentication mechanism: SCRAM-SHA-1, SCRAM-SHA-256, MONGODB-X509,
+    /// MONGODB-AWS, or PLAIN
+    #[structopt(long, value_name = "MECHANISM")]
+    pub auth_mechanism: Option<String>,
+    /// Database to authenticate against
+    #[structopt(long, value_name = "DB")]
+    pub auth_source: Option<String>,
+    /// Authentication mechanism property, given as "KEY:VALUE", such as
+    /// "AWS_SESSION_TOKEN:..."
+    #[structopt(long, value_name = "PROPERTY", number_of_values = 1)]
+    pub auth_mechanism_property: Vec<String>,
+    /// Ask for the MongoDB password at startup, rather than including it in
+    /// the connection string
+    #[structopt(long)]
+    pub password_prompt: bool,
+    /// After each query, report the documents MongoDB examined and the
+    /// indexes it used, found by running the query's commands with explain
+    #[structopt(long)]
+    pub server_stats: bool,
+    /// Don't print row counts, timings, or statement status messages
+    #[structopt(short, long)]
+    pub quiet: bool,
+    /// Reject statements that write data, such as INSERT
+    #[structopt(long)]
+    pub readonly: bool,
+    /// Print the plan for each statement, including the MongoDB queries it
+    /// would run, without running anything
+    #[structopt(long)]
+    pub dry_run: bool,
+    /// Don't highlight output with colour
+    #[structopt(long)]
+    pub no_color: bool,
+    /// Log more detail, repeat for more still (-vv, -vvv)
+    #[structopt(short, long, parse(from_occurrences))]
+    pub verbose: u8,
+    /// Log filter, such as "debug" or "lazy_datafusion=trace", overrides -v
+    /// and RUST_LOG
+    #[structopt(long, value_name = "FILTER")]
+    pub log_level: Option<String>,
+    /// Append each executed statement, with its duration, row count, and
+    /// any error, to PATH as JSON Lines
+    #[structopt(long, value_name = "PATH")]
+    pub query_log: Option<PathBuf>,
+    /// Load SQL functions from a plugin, a dynamic library exporting
+    /// bishop_plugin_v1
+    #[structopt(long, value_name = "PATH", number_of_values = 1)]
+    pub plugin: Vec<PathBuf>,
+    /// Check the schema directory for changes before each prompt
+    #[structopt(long)]
+    pub watch: bool,
+    #[structopt(subcommand)]
+    pub subcommand: Option<Subcommand>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Subcommand {
+    /// Sample a collection and write a schema file for it to the schema
+    /// directory
+    InferSchema {
+        /// MongoDB collection
+        collection: String,
+        /// Number of documents to sample
+        #[structopt(long, default_value = "1000", value_name = "N")]
+        sample: i64,
+        /// Write YAML rather than JSON
+        #[structopt(long)]
+        yaml: bool,
+        /// Overwrite an existing schema file
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Report the BSON types found in each of a table's fields, and how
+    /// many documents would fail to convert with its current schema
+    Audit {
+        /// Table, as named by its MongoDB collection
+        table: String,
+        /// Number of documents to sample
+        #[structopt(long, default_value = "1000", value_name = "N")]
+        sample: i64,
+        /// Read every document rather than a sample
+        #[structopt(long)]
+        all: bool,
+    },
+    /// Write all of a table to a directory of Parquet files, each holding a
+    /// range of one column and read in parallel. Running it again after a
+    /// failure writes just the missing files
+    Dump {
+        /// Table, as named by its MongoDB collection
+        table: String,
+        /// Directory to write
+        #[structopt(long, value_name = "DIR")]
+        to: PathBuf,
+        /// Column the files are ranges of [default: the _id field]
+        #[structopt(long, value_name = "NAME")]
+        by: Option<String>,
+        /// Number of files to split the table in to
+        #[structopt(long, default_value = "16", value_name = "N")]
+        partitions: u32,
+        /// Number of files written at once
+        #[structopt(long, default_value = "4", value_name = "N")]
+        parallel: usize,
+        /// What to do with values that don't match the schema: error or null
+        #[structopt(long, default_value = "error", value_name = "POLICY")]
+        error_policy: ErrorPolicy,
+    },
+    /// Write the rows of Parquet, CSV, or line delimited JSON files to a
+    /// collection, with columns mapped by its schema if it has one
+    Load {
+        /// MongoDB collection
+        collection: String,
+        /// Files to read, with the format taken from their extension
+        #[structopt(required = true, value_name = "FILE")]
+        files: Vec<String>,
+        /// Keep writing after a row fails, reporting failures at the end
+        #[structopt(long)]
+        unordered: bool,
+        /// Replace the document with the same value for FIELD rather than
+        /// inserting, may be given more than once for a compound key
+        #[structopt(long, value_name = "FIELD", number_of_values = 1)]
+        upsert_key: Vec<String>,
+    },
+    /// Write a table's rows added since the last export to a file, found by
+    /// a watermark column such as an insertion timestamp
+    Export {
+        /// Table, as named by its MongoDB collection
+        table: String,
+        /// File to write, with the format taken from its extension
+        #[structopt(long, value_name = "PATH")]
+        to: PathBuf,
+        /// Column compared against the watermark
+        #[structopt(long, value_name = "NAME")]
+        column: String,
+        /// Export rows with the column greater than VALUE, rather than the
+        /// watermark saved by the last export
+        #[structopt(long, value_name = "VALUE")]
+        since: Option<String>,
+        /// Where the watermark is saved [default: PATH.watermark]
+        #[structopt(long, value_name = "FILE")]
+        state: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() {
     let opts = Opts::from_args();
 
-    let mongodb_opts = mongodb::options::ClientOptions::parse(&opts.mongodb).await?;
-    let client = mongodb::Client::with_options(mongodb_opts)?;
-    let database = client.database(&opts.db);
+    if let Err(e) = init_logging(&opts) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 
-    let mut context = ExecutionContext::new();
+    if let Err(e) = run(opts).await {
+        eprintln!("{}", e);
+        process::exit(e.downcast_ref::<Exit>().map_or(EXIT_QUERY, |e| e.code));
+    }
+}
 
-    for entry in opts.schema.read_dir()? {
-        let path = entry?.path();
-        let schema = read_schema(&path)?;
-        let name = schema.mongodb_collection().to_owned();
-        let collection = database.collection(&name);
-        let table = MongoDbCollection::new(collection, schema);
-        let table = LazyMemTable::new(table);
-        context.register_table(&name, Box::new(table));
+// exit codes, so scripts can tell what kind of failure happened
+const EXIT_QUERY: i32 = 1;
+const EXIT_CONNECTION: i32 = 2;
+const EXIT_SCHEMA: i32 = 3;
+
+const MAX_CONCURRENT_VALIDATIONS: usize = 8;
+
+// an error along with the exit code it should produce
+#[derive(Debug)]
+struct Exit {
+    code: i32,
+    error: Box<dyn std::error::Error>,
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
     }
+}
+
+impl std::error::Error for Exit {}
+
+fn exit(code: i32, error: impl Into<Box<dyn std::error::Error>>) -> Box<dyn std::error::Error> {
+    Box::new(Exit {
+        code,
+        error: error.into(),
+    })
+}
+
+fn init_logging(opts: &Opts) -> Result<(), Box<dyn std::error::Error>> {
+    let filter = match (&opts.log_level, opts.verbose) {
+        (Some(filter), _) => EnvFilter::try_new(filter)?,
+        (None, 0) => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+        (None, 1) => EnvFilter::new("info"),
+        (None, 2) => EnvFilter::new("debug"),
+        (None, _) => EnvFilter::new("trace"),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .init();
+    Ok(())
+}
 
-    let mut rl = Editor::<()>::new();
+async fn run(opts: Opts) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load()?;
+    let profile = config.profile(opts.profile.as_deref())?;
 
-    loop {
-        let line = match rl.readline("> ") {
-            Ok(l) => l,
-            Err(ReadlineError::Eof) | Err(ReadlineError::Interrupted) => break,
-            Err(e) => return Err(e.into()),
+    // command line options take precedence over the profile, and the
+    // environment only over the default profile, so a profile asked for by
+    // name always connects to its own cluster
+    let env_uri = || {
+        env::var("BISHOP_MONGODB")
+            .or_else(|_| env::var("MONGODB_URI"))
+            .ok()
+    };
+    let uri = match (opts.mongodb, opts.profile.is_some()) {
+        (Some(uri), _) => Some(uri),
+        (None, true) if profile.mongodb.is_some() => None,
+        (None, _) => env_uri(),
+    };
+    // a config reload only reconnects if the profile chose the cluster
+    let profile_uri = match uri {
+        Some(_) => None,
+        None => profile.mongodb.clone(),
+    };
+    let uri = uri
+        .or(profile.mongodb)
+        .unwrap_or_else(|| "mongodb://localhost:27017".to_owned());
+    let db = opts.db.or(profile.db).unwrap_or_else(|| "test".to_owned());
+    let schema_dir = opts
+        .schema
+        .or(profile.schema)
+        .unwrap_or_else(|| PathBuf::from("schema"));
+    let format = match (opts.format, profile.format) {
+        (Some(format), _) => format,
+        (None, Some(format)) => format.parse()?,
+        (None, None) => Format::Table,
+    };
+
+    let mut mongodb_opts = ClientOptions::parse(&uri)
+        .await
+        .map_err(|e| exit(EXIT_CONNECTION, e))?;
+    let tls_ca_file = opts.tls_ca_file.or(profile.tls_ca_file);
+    let tls_certificate_key_file = opts
+        .tls_certificate_key_file
+        .or(profile.tls_certificate_key_file);
+    let tls_allow_invalid_certificates = opts.tls_allow_invalid_certificates
+        || profile.tls_allow_invalid_certificates.unwrap_or(false);
+    if tls_ca_file.is_some() || tls_certificate_key_file.is_some() || tls_allow_invalid_certificates
+    {
+        // keep any TLS settings from the connection string
+        let mut tls = match mongodb_opts.tls.take() {
+            Some(Tls::Enabled(tls)) => tls,
+            _ => TlsOptions::default(),
         };
+        if let Some(path) = tls_ca_file {
+            tls.ca_file_path = Some(path.to_string_lossy().into_owned());
+        }
+        if let Some(path) = tls_certificate_key_file {
+            tls.cert_key_file_path = Some(path.to_string_lossy().into_owned());
+        }
+        if tls_allow_invalid_certificates {
+            tls.allow_invalid_certificates = Some(true);
+        }
+        mongodb_opts.tls = Some(Tls::Enabled(tls));
+    }
+    if let Some(mechanism) = opts.auth_mechanism.or(profile.auth_mechanism) {
+        let mechanism =
+            auth_mechanism(&mechanism, &mongodb_opts).map_err(|e| exit(EXIT_CONNECTION, e))?;
+        let credential = mongodb_opts.credential.get_or_insert_with(Default::default);
+        credential.mechanism = Some(mechanism);
+    }
+    if let Some(source) = opts.auth_source.or(profile.auth_source) {
+        let credential = mongodb_opts.credential.get_or_insert_with(Default::default);
+        credential.source = Some(source);
+    }
+    let mut properties = profile.auth_mechanism_properties;
+    for property in opts.auth_mechanism_property {
+        let (key, value) = split_property(&property).map_err(|e| exit(EXIT_CONNECTION, e))?;
+        properties.insert(key.to_owned(), value.to_owned());
+    }
+    if !properties.is_empty() {
+        let credential = mongodb_opts.credential.get_or_insert_with(Default::default);
+        let document = credential
+            .mechanism_properties
+            .get_or_insert_with(Document::new);
+        for (key, value) in properties {
+            document.insert(key, value);
+        }
+    }
+    if opts.password_prompt {
+        let mut credential = mongodb_opts.credential.take().unwrap_or_default();
+        if credential.username.is_none() {
+            eprint!("Username: ");
+            let mut username = String::new();
+            io::stdin().read_line(&mut username)?;
+            credential.username = Some(username.trim().to_owned());
+        }
+        credential.password = Some(rpassword::read_password_from_tty(Some("Password: "))?);
+        mongodb_opts.credential = Some(credential);
+    }
+    if let Some(secs) = opts.wait {
+        schema_dir::wait_for_server(&mongodb_opts, Duration::from_secs(secs))
+            .await
+            .map_err(|e| exit(EXIT_CONNECTION, e))?;
+    }
+    let client = mongodb::Client::with_options(mongodb_opts.clone())
+        .map_err(|e| exit(EXIT_CONNECTION, e))?;
+    let database = client.database(&db);
+
+    match opts.subcommand {
+        Some(Subcommand::InferSchema {
+            collection,
+            sample,
+            yaml,
+            force,
+        }) => {
+            return infer_schema::run(&database, &schema_dir, &collection, sample, yaml, force)
+                .await;
+        }
+        Some(Subcommand::Audit { table, sample, all }) => {
+            let schemas = SchemaDir::new(schema_dir, client.clone(), mongodb_opts, db, None);
+            let schema = table_schema(schemas, opts.schema_collection, &table).await?;
+            let sample = if all { None } else { Some(sample) };
+            return audit::run(database.collection(&table), &schema, sample, format).await;
+        }
+        Some(Subcommand::Dump {
+            table,
+            to,
+            by,
+            partitions,
+            parallel,
+            error_policy,
+        }) => {
+            let schemas = SchemaDir::new(schema_dir, client.clone(), mongodb_opts, db, None);
+            let schema = table_schema(schemas, opts.schema_collection, &table).await?;
+            let field = match by {
+                Some(column) => schema
+                    .fields()
+                    .iter()
+                    .find(|f| f.name() == column)
+                    .map(|f| f.mongodb_field().to_owned())
+                    .ok_or_else(|| format!("no column {} in {}", column, table))?,
+                None => "_id".to_owned(),
+            };
+            let dump = dump::Dump {
+                dir: &to,
+                field,
+                partitions,
+                parallel,
+                error_policy,
+            };
+            return dump::run(database.collection(&table), schema, dump).await;
+        }
+        Some(Subcommand::Load {
+            collection,
+            files,
+            unordered,
+            upsert_key,
+        }) => {
+            if opts.readonly || profile.readonly.unwrap_or(false) {
+                return Err("can't write in read only mode".into());
+            }
+            let mut schemas = SchemaDir::new(schema_dir, client.clone(), mongodb_opts, db, None);
+            if let Some(registry) = opts.schema_collection {
+                schemas.set_registry(registry);
+            }
+            schemas
+                .fetch()
+                .await
+                .map_err(|e| exit(EXIT_CONNECTION, e))?;
+            // loading in to a collection without a schema is fine
+            let schema = schemas.schema(&collection).ok();
+            let load = load::Load {
+                ordered: !unordered,
+                upsert_key: &upsert_key,
+            };
+            return load::run(database.collection(&collection), schema, &files, load).await;
+        }
+        Some(Subcommand::Export {
+            table,
+            to,
+            column,
+            since,
+            state,
+        }) => {
+            let schemas = SchemaDir::new(schema_dir, client.clone(), mongodb_opts, db, None);
+            let schema = table_schema(schemas, opts.schema_collection, &table).await?;
+            let export = export::Export {
+                to: &to,
+                column: &column,
+                since,
+                state,
+            };
+            return export::run(database.collection(&table), schema, export).await;
+        }
+        None => (),
+    }
 
-        let trimmed = line.trim_end();
+    let mut context = ExecutionContext::new();
+    udf::register(&mut context);
+    for path in &opts.plugin {
+        let functions = plugin::load(&mut context, path)
+            .map_err(|e| format!("can't load plugin {}: {}", path.display(), e))?;
+        tracing::info!(plugin = %path.display(), ?functions, "loaded plugin");
+    }
+    let mut session = Session::new(context, format);
+    session.display.expanded = profile.expanded.unwrap_or(false);
+    session.display.null = profile.null.unwrap_or_default();
+    session.display.color =
+        !opts.no_color && env::var_os("NO_COLOR").is_none() && atty::is(atty::Stream::Stdout);
+    session.timing = profile.timing.unwrap_or(false);
+    session.server_stats = opts.server_stats;
+    session.quiet = opts.quiet;
+    session.readonly = opts.readonly || profile.readonly.unwrap_or(false);
+    session.dry_run = opts.dry_run;
+    session.result_cache = opts
+        .result_cache
+        .map(|secs| ResultCache::new(Duration::from_secs(secs)));
+    session.memory_limit = opts
+        .max_memory
+        .map(|mb| Arc::new(MemoryLimit::new(mb * 1024 * 1024)));
+    session.saved_queries = config.queries;
+    session.aliases = config.aliases;
+    session.profile = opts.profile;
+    session.profile_uri = profile_uri;
+
+    if let Some(path) = opts.query_log.or(profile.query_log) {
+        let log = QueryLog::open(&path)
+            .map_err(|e| format!("can't open query log {}: {}", path.display(), e))?;
+        session.query_log = Some(log);
+    }
 
-        if trimmed == "quit" || trimmed == "exit" {
-            break;
+    let refresh = opts.refresh.map(Duration::from_secs);
+    // each cluster gets its own limits
+    let (max_time_ms, max_cursors, get_more_per_second) = (
+        profile.max_time_ms,
+        profile.max_cursors,
+        profile.get_more_per_second,
+    );
+    let throttle = || {
+        let mut throttle = Throttle::default();
+        if let Some(ms) = max_time_ms {
+            throttle = throttle.with_max_time(Duration::from_millis(ms));
+        }
+        if let Some(max) = max_cursors {
+            throttle = throttle.with_max_cursors(max);
+        }
+        if let Some(rate) = get_more_per_second {
+            throttle = throttle.with_get_more_rate(rate);
         }
+        throttle
+    };
+    for attach in &opts.attach {
+        let (name, uri) = split_attach(attach).map_err(|e| exit(EXIT_CONNECTION, e))?;
+        let options = ClientOptions::parse(uri)
+            .await
+            .map_err(|e| exit(EXIT_CONNECTION, e))?;
+        let client =
+            mongodb::Client::with_options(options.clone()).map_err(|e| exit(EXIT_CONNECTION, e))?;
+        let mut attached = SchemaDir::new(schema_dir.clone(), client, options, db.clone(), refresh);
+        attached.set_catalog(name.to_owned());
+        attached.set_sample(opts.sample);
+        attached.set_throttle(throttle());
+        if let Some(ref memory_limit) = session.memory_limit {
+            attached.set_memory_limit(memory_limit.clone());
+        }
+        if let Some(ref registry) = opts.schema_collection {
+            attached.set_registry(registry.clone());
+        }
+        session.attached.push(attached);
+    }
+    let mut schema_dir = SchemaDir::new(schema_dir, client, mongodb_opts, db, refresh);
+    schema_dir.set_sample(opts.sample);
+    schema_dir.set_throttle(throttle());
+    if let Some(ref memory_limit) = session.memory_limit {
+        schema_dir.set_memory_limit(memory_limit.clone());
+    }
+    if let Some(registry) = opts.schema_collection {
+        schema_dir.set_registry(registry);
+    }
+    session.sample = opts.sample;
+    session.schema_dir = Some(schema_dir);
+
+    session
+        .fetch_schemas()
+        .await
+        .map_err(|e| exit(EXIT_SCHEMA, e))?;
 
-        let trimmed = trimmed.strip_suffix(';').unwrap_or(trimmed);
-        match query(&mut context, trimmed).await {
-            Ok(r) => arrow::util::pretty::print_batches(&r)?,
-            Err(e) => eprintln!("{}", e),
+    // each cluster is a round trip, so check them side by side
+    let validated = stream::iter(session.schema_dir.iter().chain(&session.attached))
+        .map(|schema_dir| schema_dir.validate())
+        .buffer_unordered(MAX_CONCURRENT_VALIDATIONS)
+        .collect::<Vec<_>>()
+        .await;
+    let mut found = 0;
+    for problems in validated {
+        for problem in problems.map_err(|e| exit(EXIT_SCHEMA, e))? {
+            eprintln!("warning: {}", problem);
+            found += 1;
         }
     }
+    // only the REPL carries on, so scripts and CI checks see the failure
+    let interactive = opts.command.is_none() && atty::is(atty::Stream::Stdin);
+    if found > 0 && !interactive {
+        return Err(exit(
+            EXIT_SCHEMA,
+            format!("{} schema problems found", found),
+        ));
+    }
+    session.reload().map_err(|e| exit(EXIT_SCHEMA, e))?;
 
-    Ok(())
+    for file in opts.parquet {
+        session.register_table(&file.name, file.parquet()?);
+    }
+    for file in opts.csv {
+        session.register_table(&file.name, file.csv()?);
+    }
+    for file in opts.json {
+        session.register_table(&file.name, file.json()?);
+    }
+
+    if let Some(sql) = opts.command {
+        run_script(&mut session, &sql).await
+    } else if atty::isnt(atty::Stream::Stdin) {
+        let mut sql = String::new();
+        io::stdin().read_to_string(&mut sql)?;
+        run_script(&mut session, &sql).await
+    } else {
+        repl::run(&mut session, opts.watch, profile.max_rows).await
+    }
 }
 
-async fn query(
-    context: &mut ExecutionContext,
-    sql: &str,
-) -> Result<Vec<RecordBatch>, Box<dyn std::error::Error>> {
-    Ok(context.sql(sql)?.collect().await?)
+// parses an authentication mechanism name, checking the options it needs
+fn auth_mechanism(name: &str, options: &ClientOptions) -> Result<AuthMechanism, String> {
+    if name.eq_ignore_ascii_case("GSSAPI") {
+        return Err(
+            "GSSAPI (Kerberos) authentication isn't supported by the MongoDB driver".into(),
+        );
+    }
+    let mechanism = name
+        .to_ascii_uppercase()
+        .parse::<AuthMechanism>()
+        .map_err(|e| e.to_string())?;
+    if let AuthMechanism::MongoDbX509 = mechanism {
+        let has_cert = match options.tls {
+            Some(Tls::Enabled(ref tls)) => tls.cert_key_file_path.is_some(),
+            _ => false,
+        };
+        if !has_cert {
+            return Err("MONGODB-X509 authentication needs --tls-certificate-key-file".into());
+        }
+    }
+    Ok(mechanism)
 }
 
-fn read_schema<P: AsRef<Path>>(path: P) -> Result<MappedSchema, Box<dyn std::error::Error>> {
-    let file = File::open(path.as_ref())?;
-    let buf_reader = BufReader::new(file);
+// splits a "KEY:VALUE" authentication mechanism property
+fn split_property(property: &str) -> Result<(&str, &str), String> {
+    let i = property
+        .find(':')
+        .ok_or_else(|| format!("expected KEY:VALUE, got {:?}", property))?;
+    Ok((&property[..i], &property[i + 1..]))
+}
 
-    let schema = match path.as_ref().extension().and_then(|e| e.to_str()) {
-        Some("yaml") | Some("yml") => Schema::from(&serde_yaml::from_reader(buf_reader)?)?,
-        _ => Schema::from(&serde_json::from_reader(buf_reader)?)?,
-    };
+// the schema for `table` from the schema directory, or the registry
+// collection if set
+async fn table_schema(
+    mut schemas: SchemaDir,
+    registry: Option<String>,
+    table: &str,
+) -> Result<MappedSchema, Box<dyn std::error::Error>> {
+    if let Some(registry) = registry {
+        schemas.set_registry(registry);
+    }
+    schemas
+        .fetch()
+        .await
+        .map_err(|e| exit(EXIT_CONNECTION, e))?;
+    schemas.schema(table).map_err(|e| exit(EXIT_SCHEMA, e))
+}
+
+// splits a "NAME=URL" attached cluster
+fn split_attach(attach: &str) -> Result<(&str, &str), String> {
+    let i = attach
+        .find('=')
+        .ok_or_else(|| format!("expected NAME=URL, got {:?}", attach))?;
+    let (name, uri) = (&attach[..i], &attach[i + 1..]);
+    match statements::identifier(name) {
+        Some((_, rest)) if rest.is_empty() => Ok((name, uri)),
+        _ => Err(format!("invalid name {:?} for attached cluster", name)),
+    }
+}
+
+// runs each statement in turn, stopping at the first error
+async fn run_script(session: &mut Session, sql: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut statements, rest) = statements::split(sql);
+    if !rest.trim().is_empty() {
+        statements.push(rest.trim());
+    }
 
-    // [TODO] error if schema uses any type we don't support
-
-    let fields = schema
-        .fields()
-        .iter()
-        .map(|f| {
-            let mut field = f.clone();
-            let mongodb_field = field
-                .metadata()
-                .as_ref()
-                .and_then(|m| m.get("mongodb"))
-                .unwrap_or_else(|| field.name())
-                .to_owned();
-            field.set_metadata(None);
-            MappedField::new(mongodb_field, field)
-        })
-        .collect();
-
-    let mongodb_collection = path
-        .as_ref()
-        .file_stem()
-        .and_then(|e| e.to_str())
-        .unwrap()
-        .to_owned();
-
-    Ok(MappedSchema::new(mongodb_collection, fields))
+    for statement in statements {
+        if let Err(e) = session.execute(statement).await {
+            if schema_dir::is_connection_error(&*e) {
+                return Err(exit(EXIT_CONNECTION, e));
+            }
+            return Err(e);
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,141 @@
+use arrow::datatypes::{DataType, Field};
+use mongodb_arrow::MappedField;
+
+use crate::{
+    ddl,
+    statements::{identifier, keyword, quoted},
+};
+
+/// A change to a table's mapping, from
+/// `\map table column AS TYPE [FROM 'field'] [SAVE]` or
+/// `\unmap table column [SAVE]`.
+#[derive(Debug, PartialEq)]
+pub struct Remap<'a> {
+    pub table: &'a str,
+    pub change: Change<'a>,
+    /// write the new mapping back to the table's schema file
+    pub save: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Change<'a> {
+    Map {
+        column: &'a str,
+        data_type: DataType,
+        /// None to keep the current field, or use the column name
+        mongodb_field: Option<String>,
+    },
+    Unmap {
+        column: &'a str,
+    },
+}
+
+impl Change<'_> {
+    /// Applies the change to `fields`, returning a description of it.
+    pub fn apply(&self, fields: &mut Vec<MappedField>) -> Result<String, String> {
+        match self {
+            Change::Map {
+                column,
+                data_type,
+                mongodb_field,
+            } => {
+                let existing = fields.iter().position(|f| f.name() == column);
+                let (nullable, current_field, transforms) = match existing {
+                    Some(i) => (
+                        fields[i].is_nullable(),
+                        fields[i].mongodb_field().to_owned(),
+                        fields[i].transforms().to_vec(),
+                    ),
+                    None => (true, (*column).to_owned(), Vec::new()),
+                };
+                let mongodb_field = mongodb_field.clone().unwrap_or(current_field);
+                let description = format!(
+                    "mapped {} AS {} FROM {:?}",
+                    column, data_type, mongodb_field
+                );
+                let field = MappedField::new(
+                    mongodb_field,
+                    Field::new(column, data_type.clone(), nullable),
+                )
+                .with_transforms(transforms);
+                match existing {
+                    Some(i) => fields[i] = field,
+                    None => fields.push(field),
+                }
+                Ok(description)
+            }
+            Change::Unmap { column } => {
+                let i = fields
+                    .iter()
+                    .position(|f| f.name() == column)
+                    .ok_or_else(|| format!("no column named {}", column))?;
+                if fields.len() == 1 {
+                    return Err(format!("can't unmap {}, it's the only column", column));
+                }
+                fields.remove(i);
+                Ok(format!("unmapped {}", column))
+            }
+        }
+    }
+}
+
+/// Parses the arguments to `\map`.
+pub fn parse_map(s: &str) -> Result<Remap<'_>, String> {
+    let (table, rest) = table_name(s).ok_or("expected \\map table column AS TYPE")?;
+    let (column, rest) = identifier(rest).ok_or("expected \\map table column AS TYPE")?;
+    let rest = keyword(rest, "as").ok_or("expected AS TYPE after column")?;
+    let (type_name, rest) = identifier(rest).ok_or("expected AS TYPE after column")?;
+    let data_type = ddl::data_type(type_name)?;
+    let (mongodb_field, rest) = match keyword(rest, "from") {
+        Some(rest) => {
+            let (field, rest) = quoted(rest).ok_or("expected FROM 'field'")?;
+            (Some(field), rest)
+        }
+        None => (None, rest),
+    };
+    let save = save(rest)?;
+    Ok(Remap {
+        table,
+        change: Change::Map {
+            column,
+            data_type,
+            mongodb_field,
+        },
+        save,
+    })
+}
+
+/// Parses the arguments to `\unmap`.
+pub fn parse_unmap(s: &str) -> Result<Remap<'_>, String> {
+    let (table, rest) = table_name(s).ok_or("expected \\unmap table column")?;
+    let (column, rest) = identifier(rest).ok_or("expected \\unmap table column")?;
+    let save = save(rest)?;
+    Ok(Remap {
+        table,
+        change: Change::Unmap { column },
+        save,
+    })
+}
+
+// table names may be qualified by an attached catalog, as in `staging.users`
+fn table_name(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    let (_, rest) = identifier(s)?;
+    let rest = match rest.strip_prefix('.') {
+        Some(rest) => identifier(rest)?.1,
+        None => rest,
+    };
+    Some((&s[..s.len() - rest.len()], rest))
+}
+
+fn save(rest: &str) -> Result<bool, String> {
+    let (save, rest) = match keyword(rest, "save") {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    if rest.trim().is_empty() {
+        Ok(save)
+    } else {
+        Err(format!("unexpected {:?}", rest.trim()))
+    }
+}
@@ -0,0 +1,834 @@
+use std::{error::Error, fmt, io::Write, str::FromStr, sync::Arc};
+
+use arrow::{
+    array::{
+        as_boolean_array, as_largestring_array, as_primitive_array, as_string_array, Array,
+        ArrayRef, BinaryArray, LargeBinaryArray,
+    },
+    csv,
+    datatypes::{
+        DataType, Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, Schema,
+        TimeUnit, TimestampMicrosecondType, TimestampMillisecondType, TimestampNanosecondType,
+        TimestampSecondType, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+    },
+    error::ArrowError,
+    ipc::writer::StreamWriter,
+    record_batch::RecordBatch,
+    util::display::array_value_to_string,
+};
+use chrono::{FixedOffset, Local, NaiveDateTime, TimeZone as _, Utc};
+use parquet::{arrow::ArrowWriter, file::writer::InMemoryWriteableCursor};
+use serde_json::{Map, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Csv,
+    Json,
+    Jsonl,
+    Parquet,
+    Arrow,
+    Markdown,
+    Html,
+}
+
+impl Format {
+    /// Whether the format is binary, so shouldn't be written to a terminal,
+    /// mixed with messages, or cut short.
+    pub fn is_binary(self) -> bool {
+        matches!(self, Format::Parquet | Format::Arrow)
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "jsonl" | "ndjson" => Ok(Format::Jsonl),
+            "parquet" => Ok(Format::Parquet),
+            "arrow" | "ipc" => Ok(Format::Arrow),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "html" | "htm" => Ok(Format::Html),
+            _ => Err(format!(
+                "unknown format {:?}, expected one of table, csv, json, jsonl, parquet, arrow, \
+                markdown, html",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Table => "table",
+            Format::Csv => "csv",
+            Format::Json => "json",
+            Format::Jsonl => "jsonl",
+            Format::Parquet => "parquet",
+            Format::Arrow => "arrow",
+            Format::Markdown => "markdown",
+            Format::Html => "html",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Writes `batches` in `format`. Parquet and Arrow files are written with
+/// `schema` even when there are no batches, so they're still valid.
+pub fn write_batches<W: Write>(
+    format: Format,
+    schema: &Schema,
+    batches: &[RecordBatch],
+    mut out: W,
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        Format::Table => write_table(batches, &DisplayOptions::default(), &mut out)?,
+        Format::Csv => {
+            let mut writer = csv::Writer::new(&mut out);
+            for batch in batches {
+                writer.write(batch)?;
+            }
+        }
+        Format::Json => {
+            let mut rows = Vec::new();
+            for batch in batches {
+                rows.extend(to_json_rows(batch)?.into_iter().map(Value::Object));
+            }
+            serde_json::to_writer(&mut out, &rows)?;
+            writeln!(out)?;
+        }
+        Format::Jsonl => {
+            for batch in batches {
+                for row in to_json_rows(batch)? {
+                    serde_json::to_writer(&mut out, &row)?;
+                    writeln!(out)?;
+                }
+            }
+        }
+        Format::Parquet => {
+            // ArrowWriter needs to seek, so can't write directly to out
+            let cursor = InMemoryWriteableCursor::default();
+            let mut writer = ArrowWriter::try_new(cursor.clone(), Arc::new(schema.clone()), None)?;
+            for batch in batches {
+                writer.write(batch)?;
+            }
+            writer.close()?;
+            out.write_all(&cursor.data())?;
+        }
+        Format::Arrow => {
+            let mut writer = StreamWriter::try_new(&mut out, schema)?;
+            for batch in batches {
+                writer.write(batch)?;
+            }
+            writer.finish()?;
+        }
+        Format::Markdown => {
+            if let Some(first) = batches.first() {
+                let schema = first.schema();
+                let names = schema.fields().iter().map(|f| markdown_escape(f.name()));
+                writeln!(out, "| {} |", names.collect::<Vec<_>>().join(" | "))?;
+                let rule = vec!["---"; schema.fields().len()].join(" | ");
+                writeln!(out, "| {} |", rule)?;
+                for row in string_rows(batches, &DisplayOptions::default())? {
+                    let values = row
+                        .iter()
+                        .map(|v| markdown_escape(v.as_deref().unwrap_or("")))
+                        .collect::<Vec<_>>();
+                    writeln!(out, "| {} |", values.join(" | "))?;
+                }
+            }
+        }
+        Format::Html => {
+            writeln!(out, "<table>")?;
+            if let Some(first) = batches.first() {
+                writeln!(out, "  <thead>")?;
+                write!(out, "    <tr>")?;
+                for field in first.schema().fields() {
+                    write!(out, "<th>{}</th>", html_escape(field.name()))?;
+                }
+                writeln!(out, "</tr>")?;
+                writeln!(out, "  </thead>")?;
+                writeln!(out, "  <tbody>")?;
+                for row in string_rows(batches, &DisplayOptions::default())? {
+                    write!(out, "    <tr>")?;
+                    for value in row {
+                        write!(
+                            out,
+                            "<td>{}</td>",
+                            html_escape(value.as_deref().unwrap_or(""))
+                        )?;
+                    }
+                    writeln!(out, "</tr>")?;
+                }
+                writeln!(out, "  </tbody>")?;
+            }
+            writeln!(out, "</table>")?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Settings for how results are shown in the terminal.
+#[derive(Clone, Debug, Default)]
+pub struct DisplayOptions {
+    /// print one line per column for each row, like `\x` in psql
+    pub expanded: bool,
+    /// shown in place of null values, so they stand out from empty strings
+    pub null: String,
+    /// highlight headers and nulls with ANSI escape codes
+    pub color: bool,
+    /// digits after the decimal point for floats, all significant digits
+    /// if None
+    pub float_precision: Option<usize>,
+    /// strftime style format for timestamps
+    pub timestamp_format: Option<String>,
+    /// timestamps are shown in this time zone
+    pub timezone: TimeZone,
+    pub binary: BinaryFormat,
+    pub border: BorderStyle,
+    /// longer values are cut short with an ellipsis
+    pub max_width: Option<usize>,
+}
+
+/// The lines drawn around and between table cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorderStyle {
+    Ascii,
+    Unicode,
+    /// aligned columns without borders
+    None,
+    /// values separated by `|`, without alignment
+    Minimal,
+}
+
+impl Default for BorderStyle {
+    fn default() -> Self {
+        BorderStyle::Ascii
+    }
+}
+
+// left, middle, and right parts of a horizontal rule, then its fill
+type Rule = [&'static str; 4];
+
+struct Borders {
+    top: Option<Rule>,
+    header: Rule,
+    bottom: Option<Rule>,
+    // left, between cells, and right
+    vertical: [&'static str; 3],
+}
+
+impl BorderStyle {
+    fn borders(self) -> Borders {
+        match self {
+            BorderStyle::Ascii => Borders {
+                top: Some(["+", "+", "+", "-"]),
+                header: ["+", "+", "+", "-"],
+                bottom: Some(["+", "+", "+", "-"]),
+                vertical: ["|", "|", "|"],
+            },
+            BorderStyle::Unicode => Borders {
+                top: Some(["\u{250c}", "\u{252c}", "\u{2510}", "\u{2500}"]),
+                header: ["\u{251c}", "\u{253c}", "\u{2524}", "\u{2500}"],
+                bottom: Some(["\u{2514}", "\u{2534}", "\u{2518}", "\u{2500}"]),
+                vertical: ["\u{2502}", "\u{2502}", "\u{2502}"],
+            },
+            BorderStyle::None | BorderStyle::Minimal => Borders {
+                top: None,
+                header: ["", " ", "", "-"],
+                bottom: None,
+                vertical: ["", " ", ""],
+            },
+        }
+    }
+}
+
+impl FromStr for BorderStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ascii" => Ok(BorderStyle::Ascii),
+            "unicode" => Ok(BorderStyle::Unicode),
+            "none" => Ok(BorderStyle::None),
+            "minimal" => Ok(BorderStyle::Minimal),
+            _ => Err(format!(
+                "unknown border style {:?}, expected one of ascii, unicode, none, minimal",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BorderStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BorderStyle::Ascii => "ascii",
+            BorderStyle::Unicode => "unicode",
+            BorderStyle::None => "none",
+            BorderStyle::Minimal => "minimal",
+        };
+        f.write_str(name)
+    }
+}
+
+const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+impl DisplayOptions {
+    fn format_timestamp(&self, datetime: NaiveDateTime) -> String {
+        let format = self
+            .timestamp_format
+            .as_deref()
+            .unwrap_or(DEFAULT_TIMESTAMP_FORMAT);
+        let utc = Utc.from_utc_datetime(&datetime);
+        match self.timezone {
+            TimeZone::Utc => utc.format(format).to_string(),
+            TimeZone::Local => utc.with_timezone(&Local).format(format).to_string(),
+            TimeZone::Fixed(offset) => utc.with_timezone(&offset).format(format).to_string(),
+        }
+    }
+}
+
+/// The time zone timestamps are displayed in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeZone {
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        TimeZone::Utc
+    }
+}
+
+impl FromStr for TimeZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "utc" | "z" => return Ok(TimeZone::Utc),
+            "local" => return Ok(TimeZone::Local),
+            _ => (),
+        }
+        // an offset like +01:00 or -0530
+        let error = || format!("expected utc, local, or an offset like +01:00, got {:?}", s);
+        let sign = match s.get(..1) {
+            Some("+") => 1,
+            Some("-") => -1,
+            _ => return Err(error()),
+        };
+        let digits = s[1..].replace(':', "");
+        if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(error());
+        }
+        let hours: i32 = digits[..2].parse().map_err(|_| error())?;
+        let minutes: i32 = digits[2..].parse().map_err(|_| error())?;
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+            .map(TimeZone::Fixed)
+            .ok_or_else(error)
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeZone::Utc => f.write_str("utc"),
+            TimeZone::Local => f.write_str("local"),
+            TimeZone::Fixed(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+/// How binary values are displayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryFormat {
+    Hex,
+    Base64,
+    /// only the number of bytes
+    Length,
+}
+
+impl Default for BinaryFormat {
+    fn default() -> Self {
+        BinaryFormat::Hex
+    }
+}
+
+impl BinaryFormat {
+    fn format(self, bytes: &[u8]) -> String {
+        match self {
+            BinaryFormat::Hex => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            BinaryFormat::Base64 => base64::encode(bytes),
+            BinaryFormat::Length => format!("<{} bytes>", bytes.len()),
+        }
+    }
+}
+
+impl FromStr for BinaryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hex" => Ok(BinaryFormat::Hex),
+            "base64" => Ok(BinaryFormat::Base64),
+            "length" => Ok(BinaryFormat::Length),
+            _ => Err(format!(
+                "unknown binary format {:?}, expected one of hex, base64, length",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BinaryFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BinaryFormat::Hex => "hex",
+            BinaryFormat::Base64 => "base64",
+            BinaryFormat::Length => "length",
+        };
+        f.write_str(name)
+    }
+}
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+pub const RED: &str = "\x1b[31m";
+pub const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Wraps `text` in the ANSI escape `code` if `color` is set.
+pub fn paint(color: bool, code: &str, text: &str) -> String {
+    if color {
+        format!("{}{}{}", code, text, RESET)
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Like `write_batches`, but the table format is written according to
+/// `options`.
+pub fn write_display<W: Write>(
+    format: Format,
+    options: &DisplayOptions,
+    batches: &[RecordBatch],
+    out: W,
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        Format::Table if options.expanded => write_expanded(batches, options, out),
+        Format::Table => write_table(batches, options, out),
+        _ => {
+            // there's no plan to take the schema from here, so a result
+            // without batches is written with no columns
+            let schema = batches
+                .first()
+                .map(|b| b.schema())
+                .unwrap_or_else(|| Arc::new(Schema::empty()));
+            write_batches(format, &schema, batches, out)
+        }
+    }
+}
+
+fn write_table<W: Write>(
+    batches: &[RecordBatch],
+    options: &DisplayOptions,
+    mut out: W,
+) -> Result<(), Box<dyn Error>> {
+    let borders = options.border.borders();
+    let schema = match batches.first() {
+        Some(batch) => batch.schema(),
+        None => {
+            for rule in [&borders.top, &borders.bottom].iter().copied().flatten() {
+                writeln!(out, "{}{}", rule[0], rule[2])?;
+            }
+            return Ok(());
+        }
+    };
+    let names = schema.fields().iter().map(|f| f.name()).collect::<Vec<_>>();
+
+    let mut rows = string_rows(batches, options)?;
+    if let Some(max_width) = options.max_width {
+        for value in rows.iter_mut().flatten().flatten() {
+            *value = cap_width(value, max_width);
+        }
+    }
+
+    if options.border == BorderStyle::Minimal {
+        let names = names.iter().map(|n| paint(options.color, BOLD, n));
+        writeln!(out, "{}", names.collect::<Vec<_>>().join("|"))?;
+        for row in &rows {
+            let values = row.iter().map(|v| match v {
+                Some(value) => value.clone(),
+                None => paint(options.color, DIM, &options.null),
+            });
+            writeln!(out, "{}", values.collect::<Vec<_>>().join("|"))?;
+        }
+        out.flush()?;
+        return Ok(());
+    }
+
+    let mut widths = names.iter().map(|n| n.chars().count()).collect::<Vec<_>>();
+    for row in &rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            let value = value.as_deref().unwrap_or(&options.null);
+            *width = (*width).max(value.chars().count());
+        }
+    }
+
+    let rule = |parts: &[&str; 4]| {
+        let fill = widths
+            .iter()
+            .map(|w| parts[3].repeat(w + 2))
+            .collect::<Vec<_>>()
+            .join(parts[1]);
+        format!("{}{}{}", parts[0], fill, parts[2])
+    };
+    let [left, middle, right] = borders.vertical;
+
+    if let Some(ref top) = borders.top {
+        writeln!(out, "{}", rule(top))?;
+    }
+    write!(out, "{}", left)?;
+    for (i, (name, width)) in names.iter().zip(&widths).enumerate() {
+        let name = format!("{:<width$}", name, width = width);
+        let separator = if i + 1 == widths.len() { right } else { middle };
+        write!(out, " {} {}", paint(options.color, BOLD, &name), separator)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "{}", rule(&borders.header))?;
+    for row in &rows {
+        write!(out, "{}", left)?;
+        for (i, (value, width)) in row.iter().zip(&widths).enumerate() {
+            let separator = if i + 1 == widths.len() { right } else { middle };
+            match value {
+                Some(value) => write!(out, " {:<width$} {}", value, separator, width = width)?,
+                None => {
+                    let null = format!("{:<width$}", options.null, width = width);
+                    write!(out, " {} {}", paint(options.color, DIM, &null), separator)?;
+                }
+            }
+        }
+        writeln!(out)?;
+    }
+    if let Some(ref bottom) = borders.bottom {
+        writeln!(out, "{}", rule(bottom))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// shortens value to at most width characters, marking it with an ellipsis
+fn cap_width(value: &str, width: usize) -> String {
+    if value.chars().count() <= width {
+        return value.to_owned();
+    }
+    let mut capped = value
+        .chars()
+        .take(width.saturating_sub(1))
+        .collect::<String>();
+    capped.push('\u{2026}');
+    capped
+}
+
+fn write_expanded<W: Write>(
+    batches: &[RecordBatch],
+    options: &DisplayOptions,
+    mut out: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut record = 0;
+    for batch in batches {
+        write_expanded_batch(batch, options, &mut record, &mut out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// writes the rows of batch, numbering them on from record
+fn write_expanded_batch<W: Write>(
+    batch: &RecordBatch,
+    options: &DisplayOptions,
+    record: &mut usize,
+    mut out: W,
+) -> Result<(), Box<dyn Error>> {
+    let schema = batch.schema();
+    let names = schema.fields().iter().map(|f| f.name()).collect::<Vec<_>>();
+    let name_width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0);
+
+    for i in 0..batch.num_rows() {
+        *record += 1;
+        let mut values = batch
+            .columns()
+            .iter()
+            .map(|c| cell(c, i, options))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(max_width) = options.max_width {
+            for value in values.iter_mut().flatten() {
+                *value = cap_width(value, max_width);
+            }
+        }
+        let value_width = values
+            .iter()
+            .map(|v| v.as_deref().unwrap_or(&options.null).chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let header = format!("-[ RECORD {} ]", record);
+        let width = (name_width + 3 + value_width).max(header.len());
+        writeln!(out, "{:-<width$}", header, width = width)?;
+        for (name, value) in names.iter().zip(values) {
+            let name = format!("{:<width$}", name, width = name_width);
+            let value = match value {
+                Some(value) => value,
+                None => paint(options.color, DIM, &options.null),
+            };
+            writeln!(out, "{} | {}", paint(options.color, BOLD, &name), value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if `format` can be written a batch at a time with
+/// `BatchWriter`, rather than needing the whole result up front.
+pub fn is_streamable(format: Format, options: &DisplayOptions) -> bool {
+    match format {
+        Format::Csv | Format::Jsonl | Format::Arrow => true,
+        Format::Table => options.expanded,
+        _ => false,
+    }
+}
+
+/// Writes results as they arrive, for formats where `is_streamable` is true.
+pub struct BatchWriter<W: Write> {
+    state: WriterState<W>,
+}
+
+enum WriterState<W: Write> {
+    Csv(csv::Writer<W>),
+    Jsonl(W),
+    Arrow(StreamWriter<W>),
+    Expanded {
+        out: W,
+        options: DisplayOptions,
+        record: usize,
+    },
+}
+
+impl<W: Write> BatchWriter<W> {
+    /// `schema` is written up front by formats that include it, so the
+    /// output is valid even if there are no batches.
+    pub fn new(
+        format: Format,
+        options: &DisplayOptions,
+        schema: &Schema,
+        out: W,
+    ) -> Result<Self, Box<dyn Error>> {
+        let state = match format {
+            Format::Csv => WriterState::Csv(csv::Writer::new(out)),
+            Format::Jsonl => WriterState::Jsonl(out),
+            Format::Arrow => WriterState::Arrow(StreamWriter::try_new(out, schema)?),
+            Format::Table if options.expanded => WriterState::Expanded {
+                out,
+                options: options.clone(),
+                record: 0,
+            },
+            _ => return Err(format!("can't stream {} output", format).into()),
+        };
+        Ok(Self { state })
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), Box<dyn Error>> {
+        match &mut self.state {
+            WriterState::Csv(writer) => writer.write(batch)?,
+            WriterState::Jsonl(out) => {
+                for row in to_json_rows(batch)? {
+                    serde_json::to_writer(&mut *out, &row)?;
+                    writeln!(out)?;
+                }
+                out.flush()?;
+            }
+            WriterState::Arrow(writer) => writer.write(batch)?,
+            WriterState::Expanded {
+                out,
+                options,
+                record,
+            } => {
+                write_expanded_batch(batch, options, record, &mut *out)?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self.state {
+            WriterState::Arrow(mut writer) => writer.finish()?,
+            WriterState::Jsonl(mut out) => out.flush()?,
+            WriterState::Expanded { mut out, .. } => out.flush()?,
+            WriterState::Csv(_) => (),
+        }
+        Ok(())
+    }
+}
+
+// every row as strings, with None for nulls
+fn string_rows(
+    batches: &[RecordBatch],
+    options: &DisplayOptions,
+) -> Result<Vec<Vec<Option<String>>>, ArrowError> {
+    let mut rows = Vec::new();
+    for batch in batches {
+        for i in 0..batch.num_rows() {
+            let row = batch
+                .columns()
+                .iter()
+                .map(|c| cell(c, i, options))
+                .collect::<Result<Vec<_>, _>>()?;
+            rows.push(row);
+        }
+    }
+    Ok(rows)
+}
+
+fn markdown_escape(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', "<br>")
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// the value at row i, or None if it's null
+fn cell(
+    column: &ArrayRef,
+    i: usize,
+    options: &DisplayOptions,
+) -> Result<Option<String>, ArrowError> {
+    if column.is_null(i) {
+        return Ok(None);
+    }
+    let value = match column.data_type() {
+        DataType::Float32 if options.float_precision.is_some() => {
+            let value = as_primitive_array::<Float32Type>(column).value(i);
+            format!("{:.*}", options.float_precision.unwrap_or_default(), value)
+        }
+        DataType::Float64 if options.float_precision.is_some() => {
+            let value = as_primitive_array::<Float64Type>(column).value(i);
+            format!("{:.*}", options.float_precision.unwrap_or_default(), value)
+        }
+        DataType::Timestamp(unit, _) => {
+            let datetime = match unit {
+                TimeUnit::Second => {
+                    as_primitive_array::<TimestampSecondType>(column).value_as_datetime(i)
+                }
+                TimeUnit::Millisecond => {
+                    as_primitive_array::<TimestampMillisecondType>(column).value_as_datetime(i)
+                }
+                TimeUnit::Microsecond => {
+                    as_primitive_array::<TimestampMicrosecondType>(column).value_as_datetime(i)
+                }
+                TimeUnit::Nanosecond => {
+                    as_primitive_array::<TimestampNanosecondType>(column).value_as_datetime(i)
+                }
+            };
+            match datetime {
+                Some(datetime) => options.format_timestamp(datetime),
+                None => array_value_to_string(column, i)?,
+            }
+        }
+        DataType::Binary => match column.as_any().downcast_ref::<BinaryArray>() {
+            Some(array) => options.binary.format(array.value(i)),
+            None => array_value_to_string(column, i)?,
+        },
+        DataType::LargeBinary => match column.as_any().downcast_ref::<LargeBinaryArray>() {
+            Some(array) => options.binary.format(array.value(i)),
+            None => array_value_to_string(column, i)?,
+        },
+        _ => array_value_to_string(column, i)?,
+    };
+    Ok(Some(value))
+}
+
+/// Returns at most `max_rows` rows from `batches`, and the number of rows
+/// that were left out.
+pub fn truncate(
+    batches: &[RecordBatch],
+    max_rows: usize,
+) -> Result<(Vec<RecordBatch>, usize), ArrowError> {
+    let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+    Ok((slice(batches, 0, max_rows)?, rows.saturating_sub(max_rows)))
+}
+
+/// Returns at most `len` rows of `batches`, starting from row `offset`.
+pub fn slice(
+    batches: &[RecordBatch],
+    offset: usize,
+    len: usize,
+) -> Result<Vec<RecordBatch>, ArrowError> {
+    let mut skip = offset;
+    let mut remaining = len;
+    let mut kept = Vec::new();
+    for batch in batches {
+        if remaining == 0 {
+            break;
+        }
+        if skip >= batch.num_rows() {
+            skip -= batch.num_rows();
+            continue;
+        }
+        let n = (batch.num_rows() - skip).min(remaining);
+        if n == batch.num_rows() {
+            kept.push(batch.clone());
+        } else {
+            let columns = batch.columns().iter().map(|c| c.slice(skip, n)).collect();
+            kept.push(RecordBatch::try_new(batch.schema(), columns)?);
+        }
+        skip = 0;
+        remaining -= n;
+    }
+    Ok(kept)
+}
+
+fn to_json_rows(batch: &RecordBatch) -> Result<Vec<Map<String, Value>>, ArrowError> {
+    let schema = batch.schema();
+    let mut rows = vec![Map::new(); batch.num_rows()];
+    for (field, column) in schema.fields().iter().zip(batch.columns()) {
+        for (i, row) in rows.iter_mut().enumerate() {
+            row.insert(field.name().clone(), to_json_value(column, i)?);
+        }
+    }
+    Ok(rows)
+}
+
+fn to_json_value(array: &ArrayRef, i: usize) -> Result<Value, ArrowError> {
+    if array.is_null(i) {
+        return Ok(Value::Null);
+    }
+    let value = match array.data_type() {
+        DataType::Boolean => Value::from(as_boolean_array(array).value(i)),
+        DataType::Int8 => Value::from(as_primitive_array::<Int8Type>(array).value(i)),
+        DataType::Int16 => Value::from(as_primitive_array::<Int16Type>(array).value(i)),
+        DataType::Int32 => Value::from(as_primitive_array::<Int32Type>(array).value(i)),
+        DataType::Int64 => Value::from(as_primitive_array::<Int64Type>(array).value(i)),
+        DataType::UInt8 => Value::from(as_primitive_array::<UInt8Type>(array).value(i)),
+        DataType::UInt16 => Value::from(as_primitive_array::<UInt16Type>(array).value(i)),
+        DataType::UInt32 => Value::from(as_primitive_array::<UInt32Type>(array).value(i)),
+        DataType::UInt64 => Value::from(as_primitive_array::<UInt64Type>(array).value(i)),
+        DataType::Float32 => Value::from(as_primitive_array::<Float32Type>(array).value(i)),
+        DataType::Float64 => Value::from(as_primitive_array::<Float64Type>(array).value(i)),
+        DataType::Utf8 => Value::from(as_string_array(array).value(i)),
+        DataType::LargeUtf8 => Value::from(as_largestring_array(array).value(i)),
+        _ => Value::from(array_value_to_string(array, i)?),
+    };
+    Ok(value)
+}
@@ -0,0 +1,176 @@
+use std::{
+    ffi::CStr,
+    os::raw::{c_char, c_int},
+    path::Path,
+    slice,
+    sync::Arc,
+};
+
+use arrow::{
+    array::{make_array_from_raw, ArrayRef},
+    datatypes::{DataType, TimeUnit},
+    ffi::{ArrowArray, FFI_ArrowArray, FFI_ArrowSchema},
+};
+use datafusion::{
+    error::{DataFusionError, Result as DataFusionResult},
+    execution::context::ExecutionContext,
+    physical_plan::functions::make_scalar_function,
+    prelude::create_udf,
+};
+use libloading::{Library, Symbol};
+
+// bumped if the layout of the structs below changes
+const ENTRY_POINT: &[u8] = b"bishop_plugin_v1\0";
+
+/// The functions a plugin provides, returned by its `bishop_plugin_v1`
+/// function.
+#[repr(C)]
+pub struct Plugin {
+    pub functions: *const Function,
+    pub num_functions: usize,
+}
+
+/// A scalar function provided by a plugin.
+#[repr(C)]
+pub struct Function {
+    pub name: *const c_char,
+    /// argument and return types as Arrow C data interface format strings,
+    /// e.g. "l" for Int64 or "u" for Utf8
+    pub arg_types: *const *const c_char,
+    pub num_args: usize,
+    pub return_type: *const c_char,
+    /// Called with `num_args` arrays of the same length, borrowed for the
+    /// call. Returns 0 having set `out` and `out_schema` to the result,
+    /// created with arrow's `Array::to_raw`, otherwise sets `error` to a
+    /// message kept valid until the next call.
+    pub invoke: Invoke,
+}
+
+pub type Invoke = unsafe extern "C" fn(
+    args: *const *const FFI_ArrowArray,
+    arg_schemas: *const *const FFI_ArrowSchema,
+    out: *mut *const FFI_ArrowArray,
+    out_schema: *mut *const FFI_ArrowSchema,
+    error: *mut *const c_char,
+) -> c_int;
+
+/// Loads the dynamic library at `path` and registers its functions,
+/// returning their names.
+///
+/// Plugins run in process with no sandboxing, only load libraries you
+/// trust.
+pub fn load(
+    context: &mut ExecutionContext,
+    path: &Path,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let library = Arc::new(unsafe { Library::new(path)? });
+    let plugin = unsafe {
+        let entry: Symbol<unsafe extern "C" fn() -> Plugin> = library.get(ENTRY_POINT)?;
+        entry()
+    };
+    let functions = if plugin.num_functions == 0 {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(plugin.functions, plugin.num_functions) }
+    };
+
+    let mut names = Vec::with_capacity(functions.len());
+    for function in functions {
+        let name = unsafe { string(function.name) }?;
+        let arg_types = if function.num_args == 0 {
+            Vec::new()
+        } else {
+            unsafe { slice::from_raw_parts(function.arg_types, function.num_args) }
+                .iter()
+                .map(|&format| data_type(unsafe { string(format) }?.as_str()))
+                .collect::<Result<Vec<_>, _>>()
+        }
+        .map_err(|e| format!("{}: {}", name, e))?;
+        let return_type = unsafe { string(function.return_type) }
+            .and_then(|format| data_type(&format))
+            .map_err(|e| format!("{}: {}", name, e))?;
+
+        let library = library.clone();
+        let invoke = function.invoke;
+        let fun = make_scalar_function(move |args: &[ArrayRef]| {
+            // only for the library to outlive the function
+            let _ = &library;
+            call(invoke, args)
+        });
+        context.register_udf(create_udf(&name, arg_types, Arc::new(return_type), fun));
+        names.push(name);
+    }
+    Ok(names)
+}
+
+// passes args to a plugin function through the Arrow C data interface
+fn call(invoke: Invoke, args: &[ArrayRef]) -> DataFusionResult<ArrayRef> {
+    let raw = args
+        .iter()
+        .map(|a| a.to_raw())
+        .collect::<arrow::error::Result<Vec<_>>>()?;
+    // the args are released when this is dropped, whatever the outcome
+    let borrowed = raw
+        .iter()
+        .map(|&(array, schema)| unsafe { ArrowArray::try_from_raw(array, schema) })
+        .collect::<arrow::error::Result<Vec<_>>>()?;
+    let arrays = raw.iter().map(|(array, _)| *array).collect::<Vec<_>>();
+    let schemas = raw.iter().map(|(_, schema)| *schema).collect::<Vec<_>>();
+
+    let mut out = std::ptr::null();
+    let mut out_schema = std::ptr::null();
+    let mut error = std::ptr::null();
+    let status = unsafe {
+        invoke(
+            arrays.as_ptr(),
+            schemas.as_ptr(),
+            &mut out,
+            &mut out_schema,
+            &mut error,
+        )
+    };
+    drop(borrowed);
+    if status != 0 {
+        let message = if error.is_null() {
+            format!("plugin function failed with status {}", status)
+        } else {
+            unsafe { CStr::from_ptr(error) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        return Err(DataFusionError::Execution(message));
+    }
+    if out.is_null() || out_schema.is_null() {
+        return Err(DataFusionError::Execution(
+            "plugin function returned no result".to_owned(),
+        ));
+    }
+    Ok(unsafe { make_array_from_raw(out, out_schema) }?)
+}
+
+unsafe fn string(ptr: *const c_char) -> Result<String, String> {
+    if ptr.is_null() {
+        return Err("missing name or type".to_owned());
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map(ToOwned::to_owned)
+        .map_err(|e| e.to_string())
+}
+
+// the types usable in plugin function signatures, by their Arrow C data
+// interface format string
+fn data_type(format: &str) -> Result<DataType, String> {
+    Ok(match format {
+        "b" => DataType::Boolean,
+        "i" => DataType::Int32,
+        "l" => DataType::Int64,
+        "g" => DataType::Float64,
+        "u" => DataType::Utf8,
+        "z" => DataType::Binary,
+        "tsm:" => DataType::Timestamp(TimeUnit::Millisecond, None),
+        "tsu:" => DataType::Timestamp(TimeUnit::Microsecond, None),
+        "tsn:" => DataType::Timestamp(TimeUnit::Nanosecond, None),
+        _ => return Err(format!("unsupported type {:?}", format)),
+    })
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use datafusion::scalar::ScalarValue;
+
+use crate::statements::{identifier, keyword, parenthesised, quoted, split_list};
+
+/// A parsed `PREPARE`, `EXECUTE`, or `DEALLOCATE` statement.
+#[derive(Debug, PartialEq)]
+pub enum Prepared<'a> {
+    /// `PREPARE name AS query`, with parameters written `$1`, `$2`, ...
+    Prepare { name: &'a str, query: &'a str },
+    /// `EXECUTE name [(value, ...)]`
+    Execute {
+        name: &'a str,
+        params: Vec<ScalarValue>,
+    },
+    /// `DEALLOCATE name`
+    Deallocate { name: &'a str },
+}
+
+/// Returns None if `sql` isn't a PREPARE, EXECUTE, or DEALLOCATE statement,
+/// or an error if it is but it's malformed.
+pub fn parse(sql: &str) -> Option<Result<Prepared<'_>, String>> {
+    let sql = sql.trim();
+    if let Some(rest) = keyword(sql, "prepare") {
+        Some(parse_prepare(rest))
+    } else if let Some(rest) = keyword(sql, "execute") {
+        Some(parse_execute(rest))
+    } else if let Some(rest) = keyword(sql, "deallocate") {
+        let rest = keyword(rest, "prepare").unwrap_or(rest);
+        Some(match identifier(rest) {
+            Some((name, rest)) if rest.trim().is_empty() => Ok(Prepared::Deallocate { name }),
+            _ => Err("expected DEALLOCATE name".to_owned()),
+        })
+    } else {
+        None
+    }
+}
+
+fn parse_prepare(sql: &str) -> Result<Prepared<'_>, String> {
+    let (name, rest) = identifier(sql).ok_or("expected PREPARE name AS query")?;
+    let query = keyword(rest, "as").ok_or("expected AS after PREPARE name")?;
+    Ok(Prepared::Prepare {
+        name,
+        query: query.trim(),
+    })
+}
+
+fn parse_execute(sql: &str) -> Result<Prepared<'_>, String> {
+    let (name, rest) = identifier(sql).ok_or("expected EXECUTE name")?;
+    let params = if rest.trim().is_empty() {
+        Vec::new()
+    } else {
+        let (args, rest) = parenthesised(rest).ok_or("expected EXECUTE name (value, ...)")?;
+        if !rest.trim().is_empty() {
+            return Err(format!("unexpected {:?} after EXECUTE", rest.trim()));
+        }
+        split_list(args)
+            .into_iter()
+            .map(literal)
+            .collect::<Result<_, _>>()?
+    };
+    Ok(Prepared::Execute { name, params })
+}
+
+pub fn literal(s: &str) -> Result<ScalarValue, String> {
+    if let Some((value, rest)) = quoted(s) {
+        if rest.trim().is_empty() {
+            return Ok(ScalarValue::Utf8(Some(value)));
+        }
+    }
+    if s.eq_ignore_ascii_case("null") {
+        Ok(ScalarValue::Utf8(None))
+    } else if s.eq_ignore_ascii_case("true") {
+        Ok(ScalarValue::Boolean(Some(true)))
+    } else if s.eq_ignore_ascii_case("false") {
+        Ok(ScalarValue::Boolean(Some(false)))
+    } else if let Ok(i) = s.parse() {
+        Ok(ScalarValue::Int64(Some(i)))
+    } else if let Ok(f) = s.parse() {
+        Ok(ScalarValue::Float64(Some(f)))
+    } else {
+        Err(format!("expected a literal value, got {:?}", s))
+    }
+}
+
+/// Substitutes `params` for the `$1`, `$2`, ... placeholders in `query`.
+///
+/// Values are written as SQL literals, with strings quoted and escaped, so
+/// they can't change the structure of the query.
+pub fn bind(query: &str, params: &[ScalarValue]) -> Result<String, String> {
+    substitute(
+        query,
+        |c| c.is_ascii_digit(),
+        |placeholder| {
+            let n = placeholder.parse::<usize>().unwrap_or(0);
+            n.checked_sub(1)
+                .and_then(|i| params.get(i))
+                .ok_or_else(|| format!("no value for ${}, {} given", n, params.len()))
+        },
+    )
+}
+
+/// Like `bind`, but for named `$name` placeholders.
+pub fn bind_named(query: &str, params: &HashMap<String, ScalarValue>) -> Result<String, String> {
+    substitute(
+        query,
+        |c| c.is_alphanumeric() || c == '_',
+        |placeholder| {
+            params
+                .get(placeholder)
+                .ok_or_else(|| format!("no value for ${}", placeholder))
+        },
+    )
+}
+
+/// Parses `name=value` pairs separated by whitespace, as given to saved
+/// queries, such as `date='2024-01-01' limit=10`.
+pub fn named_args(s: &str) -> Result<HashMap<String, ScalarValue>, String> {
+    let mut args = HashMap::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let (name, after) = identifier(rest).ok_or("expected name=value")?;
+        let after = after
+            .trim_start()
+            .strip_prefix('=')
+            .ok_or_else(|| format!("expected = after {}", name))?
+            .trim_start();
+        let (value, after) = match quoted(after) {
+            Some((value, after)) => (ScalarValue::Utf8(Some(value)), after),
+            None => {
+                let end = after
+                    .find(char::is_whitespace)
+                    .unwrap_or_else(|| after.len());
+                (literal(&after[..end])?, &after[end..])
+            }
+        };
+        args.insert(name.to_owned(), value);
+        rest = after.trim_start();
+    }
+    Ok(args)
+}
+
+// replaces `$` followed by characters matching `placeholder` outside of
+// quotes with the SQL for the value `lookup` gives for them
+fn substitute<'a, P, L>(query: &str, placeholder: P, mut lookup: L) -> Result<String, String>
+where
+    P: Fn(char) -> bool,
+    L: FnMut(&str) -> Result<&'a ScalarValue, String>,
+{
+    let mut bound = String::with_capacity(query.len());
+    let mut quote = None;
+    let mut chars = query.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '$' if chars.peek().map_or(false, |&(_, c)| placeholder(c)) => {
+                    let start = i + 1;
+                    let mut end = start;
+                    while let Some(&(j, c)) = chars.peek() {
+                        if !placeholder(c) {
+                            break;
+                        }
+                        end = j + c.len_utf8();
+                        chars.next();
+                    }
+                    bound.push_str(&to_sql(lookup(&query[start..end])?));
+                    continue;
+                }
+                _ => (),
+            },
+        }
+        bound.push(c);
+    }
+
+    Ok(bound)
+}
+
+fn to_sql(value: &ScalarValue) -> String {
+    match value {
+        ScalarValue::Utf8(Some(s)) => format!("'{}'", s.replace('\'', "''")),
+        ScalarValue::Int64(Some(i)) => i.to_string(),
+        ScalarValue::Float64(Some(f)) => format!("{:?}", f),
+        ScalarValue::Boolean(Some(b)) => b.to_string(),
+        _ => "NULL".to_owned(),
+    }
+}
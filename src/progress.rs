@@ -0,0 +1,53 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use mongodb_datafusion::datasource::documents_read;
+
+const FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+// quick queries finish before the spinner would appear, so don't flash it
+const DELAY: Duration = Duration::from_millis(500);
+const INTERVAL: Duration = Duration::from_millis(100);
+
+// whether the spinner is on screen, and needs clearing before other output
+static DRAWN: AtomicBool = AtomicBool::new(false);
+
+/// A spinner on stderr showing a query is still running, with the time
+/// taken and the documents read from MongoDB so far.
+pub struct Progress {
+    start: Instant,
+    documents: u64,
+}
+
+impl Progress {
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            documents: documents_read(),
+        }
+    }
+
+    /// Redraws the spinner until the future is dropped.
+    pub async fn run(&self) {
+        tokio::time::delay_for(DELAY).await;
+        for frame in FRAMES.iter().cycle() {
+            eprint!(
+                "\r{} {:.1}s, {} documents read",
+                frame,
+                self.start.elapsed().as_secs_f64(),
+                documents_read() - self.documents,
+            );
+            DRAWN.store(true, Ordering::Relaxed);
+            tokio::time::delay_for(INTERVAL).await;
+        }
+    }
+}
+
+/// Removes the spinner, if it's shown, so it doesn't run in to results or
+/// messages.
+pub fn clear() {
+    if DRAWN.swap(false, Ordering::Relaxed) {
+        eprint!("\r\x1b[K");
+    }
+}
@@ -0,0 +1,43 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+use chrono::Utc;
+use serde_json::json;
+
+/// An append only JSON Lines record of executed statements.
+pub struct QueryLog {
+    file: File,
+}
+
+impl QueryLog {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    /// Appends an entry for `sql`, `rows` is None for statements that don't
+    /// return or affect rows.
+    pub fn record(
+        &mut self,
+        sql: &str,
+        elapsed: Duration,
+        rows: Option<usize>,
+        error: Option<&str>,
+    ) -> io::Result<()> {
+        let entry = json!({
+            "timestamp": Utc::now().to_rfc3339(),
+            "statement": sql,
+            "duration_ms": elapsed.as_secs_f64() * 1000.0,
+            "rows": rows,
+            "error": error,
+        });
+        // written in one call so concurrent sessions don't interleave lines
+        let mut line = entry.to_string();
+        line.push('\n');
+        self.file.write_all(line.as_bytes())
+    }
+}
@@ -0,0 +1,516 @@
+use std::{
+    fs::File,
+    time::{Duration, Instant},
+};
+
+use futures::{
+    future::{self, Either},
+    pin_mut,
+};
+use rustyline::{error::ReadlineError, Editor};
+
+use crate::{
+    completion::SqlHelper,
+    mapping, output,
+    progress::{self, Progress},
+    result_cache::ResultCache,
+    session::Session,
+    statements,
+    transcript::Transcript,
+};
+
+const DEFAULT_MAX_ROWS: usize = 1000;
+
+/// Runs the interactive prompt, showing at most `max_rows` rows of each
+/// result, or `DEFAULT_MAX_ROWS` if None.
+pub async fn run(
+    session: &mut Session,
+    watch: bool,
+    max_rows: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // scripts and piped input are never truncated, so this is only set here
+    session.max_rows = Some(max_rows.unwrap_or(DEFAULT_MAX_ROWS));
+
+    let mut rl = Editor::<SqlHelper>::new();
+    rl.set_helper(Some(SqlHelper::default()));
+
+    // lines of a statement that hasn't been terminated with a ; yet
+    let mut buffer = String::new();
+
+    #[cfg(unix)]
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+
+    loop {
+        if watch {
+            if let Err(e) = session.fetch_schemas().await {
+                print_error(session, e);
+            }
+            match session.reload() {
+                Ok(changes) => changes.iter().for_each(|c| eprintln!("{}", c)),
+                Err(e) => print_error(session, e),
+            }
+        }
+
+        if let Some(helper) = rl.helper_mut() {
+            helper.tables = session.tables().clone();
+        }
+
+        let prompt = if buffer.is_empty() { "> " } else { "- " };
+        let (editor, line) = read_line(
+            session,
+            rl,
+            prompt,
+            #[cfg(unix)]
+            &mut hangup,
+        )
+        .await?;
+        rl = editor;
+        let line = match line {
+            Ok(l) => l,
+            // abandon the current statement rather than exiting
+            Err(ReadlineError::Interrupted) if !buffer.is_empty() => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) | Err(ReadlineError::Interrupted) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        if buffer.is_empty() {
+            let trimmed = line.trim();
+
+            if trimmed == "quit" || trimmed == "exit" {
+                break;
+            }
+
+            if let Some(command) = trimmed.strip_prefix('\\') {
+                // saved queries and plans are run here, as meta commands are
+                // synchronous
+                if let Some(args) = statements::keyword(command, "run") {
+                    match saved_query(session, args) {
+                        Ok(Some(sql)) => {
+                            execute(session, &sql).await;
+                        }
+                        Ok(None) => (),
+                        Err(e) => print_error(session, e),
+                    }
+                    continue;
+                }
+                if let Some(query) = statements::keyword(command, "plan") {
+                    let query = query.trim().trim_end_matches(';');
+                    let dry_run = session.dry_run;
+                    session.dry_run = true;
+                    execute(session, query).await;
+                    session.dry_run = dry_run;
+                    continue;
+                }
+                if command
+                    .split_whitespace()
+                    .eq(["admin", "reload"].iter().copied())
+                {
+                    admin_reload(session).await;
+                    continue;
+                }
+                if let Some(table) = statements::keyword(command, "publish") {
+                    match session.publish(table.trim()).await {
+                        Ok(message) => println!("{}", message),
+                        Err(e) => print_error(session, e),
+                    }
+                    continue;
+                }
+                // schemas from a registry collection are fetched before
+                // \reload syncs them
+                if command.trim() == "reload" {
+                    if let Err(e) = session.fetch_schemas().await {
+                        print_error(session, e);
+                        continue;
+                    }
+                }
+                let name = command.split_whitespace().next().unwrap_or_default();
+                if let Some(template) = session.aliases.get(name) {
+                    match expand_alias(template, &command[name.len()..]) {
+                        Ok(sql) => {
+                            let (mut statements, rest) = statements::split(&sql);
+                            if !rest.trim().is_empty() {
+                                statements.push(rest.trim());
+                            }
+                            let statements = statements
+                                .into_iter()
+                                .map(ToOwned::to_owned)
+                                .collect::<Vec<_>>();
+                            for statement in statements {
+                                if !execute(session, &statement).await {
+                                    break;
+                                }
+                            }
+                        }
+                        Err(e) => print_error(session, e),
+                    }
+                    continue;
+                }
+                if let Err(e) = meta_command(session, command) {
+                    print_error(session, e);
+                }
+                continue;
+            }
+
+            if trimmed.is_empty() {
+                continue;
+            }
+        }
+
+        buffer.push_str(&line);
+        buffer.push('\n');
+
+        let (statements, rest) = statements::split(&buffer);
+        let statements = statements
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+        buffer = rest.trim_start().to_owned();
+
+        for statement in statements {
+            if !execute(session, &statement).await {
+                // don't run the rest of the statements after a cancel
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// reads a line on a blocking thread so SIGHUP, like \admin reload, can
+// reload the config while the prompt waits. A SIGHUP during a statement is
+// handled as soon as it finishes, when the next line is read
+async fn read_line(
+    session: &mut Session,
+    mut rl: Editor<SqlHelper>,
+    prompt: &'static str,
+    #[cfg(unix)] hangup: &mut tokio::signal::unix::Signal,
+) -> Result<(Editor<SqlHelper>, rustyline::Result<String>), Box<dyn std::error::Error>> {
+    let mut read = tokio::task::spawn_blocking(move || {
+        let line = rl.readline(prompt);
+        (rl, line)
+    });
+    #[cfg(unix)]
+    loop {
+        let signal = hangup.recv();
+        pin_mut!(signal);
+        match future::select(&mut read, signal).await {
+            Either::Left((read, _)) => return Ok(read?),
+            Either::Right((Some(()), _)) => admin_reload(session).await,
+            // no more signals will arrive
+            Either::Right((None, _)) => break,
+        }
+    }
+    Ok(read.await?)
+}
+
+// runs statement, returning false if it was cancelled with Ctrl-C. Dropping
+// the query future drops the DataFusion streams, which in turn cancels any
+// in progress loads from MongoDB
+async fn execute(session: &mut Session, statement: &str) -> bool {
+    // the spinner would be mixed in with results written as they arrive
+    let show_progress = !session.quiet
+        && atty::is(atty::Stream::Stderr)
+        && !(session.output.is_none() && output::is_streamable(session.format, &session.display));
+    let progress = Progress::start();
+    let start = Instant::now();
+    let result = {
+        let execute = session.execute(statement);
+        let interrupt = tokio::signal::ctrl_c();
+        let spinner = async {
+            if show_progress {
+                progress.run().await
+            } else {
+                future::pending().await
+            }
+        };
+        pin_mut!(execute, interrupt, spinner);
+        match future::select(execute, future::select(interrupt, spinner)).await {
+            Either::Left((result, _)) => Some(result),
+            Either::Right(_) => None,
+        }
+    };
+    progress::clear();
+    match result {
+        Some(Ok(())) => true,
+        Some(Err(e)) => {
+            print_error(session, e);
+            true
+        }
+        None => {
+            eprintln!("cancelled");
+            session.log_cancelled(statement, start.elapsed());
+            false
+        }
+    }
+}
+
+// returns the SQL for `\run name [param=value ...]`, or lists the saved
+// queries if no name is given
+fn saved_query(session: &Session, args: &str) -> Result<Option<String>, String> {
+    let args = args.trim();
+    if args.is_empty() {
+        let mut names = session.saved_queries.keys().collect::<Vec<_>>();
+        names.sort();
+        names.iter().for_each(|name| println!("{}", name));
+        return Ok(None);
+    }
+    let (name, args) = statements::identifier(args).ok_or("expected \\run name")?;
+    let query = session
+        .saved_queries
+        .get(name)
+        .ok_or_else(|| format!("no saved query named {:?}", name))?;
+    query.bind(args).map(Some)
+}
+
+// substitutes the whitespace separated `args` for `$1`, `$2`, ... in an
+// alias. Unlike prepared statements this is plain text substitution, so
+// aliases can take table and column names
+fn expand_alias(template: &str, args: &str) -> Result<String, String> {
+    let mut values = Vec::new();
+    let mut rest = args.trim_start();
+    while !rest.is_empty() {
+        let (value, after) = match statements::quoted(rest) {
+            Some((value, after)) => (value, after),
+            None => {
+                let end = rest.find(char::is_whitespace).unwrap_or_else(|| rest.len());
+                (rest[..end].to_owned(), &rest[end..])
+            }
+        };
+        values.push(value);
+        rest = after.trim_start();
+    }
+
+    let mut expanded = String::with_capacity(template.len());
+    let mut chars = template.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '$' && chars.peek().map_or(false, |&(_, c)| c.is_ascii_digit()) {
+            let mut end = i + 1;
+            while let Some(&(j, c)) = chars.peek() {
+                if !c.is_ascii_digit() {
+                    break;
+                }
+                end = j + 1;
+                chars.next();
+            }
+            let n = template[i + 1..end].parse::<usize>().unwrap_or(0);
+            let value = n
+                .checked_sub(1)
+                .and_then(|i| values.get(i))
+                .ok_or_else(|| format!("no value for ${}, {} given", n, values.len()))?;
+            expanded.push_str(value);
+        } else {
+            expanded.push(c);
+        }
+    }
+    Ok(expanded)
+}
+
+// re-reads the config and schemas, for \admin reload and SIGHUP
+async fn admin_reload(session: &mut Session) {
+    match session.reload_config().await {
+        Ok(changes) => {
+            changes.iter().for_each(|c| eprintln!("{}", c));
+            eprintln!("Configuration reloaded.");
+        }
+        Err(e) => print_error(session, e),
+    }
+}
+
+// handles backslash commands, `command` is the line without the backslash
+fn meta_command(session: &mut Session, command: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut parts = command.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("format"), Some(format)) => session.format = format.parse()?,
+        (Some("format"), None) => println!("{}", session.format),
+        (Some("o"), Some(_)) => {
+            let path = command[1..].trim();
+            session.output = Some(File::create(path)?);
+        }
+        (Some("o"), None) => session.output = None,
+        (Some("timing"), Some("on")) => session.timing = true,
+        (Some("timing"), Some("off")) => session.timing = false,
+        (Some("timing"), None) => {
+            session.timing = !session.timing;
+            println!("Timing is {}.", if session.timing { "on" } else { "off" });
+        }
+        (Some("stats"), Some("on")) => session.server_stats = true,
+        (Some("stats"), Some("off")) => session.server_stats = false,
+        (Some("stats"), None) => {
+            session.server_stats = !session.server_stats;
+            let state = if session.server_stats { "on" } else { "off" };
+            println!("Server stats are {}.", state);
+        }
+        (Some("x"), Some("on")) => session.display.expanded = true,
+        (Some("x"), Some("off")) => session.display.expanded = false,
+        (Some("x"), None) => {
+            session.display.expanded = !session.display.expanded;
+            let state = if session.display.expanded {
+                "on"
+            } else {
+                "off"
+            };
+            println!("Expanded display is {}.", state);
+        }
+        (Some("reload"), None) => {
+            for change in session.reload()? {
+                println!("{}", change);
+            }
+        }
+        (Some("map"), Some(_)) => {
+            let remap = mapping::parse_map(&command[3..])?;
+            println!("{}", session.remap(&remap)?);
+        }
+        (Some("unmap"), Some(_)) => {
+            let remap = mapping::parse_unmap(&command[5..])?;
+            println!("{}", session.remap(&remap)?);
+        }
+        (Some("cache"), Some("clear")) => session.clear_result_cache(),
+        (Some("cache"), Some("off")) => session.result_cache = None,
+        (Some("cache"), Some(secs)) => {
+            let ttl = Duration::from_secs(secs.parse()?);
+            session.result_cache = Some(ResultCache::new(ttl));
+        }
+        (Some("cache"), None) => match session.result_cache {
+            Some(ref cache) => println!(
+                "{} cached {}, kept for {}s",
+                cache.len(),
+                if cache.len() == 1 {
+                    "result"
+                } else {
+                    "results"
+                },
+                cache.ttl().as_secs()
+            ),
+            None => println!("off"),
+        },
+        (Some("max_rows"), Some("off")) => session.max_rows = None,
+        (Some("max_rows"), Some(n)) => session.max_rows = Some(n.parse()?),
+        (Some("max_rows"), None) => match session.max_rows {
+            Some(n) => println!("{}", n),
+            None => println!("off"),
+        },
+        (Some("alias"), Some(name)) => {
+            let definition = command[5..].trim_start()[name.len()..].trim();
+            let template = match statements::quoted(definition) {
+                Some((template, rest)) if rest.trim().is_empty() => template,
+                _ if definition.is_empty() => {
+                    let template = session
+                        .aliases
+                        .get(name)
+                        .ok_or_else(|| format!("no alias named {:?}", name))?;
+                    println!("{}", template);
+                    return Ok(());
+                }
+                _ => definition.to_owned(),
+            };
+            session.aliases.insert(name.to_owned(), template);
+        }
+        (Some("alias"), None) => {
+            let mut aliases = session.aliases.iter().collect::<Vec<_>>();
+            aliases.sort();
+            for (name, template) in aliases {
+                println!("{}: {}", name, template);
+            }
+        }
+        (Some("unalias"), Some(name)) => {
+            session
+                .aliases
+                .remove(name)
+                .ok_or_else(|| format!("no alias named {:?}", name))?;
+        }
+        (Some("record"), Some("off")) => {
+            let transcript = session.transcript.take().ok_or("not recording")?;
+            println!("Stopped recording to {}.", transcript.path().display());
+        }
+        (Some("record"), Some(_)) => {
+            let path = command[6..].trim();
+            session.transcript = Some(Transcript::open(path)?);
+            println!("Recording to {}, \\record off to stop.", path);
+        }
+        (Some("record"), None) => match session.transcript {
+            Some(ref transcript) => println!("Recording to {}.", transcript.path().display()),
+            None => println!("off"),
+        },
+        (Some("next"), None) => session.next_page()?,
+        (Some("prev"), None) => session.prev_page()?,
+        (Some("d"), Some(table)) => session.describe_table(table)?,
+        (Some("d"), None) => {
+            for name in session.tables().keys() {
+                match session.view(name) {
+                    Some(view) => println!("{} (view on {})", name, view.view_on),
+                    None => println!("{}", name),
+                }
+            }
+        }
+        (Some("pset"), Some(option)) => {
+            let value = command[4..].trim_start()[option.len()..].trim();
+            pset(session, option, value)?
+        }
+        _ => return Err(format!("unknown command \\{}", command).into()),
+    }
+    Ok(())
+}
+
+// sets display options with `\pset option value`, or prints the current
+// value if value is empty
+fn pset(
+    session: &mut Session,
+    option: &str,
+    value: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let value = match statements::quoted(value) {
+        Some((unquoted, rest)) if rest.trim().is_empty() => Some(unquoted),
+        _ if value.is_empty() => None,
+        _ => Some(value.to_owned()),
+    };
+    let display = &mut session.display;
+    match (option, value) {
+        ("null", Some(null)) => display.null = null,
+        ("null", None) => println!("{:?}", display.null),
+        ("color", Some(color)) => display.color = on_off(&color)?,
+        ("color", None) => println!("{}", if display.color { "on" } else { "off" }),
+        ("float_precision", Some(p)) if p == "off" => display.float_precision = None,
+        ("float_precision", Some(p)) => display.float_precision = Some(p.parse()?),
+        ("float_precision", None) => match display.float_precision {
+            Some(p) => println!("{}", p),
+            None => println!("off"),
+        },
+        ("timestamp_format", Some(f)) if f == "default" => display.timestamp_format = None,
+        ("timestamp_format", Some(f)) => display.timestamp_format = Some(f),
+        ("timestamp_format", None) => match display.timestamp_format {
+            Some(ref f) => println!("{:?}", f),
+            None => println!("default"),
+        },
+        ("timezone", Some(tz)) => display.timezone = tz.parse()?,
+        ("timezone", None) => println!("{}", display.timezone),
+        ("binary", Some(binary)) => display.binary = binary.parse()?,
+        ("binary", None) => println!("{}", display.binary),
+        ("border", Some(border)) => display.border = border.parse()?,
+        ("border", None) => println!("{}", display.border),
+        ("max_width", Some(w)) if w == "off" => display.max_width = None,
+        ("max_width", Some(w)) => display.max_width = Some(w.parse()?),
+        ("max_width", None) => match display.max_width {
+            Some(w) => println!("{}", w),
+            None => println!("off"),
+        },
+        _ => return Err(format!("unknown option {:?} for \\pset", option).into()),
+    }
+    Ok(())
+}
+
+fn on_off(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "true" => Ok(true),
+        "off" | "false" => Ok(false),
+        _ => Err(format!("expected on or off, got {:?}", value)),
+    }
+}
+
+fn print_error<E: std::fmt::Display>(session: &Session, e: E) {
+    let color = session.display.color;
+    eprintln!("{}", output::paint(color, output::RED, &e.to_string()));
+}
@@ -0,0 +1,117 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use arrow::record_batch::RecordBatch;
+use datafusion::logical_plan::{LogicalPlan, PlanVisitor};
+
+/// Results of recent queries, reused when the same query is run again
+/// within `ttl` and none of the tables it read have been reloaded since.
+pub struct ResultCache {
+    ttl: Duration,
+    entries: HashMap<String, Entry>,
+}
+
+struct Entry {
+    batches: Vec<RecordBatch>,
+    stored_at: Instant,
+    // the version of each table read when the results were stored
+    versions: Vec<(String, u64)>,
+}
+
+impl ResultCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the stored results for `sql`, if they haven't expired and
+    /// `versions` of its tables match those when they were stored.
+    pub fn get(&mut self, sql: &str, versions: &[(String, u64)]) -> Option<Vec<RecordBatch>> {
+        let key = key(sql);
+        let entry = self.entries.get(&key)?;
+        let fresh = entry.stored_at.elapsed() < self.ttl && entry.versions == versions;
+        if fresh {
+            Some(entry.batches.clone())
+        } else {
+            self.entries.remove(&key);
+            None
+        }
+    }
+
+    pub fn insert(&mut self, sql: &str, batches: Vec<RecordBatch>, versions: Vec<(String, u64)>) {
+        self.entries.retain(|_, e| e.stored_at.elapsed() < self.ttl);
+        self.entries.insert(
+            key(sql),
+            Entry {
+                batches,
+                stored_at: Instant::now(),
+                versions,
+            },
+        );
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// The tables read by `plan`.
+pub fn tables(plan: &LogicalPlan) -> Vec<String> {
+    let mut visitor = Tables(Vec::new());
+    // collecting names can't fail
+    let _ = plan.accept(&mut visitor);
+    visitor.0.sort();
+    visitor.0.dedup();
+    visitor.0
+}
+
+struct Tables(Vec<String>);
+
+impl PlanVisitor for Tables {
+    type Error = ();
+
+    fn pre_visit(&mut self, plan: &LogicalPlan) -> Result<bool, Self::Error> {
+        if let LogicalPlan::TableScan { table_name, .. } = plan {
+            self.0.push(table_name.clone());
+        }
+        Ok(true)
+    }
+}
+
+// queries differing only in whitespace outside of quotes, or a trailing
+// semicolon, share an entry
+fn key(sql: &str) -> String {
+    let mut key = String::with_capacity(sql.len());
+    let mut quote = None;
+    let mut space = false;
+    for c in sql.trim().trim_end_matches(';').trim_end().chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c.is_whitespace() => {
+                space = true;
+                continue;
+            }
+            None => (),
+        }
+        if space {
+            key.push(' ');
+            space = false;
+        }
+        key.push(c);
+    }
+    key
+}
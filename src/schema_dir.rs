@@ -0,0 +1,880 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+use arrow::error::ArrowError;
+use chrono::{TimeZone, Utc};
+use datafusion::error::DataFusionError;
+use futures::TryStreamExt;
+use lazy_datafusion::{
+    CacheInvalidation, CacheMetrics, InvalidationHandle, LazyMemTable, LazyMemTableOptions,
+    MemoryLimit,
+};
+use mongodb::{
+    bson::{doc, Bson, Document, Timestamp},
+    options::{ClientOptions, ReplaceOptions},
+    Client, Collection, Database,
+};
+use mongodb_arrow::{ErrorPolicy, MappedSchema};
+use mongodb_datafusion::{
+    datasource::{MongoDbCollection, SharedClient, SnapshotTime},
+    throttle::Throttle,
+    wildcard::{MongoDbWildcard, TimePartitions},
+};
+use serde_json::Value;
+
+use crate::{mapping::Change, session::Session};
+
+const RECONNECT_ATTEMPTS: usize = 5;
+const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+const WAIT_INTERVAL: Duration = Duration::from_secs(1);
+const WAIT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A MongoDB collection backed table, which can be inserted in to.
+pub struct Target {
+    client: SharedClient,
+    db: String,
+    pub schema: MappedSchema,
+    /// for the table's cache, which is stale once the collection is written
+    pub invalidation: InvalidationHandle,
+    metrics: Arc<CacheMetrics>,
+    /// set if the collection is a view, which can be read but not written
+    pub view: Option<View>,
+}
+
+impl Target {
+    pub fn collection(&self) -> Collection {
+        self.database().collection(self.schema.mongodb_collection())
+    }
+
+    pub fn database(&self) -> Database {
+        self.client.get().database(&self.db)
+    }
+
+    /// Changes whenever the table's cached data is loaded or dropped, so
+    /// results read from it can be recognised as stale.
+    pub fn version(&self) -> u64 {
+        self.metrics.loads() + self.metrics.evictions()
+    }
+}
+
+/// The definition of a MongoDB view.
+#[derive(Clone, Debug)]
+pub struct View {
+    /// the collection or view the pipeline is applied to
+    pub view_on: String,
+    pub pipeline: Vec<Document>,
+}
+
+impl View {
+    // from the options of a view in the output of listCollections
+    fn from_options(options: &Document) -> Option<Self> {
+        let view_on = options.get_str("viewOn").ok()?.to_owned();
+        let pipeline = options
+            .get_array("pipeline")
+            .ok()?
+            .iter()
+            .filter_map(|stage| stage.as_document().cloned())
+            .collect();
+        Some(Self { view_on, pipeline })
+    }
+}
+
+/// The schema files tables are registered from, tracked so the session can
+/// be brought up to date as files are added, edited, or removed.
+pub struct SchemaDir {
+    path: PathBuf,
+    client: SharedClient,
+    client_options: ClientOptions,
+    db: String,
+    refresh: Option<Duration>,
+    error_policy: Arc<RwLock<ErrorPolicy>>,
+    snapshot_time: SnapshotTime,
+    throttle: Throttle,
+    sample: Option<i64>,
+    memory_limit: Option<Arc<MemoryLimit>>,
+    // tables are registered as catalog.collection if set
+    catalog: Option<String>,
+    // table name -> collection for each table created
+    targets: HashMap<String, Target>,
+    // schema file -> (modified time, table name)
+    loaded: HashMap<PathBuf, (SystemTime, String)>,
+    // schema file -> modified time, for files that couldn't be loaded, so
+    // they're only reported again once changed
+    failed: HashMap<PathBuf, SystemTime>,
+    // collection schemas are read from instead of files, if set
+    registry: Option<String>,
+    // the registry's documents as of the last fetch, keyed by table name in
+    // place of a file path
+    registry_schemas: HashMap<PathBuf, (SystemTime, Document)>,
+    // collection name -> definition, for the database's views
+    views: HashMap<String, View>,
+}
+
+impl SchemaDir {
+    pub fn new(
+        path: PathBuf,
+        client: Client,
+        client_options: ClientOptions,
+        db: String,
+        refresh: Option<Duration>,
+    ) -> Self {
+        Self {
+            path,
+            client: SharedClient::new(client),
+            client_options,
+            db,
+            refresh,
+            error_policy: Default::default(),
+            snapshot_time: SnapshotTime::default(),
+            throttle: Throttle::default(),
+            sample: None,
+            memory_limit: None,
+            catalog: None,
+            targets: HashMap::new(),
+            loaded: HashMap::new(),
+            failed: HashMap::new(),
+            registry: None,
+            registry_schemas: HashMap::new(),
+            views: HashMap::new(),
+        }
+    }
+
+    /// Reads schemas from the documents of `collection` rather than files,
+    /// so they can be shared by everyone using the database. Each document
+    /// has the table's collection name as its `_id`, and its schema as JSON
+    /// in `schema`.
+    pub fn set_registry(&mut self, collection: String) {
+        self.registry = Some(collection);
+    }
+
+    /// Finds which collections are views, and reads the schemas from the
+    /// registry collection if one is set, ready for the next `validate` or
+    /// `sync`.
+    pub async fn fetch(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.fetch_views().await;
+
+        let registry = match self.registry {
+            Some(ref registry) => registry,
+            None => return Ok(()),
+        };
+        let documents = self
+            .client
+            .get()
+            .database(&self.db)
+            .collection(registry)
+            .find(None, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        self.registry_schemas = documents
+            .into_iter()
+            .map(|doc| {
+                let name = match doc.get("_id") {
+                    Some(Bson::String(name)) => name.clone(),
+                    Some(id) => id.to_string(),
+                    None => String::new(),
+                };
+                let updated_at = doc
+                    .get_datetime("updated_at")
+                    .map_or(SystemTime::UNIX_EPOCH, |t| SystemTime::from(*t));
+                (PathBuf::from(name), (updated_at, doc))
+            })
+            .collect();
+        Ok(())
+    }
+
+    // a failure here only means views aren't marked as such, so it's
+    // logged rather than stopping the schemas being read
+    async fn fetch_views(&mut self) {
+        let views = self
+            .client
+            .get()
+            .database(&self.db)
+            .list_collections(doc! { "type": "view" }, None)
+            .await;
+        let views = match views {
+            Ok(cursor) => cursor.try_collect::<Vec<_>>().await,
+            Err(e) => Err(e),
+        };
+        let views = match views {
+            Ok(views) => views,
+            Err(e) => {
+                tracing::warn!(db = %self.db, error = %e, "couldn't list views");
+                return;
+            }
+        };
+        self.views = views
+            .iter()
+            .filter_map(|info| {
+                let name = info.get_str("name").ok()?;
+                let view = View::from_options(info.get_document("options").ok()?)?;
+                Some((name.to_owned(), view))
+            })
+            .collect();
+        for target in self.targets.values_mut() {
+            if target.db == self.db {
+                target.view = self.views.get(target.schema.mongodb_collection()).cloned();
+            }
+        }
+    }
+
+    /// Writes the current schema of the table `name`, including any changes
+    /// made with `remap`, to the registry collection.
+    pub async fn publish(&mut self, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let registry = self
+            .registry
+            .clone()
+            .ok_or("no schema registry, start with --schema-collection to use one")?;
+        let target = self
+            .targets
+            .get(name)
+            .ok_or_else(|| format!("{} isn't a MongoDB table", name))?;
+        let collection = target.schema.mongodb_collection().to_owned();
+        // BSON dates only hold milliseconds, so truncate to match what the
+        // next fetch will see
+        let updated_at = Utc.timestamp_millis(Utc::now().timestamp_millis());
+        let document = doc! {
+            "_id": &collection,
+            "schema": target.schema.to_json().to_string(),
+            "updated_at": updated_at,
+        };
+        let options = ReplaceOptions::builder().upsert(Some(true)).build();
+        self.client
+            .get()
+            .database(&self.db)
+            .collection(&registry)
+            .replace_one(doc! { "_id": &collection }, document.clone(), options)
+            .await?;
+
+        // recorded as loaded, so the next sync doesn't reload the table
+        let key = PathBuf::from(&collection);
+        let updated_at = SystemTime::from(updated_at);
+        self.registry_schemas
+            .insert(key.clone(), (updated_at, document));
+        self.loaded.insert(key, (updated_at, name.to_owned()));
+        Ok(format!("published {} to {}", name, registry))
+    }
+
+    /// Checks every schema file, returning a description of each problem:
+    /// files that can't be read or use unsupported types, which `sync` will
+    /// skip, tables defined more than once, and collections missing from the
+    /// database, which may just not have been written to yet. Only fails if
+    /// the directory itself can't be read.
+    pub async fn validate(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut problems = Vec::new();
+
+        let collections = match self
+            .client
+            .get()
+            .database(&self.db)
+            .list_collection_names(None)
+            .await
+        {
+            Ok(names) => Some(names),
+            Err(e) => {
+                problems.push(format!("couldn't list collections in {}: {}", self.db, e));
+                None
+            }
+        };
+
+        let mut paths = self
+            .listing()?
+            .into_iter()
+            .map(|(p, _)| p)
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut defined: HashMap<String, PathBuf> = HashMap::new();
+        for path in paths {
+            let schema = match self.read(&path) {
+                Ok(schema) => schema,
+                Err(e) => {
+                    problems.push(format!("{}: {}, skipping", path.display(), e));
+                    continue;
+                }
+            };
+            let name = schema.mongodb_collection();
+            if let Some(other) = defined.insert(name.to_owned(), path.clone()) {
+                problems.push(format!(
+                    "{}: table {} is also defined by {}",
+                    path.display(),
+                    name,
+                    other.display()
+                ));
+            }
+            if let Some(ref collections) = collections {
+                if !collections.iter().any(|c| matches_collection(name, c)) {
+                    problems.push(format!(
+                        "{}: no collection {} in database {}",
+                        path.display(),
+                        name,
+                        self.db
+                    ));
+                }
+            }
+        }
+
+        // the same files are checked against each attached cluster
+        if let Some(ref catalog) = self.catalog {
+            for problem in &mut problems {
+                *problem = format!("{}: {}", catalog, problem);
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Registers tables for new or modified schema files, and unregisters
+    /// those whose file has been removed. Returns a description of each
+    /// change made. Files that can't be loaded are skipped, leaving any table
+    /// previously loaded from them in place.
+    pub fn sync(
+        &mut self,
+        session: &mut Session,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let _span = tracing::info_span!("sync_schema_dir", path = %self.path.display()).entered();
+
+        let current = self.listing()?;
+
+        let mut changes = Vec::new();
+
+        let removed = self
+            .loaded
+            .keys()
+            .filter(|p| !current.contains_key(*p))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.failed.retain(|p, _| current.contains_key(p));
+        for path in removed {
+            if let Some((_, name)) = self.loaded.remove(&path) {
+                self.targets.remove(&name);
+                session.deregister_table(&name);
+                changes.push(format!("unregistered {}", name));
+            }
+        }
+
+        let mut paths = current.into_iter().collect::<Vec<_>>();
+        paths.sort();
+        for (path, modified) in paths {
+            let previous = match self.loaded.get(&path) {
+                Some((m, _)) if *m == modified => continue,
+                Some((_, name)) => Some(name.clone()),
+                None => None,
+            };
+
+            if self.failed.get(&path) == Some(&modified) {
+                continue;
+            }
+
+            tracing::debug!(path = %path.display(), "reading schema");
+            let schema = match self.read(&path) {
+                Ok(schema) => schema,
+                Err(e) => {
+                    tracing::warn!(path = %path.display(), error = %e, "skipping schema");
+                    changes.push(format!("skipped {}: {}", path.display(), e));
+                    self.failed.insert(path, modified);
+                    continue;
+                }
+            };
+            self.failed.remove(&path);
+            let name = match self.catalog {
+                Some(ref catalog) => format!("{}.{}", catalog, table_name(&schema)),
+                None => table_name(&schema),
+            };
+            let table = self.table(&name, None, schema)?;
+
+            if let Some(ref previous) = previous {
+                if *previous != name {
+                    self.targets.remove(previous);
+                    session.deregister_table(previous);
+                }
+            }
+            session.register_table(&name, Box::new(table));
+            changes.push(match previous {
+                Some(_) => format!("reloaded {}", name),
+                None => format!("registered {}", name),
+            });
+            self.loaded.insert(path, (modified, name));
+        }
+
+        Ok(changes)
+    }
+
+    /// Reads the schema of the table for `collection`.
+    pub fn schema(&self, collection: &str) -> Result<MappedSchema, Box<dyn std::error::Error>> {
+        let mut paths = self
+            .listing()?
+            .into_iter()
+            .map(|(p, _)| p)
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+            .iter()
+            .filter_map(|path| self.read(path).ok())
+            .find(|schema| schema.mongodb_collection() == collection)
+            .ok_or_else(|| format!("no schema for {}", collection).into())
+    }
+
+    // each schema file, or registry entry, with when it last changed
+    fn listing(&self) -> Result<HashMap<PathBuf, SystemTime>, Box<dyn std::error::Error>> {
+        if self.registry.is_some() {
+            return Ok(self
+                .registry_schemas
+                .iter()
+                .map(|(key, (updated_at, _))| (key.clone(), *updated_at))
+                .collect());
+        }
+        let mut listing = HashMap::new();
+        for entry in self.path.read_dir()? {
+            let path = entry?.path();
+            let modified = path.metadata()?.modified()?;
+            listing.insert(path, modified);
+        }
+        Ok(listing)
+    }
+
+    fn read(&self, key: &Path) -> Result<MappedSchema, Box<dyn std::error::Error>> {
+        if self.registry.is_none() {
+            return read_schema(key);
+        }
+        let (_, document) = self
+            .registry_schemas
+            .get(key)
+            .ok_or_else(|| format!("{} removed from registry", key.display()))?;
+        let value = serde_json::from_str(document.get_str("schema")?)?;
+        let schema = MappedSchema::from_json(key.display().to_string(), &value)?;
+        check_supported(&schema)?;
+        Ok(schema)
+    }
+
+    /// How tables created by this handle values that don't match their
+    /// schema. Takes effect the next time a table is loaded.
+    pub fn set_error_policy(&self, error_policy: ErrorPolicy) {
+        *self
+            .error_policy
+            .write()
+            .expect("error policy lock poisoned") = error_policy;
+    }
+
+    /// The cluster time tables created by this read at, or None for the
+    /// latest data. Cached data is dropped so it's read again at that time.
+    pub async fn set_snapshot_time(&self, time: Option<Timestamp>) {
+        self.snapshot_time.set(time);
+        for target in self.targets.values() {
+            target.invalidation.invalidate().await;
+        }
+    }
+
+    /// Registers tables as `catalog.collection`, so tables from several
+    /// clusters can be used side by side.
+    pub fn set_catalog(&mut self, catalog: String) {
+        self.catalog = Some(catalog);
+    }
+
+    /// Limits tables created from now on to a random sample of at most
+    /// `size` documents.
+    pub fn set_sample(&mut self, size: Option<i64>) {
+        self.sample = size;
+    }
+
+    /// Applies `throttle`'s limits to the scans of tables created from now
+    /// on.
+    pub fn set_throttle(&mut self, throttle: Throttle) {
+        self.throttle = throttle;
+    }
+
+    /// Counts data loaded by tables created from now on against `limit`.
+    pub fn set_memory_limit(&mut self, limit: Arc<MemoryLimit>) {
+        self.memory_limit = Some(limit);
+    }
+
+    /// Creates a cached table reading `schema`'s collection from the
+    /// database `db`, or the default database if None. The collection is
+    /// recorded as the target for inserts in to the table `name`.
+    pub fn table(
+        &mut self,
+        name: &str,
+        db: Option<&str>,
+        schema: MappedSchema,
+    ) -> Result<LazyMemTable, Box<dyn std::error::Error>> {
+        let db = db.unwrap_or(&self.db).to_owned();
+        let options = LazyMemTableOptions {
+            memory_limit: self.memory_limit.clone(),
+            ..Default::default()
+        };
+        let table = if is_wildcard(schema.mongodb_collection()) {
+            LazyMemTable::new_with_options(self.wildcard(db.clone(), schema.clone())?, options)
+        } else {
+            let table = MongoDbCollection::new_with_shared_client(
+                self.client.clone(),
+                db.clone(),
+                schema.clone(),
+                self.error_policy.clone(),
+            )
+            .with_snapshot_time(self.snapshot_time.clone())
+            .with_throttle(self.throttle.clone());
+            let table = match self.sample {
+                Some(size) => table.with_sample(size),
+                None => table,
+            };
+            LazyMemTable::new_with_options(table, options)
+        };
+        if let Some(interval) = self.refresh {
+            table.refresh_every(interval)?;
+        }
+        // views are only known for the default database
+        let view = if db == self.db {
+            self.views.get(schema.mongodb_collection()).cloned()
+        } else {
+            None
+        };
+        let target = Target {
+            client: self.client.clone(),
+            db,
+            view,
+            schema,
+            invalidation: table.invalidation_handle(),
+            metrics: table.metrics(),
+        };
+        self.targets.insert(name.to_owned(), target);
+        Ok(table)
+    }
+
+    // reads every collection matching the pattern in `schema`, skipping
+    // those outside the time range queried if the schema's
+    // partition_column and partition_format metadata are set
+    fn wildcard(
+        &self,
+        db: String,
+        schema: MappedSchema,
+    ) -> Result<MongoDbWildcard, Box<dyn std::error::Error>> {
+        let partitions = match (
+            schema.metadata().get("partition_column"),
+            schema.metadata().get("partition_format"),
+        ) {
+            (Some(column), Some(format)) => Some(TimePartitions {
+                column: column.clone(),
+                format: format.clone(),
+            }),
+            (None, None) => None,
+            _ => return Err("set both partition_column and partition_format, or neither".into()),
+        };
+        let table =
+            MongoDbWildcard::new(self.client.clone(), db, schema, self.error_policy.clone())?
+                .with_snapshot_time(self.snapshot_time.clone())
+                .with_throttle(self.throttle.clone());
+        let table = match self.sample {
+            Some(size) => table.with_sample(size),
+            None => table,
+        };
+        Ok(match partitions {
+            Some(partitions) => table.with_partitions(partitions),
+            None => table,
+        })
+    }
+
+    /// Changes the mapping of the table `name` and re-registers it,
+    /// optionally writing the new mapping back to its schema file. Returns a
+    /// description of the change.
+    pub fn remap(
+        &mut self,
+        session: &mut Session,
+        name: &str,
+        change: &Change,
+        save: bool,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let target = self
+            .targets
+            .get(name)
+            .ok_or_else(|| format!("{} isn't a MongoDB table", name))?;
+        let db = target.db.clone();
+        let mut fields = target.schema.fields().clone();
+        let description = change.apply(&mut fields)?;
+        let schema = MappedSchema::new_with_metadata(
+            target.schema.mongodb_collection().to_owned(),
+            fields,
+            target.schema.metadata().clone(),
+        );
+
+        // find the file before changing anything, so a failed save doesn't
+        // leave the table changed
+        if save && self.registry.is_some() {
+            return Err("schemas are in the registry, save with \\publish".into());
+        }
+        let path = if save {
+            let path = self
+                .loaded
+                .iter()
+                .find(|(_, (_, n))| n == name)
+                .map(|(path, _)| path.clone())
+                .ok_or_else(|| format!("{} wasn't loaded from a schema file", name))?;
+            Some(path)
+        } else {
+            None
+        };
+
+        let table = self.table(name, Some(&db), schema.clone())?;
+        session.register_table(name, Box::new(table));
+
+        if let Some(path) = path {
+            let yaml = matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("yaml") | Some("yml")
+            );
+            write_schema(File::create(&path)?, &schema, yaml)?;
+            // so the next sync doesn't reload the file we just wrote
+            let modified = path.metadata()?.modified()?;
+            self.loaded
+                .insert(path.clone(), (modified, name.to_owned()));
+            return Ok(format!("{}, saved to {}", description, path.display()));
+        }
+        Ok(description)
+    }
+
+    pub fn target(&self, name: &str) -> Option<&Target> {
+        self.targets.get(name)
+    }
+
+    /// Connects to `uri` in place of the current cluster, keeping any TLS
+    /// and credential settings not in the connection string. Scans already
+    /// running finish on the old connection, and cached data is dropped.
+    pub async fn set_uri(&mut self, uri: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut options = ClientOptions::parse(uri).await?;
+        if options.tls.is_none() {
+            options.tls = self.client_options.tls.clone();
+        }
+        if options.credential.is_none() {
+            options.credential = self.client_options.credential.clone();
+        }
+        let client = connect(options.clone()).await?;
+        self.client.replace(client);
+        self.client_options = options;
+        for target in self.targets.values() {
+            target.invalidation.invalidate().await;
+        }
+        Ok(())
+    }
+
+    /// Replaces the client with a new connection, retrying with backoff.
+    /// Tables keep any data they have cached, and those that failed to load
+    /// will try again on their next scan.
+    pub async fn reconnect(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut backoff = RECONNECT_INITIAL_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match connect(self.client_options.clone()).await {
+                Ok(client) => {
+                    self.client.replace(client);
+                    for target in self.targets.values() {
+                        target.invalidation.clear_failure();
+                    }
+                    return Ok(());
+                }
+                Err(e) if attempt < RECONNECT_ATTEMPTS => {
+                    tracing::warn!(attempt, error = %e, "reconnect failed, retrying");
+                    tokio::time::delay_for(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// creates a client and checks the server can be reached, as the client
+// itself connects lazily
+async fn connect(options: ClientOptions) -> Result<Client, Box<dyn std::error::Error>> {
+    let client = Client::with_options(options)?;
+    client
+        .database("admin")
+        .run_command(doc! {"ping": 1}, None)
+        .await?;
+    Ok(client)
+}
+
+/// Pings the server until it responds or `timeout` has passed, for when
+/// bishop is started alongside mongod. Authentication failures aren't
+/// retried, as waiting won't fix them.
+pub async fn wait_for_server(
+    options: &ClientOptions,
+    timeout: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let deadline = Instant::now() + timeout;
+    let mut options = options.clone();
+    options.server_selection_timeout = Some(WAIT_ATTEMPT_TIMEOUT);
+    loop {
+        match connect(options.clone()).await {
+            Ok(_) => return Ok(()),
+            Err(e) if !is_auth_error(&*e) && Instant::now() < deadline => {
+                tracing::info!(error = %e, "waiting for MongoDB");
+                tokio::time::delay_for(WAIT_INTERVAL).await;
+            }
+            Err(e) => return Err(diagnose(&options, &*e).into()),
+        }
+    }
+}
+
+// describes a failure to connect, with the hosts tried and whether it was
+// the credentials or the network at fault
+fn diagnose(options: &ClientOptions, e: &(dyn std::error::Error + 'static)) -> String {
+    let hosts = options
+        .hosts
+        .iter()
+        .map(|h| format!("{}:{}", h.hostname, h.port.unwrap_or(27017)))
+        .collect::<Vec<_>>()
+        .join(",");
+    let cause = if is_auth_error(e) {
+        let user = options
+            .credential
+            .as_ref()
+            .and_then(|c| c.username.as_deref())
+            .unwrap_or("<none>");
+        format!("authentication failed for user {}", user)
+    } else if is_connection_error(e) {
+        "network error, is the server running and reachable?".to_owned()
+    } else {
+        "error".to_owned()
+    };
+    format!("couldn't connect to MongoDB at {}: {}: {}", hosts, cause, e)
+}
+
+fn is_auth_error(e: &(dyn std::error::Error + 'static)) -> bool {
+    mongodb_error(e).map_or(false, |e| {
+        matches!(
+            e.kind.as_ref(),
+            mongodb::error::ErrorKind::AuthenticationError { .. }
+        )
+    })
+}
+
+/// Returns true if `e` looks like the connection to MongoDB was lost.
+///
+/// Errors from a table's cache are flattened to a string, so if the
+/// driver's error isn't available this goes by the message.
+pub fn is_connection_error(e: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(e) = mongodb_error(e) {
+        return matches!(
+            e.kind.as_ref(),
+            mongodb::error::ErrorKind::Io(_)
+                | mongodb::error::ErrorKind::ServerSelectionError { .. }
+        );
+    }
+    let message = e.to_string().to_ascii_lowercase();
+    [
+        "i/o error",
+        "connection reset",
+        "broken pipe",
+        "connection refused",
+        "server selection timeout",
+        "connection pool",
+    ]
+    .iter()
+    .any(|m| message.contains(m))
+}
+
+// the driver's error behind e, which may have been carried through
+// DataFusion and Arrow errors
+fn mongodb_error<'a>(
+    e: &'a (dyn std::error::Error + 'static),
+) -> Option<&'a mongodb::error::Error> {
+    if let Some(e) = e.downcast_ref::<mongodb::error::Error>() {
+        return Some(e);
+    }
+    let arrow = match e.downcast_ref::<DataFusionError>() {
+        Some(DataFusionError::ArrowError(e)) => e,
+        _ => e.downcast_ref::<ArrowError>()?,
+    };
+    match arrow {
+        ArrowError::ExternalError(e) => match e.downcast_ref::<mongodb_datafusion::Error>()? {
+            mongodb_datafusion::Error::MongoDb(e) => Some(e),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns true if `collection` is a pattern, such as `events_*`, read as
+/// one table from every matching collection.
+pub fn is_wildcard(collection: &str) -> bool {
+    collection.contains('*')
+}
+
+// the table a schema is registered as, its collection, or for a pattern its
+// collection without the wildcard, e.g. events for events_*
+fn table_name(schema: &MappedSchema) -> String {
+    let collection = schema.mongodb_collection();
+    if !is_wildcard(collection) {
+        return collection.to_owned();
+    }
+    collection
+        .replace('*', "")
+        .trim_matches(|c| c == '_' || c == '-' || c == '.')
+        .to_owned()
+}
+
+// whether `collection` is `name`, or matches it if it's a pattern
+fn matches_collection(name: &str, collection: &str) -> bool {
+    match name.find('*') {
+        Some(i) => {
+            collection.len() >= name.len() - 1
+                && collection.starts_with(&name[..i])
+                && collection.ends_with(&name[i + 1..])
+        }
+        None => name == collection,
+    }
+}
+
+fn read_schema<P: AsRef<Path>>(path: P) -> Result<MappedSchema, Box<dyn std::error::Error>> {
+    let file = File::open(path.as_ref())?;
+    let buf_reader = BufReader::new(file);
+
+    let value: Value = match path.as_ref().extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_reader(buf_reader)?,
+        _ => serde_json::from_reader(buf_reader)?,
+    };
+
+    let mongodb_collection = path
+        .as_ref()
+        .file_stem()
+        .and_then(|e| e.to_str())
+        .unwrap()
+        .to_owned();
+    let schema = MappedSchema::from_json(mongodb_collection, &value)?;
+    check_supported(&schema)?;
+    Ok(schema)
+}
+
+fn check_supported(schema: &MappedSchema) -> Result<(), Box<dyn std::error::Error>> {
+    let unsupported = schema
+        .fields()
+        .iter()
+        .filter(|f| !mongodb_arrow::is_supported(f.data_type()))
+        .map(|f| format!("{} ({})", f.name(), f.data_type()))
+        .collect::<Vec<_>>();
+    if !unsupported.is_empty() {
+        return Err(format!("unsupported column types: {}", unsupported.join(", ")).into());
+    }
+    Ok(())
+}
+
+/// Writes `schema` as JSON or YAML in the format read by `read_schema`.
+pub fn write_schema<W: Write>(
+    mut writer: W,
+    schema: &MappedSchema,
+    yaml: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let value = schema.to_json();
+    if yaml {
+        serde_yaml::to_writer(&mut writer, &value)?;
+    } else {
+        serde_json::to_writer_pretty(&mut writer, &value)?;
+    }
+    writeln!(writer)?;
+    Ok(())
+}
@@ -0,0 +1,1152 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fs::File,
+    io::{self, Write},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use arrow::{
+    array::{ArrayRef, BooleanBuilder, StringBuilder},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    error::ArrowError,
+    record_batch::RecordBatch,
+};
+use chrono::DateTime;
+use datafusion::{
+    datasource::{MemTable, TableProvider},
+    error::DataFusionError,
+    execution::context::ExecutionContext,
+    logical_plan::LogicalPlan,
+    physical_plan::{merge::MergeExec, ExecutionPlan},
+};
+use futures::StreamExt;
+use lazy_datafusion::{CacheInvalidation, MemoryLimit, MemoryLimitExceeded};
+use mongodb::bson::Timestamp;
+
+use crate::{
+    config::{Config, SavedQuery},
+    copy, ddl, describe, explain, insert,
+    mapping::Remap,
+    output::{self, DisplayOptions, Format},
+    prepare::{self, Prepared},
+    progress,
+    query_log::QueryLog,
+    result_cache::{self, ResultCache},
+    schema_dir::{self, SchemaDir, Target, View},
+    set, statements,
+    transcript::{self, Transcript},
+};
+
+// pages of a streamed result kept for \next, beyond that it's read only to
+// count the rows
+const MAX_KEPT_PAGES: usize = 100;
+
+pub struct Session {
+    pub context: ExecutionContext,
+    pub format: Format,
+    /// where results are written, stdout if None
+    pub output: Option<File>,
+    /// report how long each query took
+    pub timing: bool,
+    /// report the documents MongoDB examined for each query, and the
+    /// indexes it used
+    pub server_stats: bool,
+    /// how results are shown in the terminal
+    pub display: DisplayOptions,
+    /// don't print row counts, timings, or status messages
+    pub quiet: bool,
+    /// reject statements that write data
+    pub readonly: bool,
+    /// print the plan for statements that would read or write data, rather
+    /// than running them
+    pub dry_run: bool,
+    /// limit on rows printed to the terminal, exports are never truncated
+    pub max_rows: Option<usize>,
+    /// MongoDB tables are sampled to at most this many documents, noted
+    /// with each result
+    pub sample: Option<i64>,
+    /// where tables are loaded from, see `reload`
+    pub schema_dir: Option<SchemaDir>,
+    /// schema directories for other clusters, with their tables registered
+    /// under a catalog name
+    pub attached: Vec<SchemaDir>,
+    /// where executed statements are recorded
+    pub query_log: Option<QueryLog>,
+    /// Markdown file statements and their results are recorded to, set with
+    /// `\record`
+    pub transcript: Option<Transcript>,
+    /// queries from the config file, run with `\run`
+    pub saved_queries: HashMap<String, SavedQuery>,
+    /// `\name args` shortcuts, expanded with `$1`, `$2`, ... replaced by args
+    pub aliases: HashMap<String, String>,
+    /// the config file profile in use, "default" if None
+    pub profile: Option<String>,
+    /// the profile's connection string, if that's what's connected to
+    /// rather than one given on the command line
+    pub profile_uri: Option<String>,
+    /// reuses the results of recently run queries if set
+    pub result_cache: Option<ResultCache>,
+    /// budget for the MongoDB data each query loads, shared with the schema
+    /// directories' tables
+    pub memory_limit: Option<Arc<MemoryLimit>>,
+    tables: BTreeMap<String, SchemaRef>,
+    prepared: HashMap<String, String>,
+    // rows returned or affected by the last statement, for the query log
+    rows: Option<usize>,
+    // the last result if it was too long to show at once, for \next and
+    // \prev
+    pages: Option<Pages>,
+    materialized: HashMap<String, Materialized>,
+}
+
+// a query's result kept as a table, run again every `refresh`
+struct Materialized {
+    query: String,
+    refresh: Option<Duration>,
+    refreshed: Instant,
+}
+
+impl Materialized {
+    fn is_due(&self) -> bool {
+        self.refresh
+            .map_or(false, |refresh| self.refreshed.elapsed() >= refresh)
+    }
+}
+
+// a result shown `page_size` rows at a time
+struct Pages {
+    batches: Vec<RecordBatch>,
+    rows: usize,
+    // rows in the whole result, more than kept if it was too long
+    total: usize,
+    page_size: usize,
+    page: usize,
+}
+
+impl Pages {
+    fn new(batches: Vec<RecordBatch>, page_size: usize) -> Self {
+        let rows = batches.iter().map(|b| b.num_rows()).sum();
+        Self::new_partial(batches, rows, page_size)
+    }
+
+    fn new_partial(batches: Vec<RecordBatch>, total: usize, page_size: usize) -> Self {
+        let rows = batches.iter().map(|b| b.num_rows()).sum();
+        Self {
+            batches,
+            rows,
+            total,
+            page_size: page_size.max(1),
+            page: 0,
+        }
+    }
+
+    fn count(&self) -> usize {
+        (self.rows + self.page_size - 1) / self.page_size
+    }
+}
+
+impl Session {
+    pub fn new(context: ExecutionContext, format: Format) -> Self {
+        Self {
+            context,
+            format,
+            output: None,
+            timing: false,
+            server_stats: false,
+            display: DisplayOptions::default(),
+            quiet: false,
+            readonly: false,
+            dry_run: false,
+            max_rows: None,
+            sample: None,
+            schema_dir: None,
+            attached: Vec::new(),
+            query_log: None,
+            transcript: None,
+            saved_queries: HashMap::new(),
+            aliases: HashMap::new(),
+            profile: None,
+            profile_uri: None,
+            result_cache: None,
+            memory_limit: None,
+            tables: BTreeMap::new(),
+            prepared: HashMap::new(),
+            rows: None,
+            pages: None,
+            materialized: HashMap::new(),
+        }
+    }
+
+    pub fn register_table(&mut self, name: &str, table: Box<dyn TableProvider + Send + Sync>) {
+        tracing::info!(table = name, "registering table");
+        self.clear_result_cache();
+        self.tables.insert(name.to_owned(), table.schema());
+        self.context.register_table(name, table);
+    }
+
+    pub fn deregister_table(&mut self, name: &str) {
+        tracing::info!(table = name, "unregistering table");
+        self.clear_result_cache();
+        self.tables.remove(name);
+        self.context
+            .state
+            .lock()
+            .expect("execution context state mutex poisoned")
+            .datasources
+            .remove(name);
+    }
+
+    /// Brings the registered tables up to date with the schema directories,
+    /// returning a description of each change.
+    pub fn reload(&mut self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut changes = Vec::new();
+        if let Some(mut schema_dir) = self.schema_dir.take() {
+            let result = schema_dir.sync(self);
+            self.schema_dir = Some(schema_dir);
+            changes = result?;
+        }
+
+        let mut attached = std::mem::take(&mut self.attached);
+        let result = attached
+            .iter_mut()
+            .map(|s| s.sync(self))
+            .collect::<Result<Vec<_>, _>>();
+        self.attached = attached;
+        changes.extend(result?.into_iter().flatten());
+        Ok(changes)
+    }
+
+    /// Re-reads the config file's saved queries and aliases, reconnects if
+    /// the profile's connection string changed, and reloads the schema
+    /// directory. Queries already running finish on the old connection.
+    /// Returns a description of each change.
+    pub async fn reload_config(&mut self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let config = Config::load()?;
+        let profile = config.profile(self.profile.as_deref())?;
+        self.saved_queries = config.queries;
+        self.aliases = config.aliases;
+
+        let mut changes = Vec::new();
+        if let (Some(old), Some(new)) = (&self.profile_uri, profile.mongodb) {
+            if *old != new {
+                self.schema_dir
+                    .as_mut()
+                    .ok_or("no MongoDB connection")?
+                    .set_uri(&new)
+                    .await?;
+                // the new cluster may not have the same data
+                self.clear_result_cache();
+                self.profile_uri = Some(new);
+                changes.push("reconnected with the profile's new connection string".to_owned());
+            }
+        }
+
+        self.fetch_schemas().await?;
+        changes.extend(self.reload()?);
+        Ok(changes)
+    }
+
+    /// Fetches the schemas of any directories reading from a registry
+    /// collection, for the next `reload`.
+    pub async fn fetch_schemas(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        for schema_dir in self.schema_dir.iter_mut().chain(self.attached.iter_mut()) {
+            schema_dir.fetch().await?;
+        }
+        Ok(())
+    }
+
+    /// Writes a MongoDB table's schema to its registry collection, see
+    /// `SchemaDir::publish`.
+    pub async fn publish(&mut self, table: &str) -> Result<String, Box<dyn std::error::Error>> {
+        if self.readonly {
+            return Err("can't write in read only mode".into());
+        }
+        let schema_dir = self
+            .schema_dir
+            .iter_mut()
+            .chain(self.attached.iter_mut())
+            .find(|s| s.target(table).is_some())
+            .ok_or_else(|| format!("{} isn't a MongoDB table", table))?;
+        schema_dir.publish(table).await
+    }
+
+    /// Changes the mapping of a MongoDB table, see `SchemaDir::remap`.
+    pub fn remap(&mut self, remap: &Remap) -> Result<String, Box<dyn std::error::Error>> {
+        if let Some(i) = self
+            .attached
+            .iter()
+            .position(|s| s.target(remap.table).is_some())
+        {
+            let mut schema_dir = self.attached.remove(i);
+            let result = schema_dir.remap(self, remap.table, &remap.change, remap.save);
+            self.attached.insert(i, schema_dir);
+            return result;
+        }
+        let mut schema_dir = self
+            .schema_dir
+            .take()
+            .ok_or_else(|| format!("{} isn't a MongoDB table", remap.table))?;
+        let result = schema_dir.remap(self, remap.table, &remap.change, remap.save);
+        self.schema_dir = Some(schema_dir);
+        result
+    }
+
+    /// Writes the columns of `table`, followed by the pipeline defining it if
+    /// it's a MongoDB view.
+    pub fn describe_table(&mut self, table: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let batch = self.describe(table)?;
+        self.display_batches(&[batch])?;
+        if let Some(view) = self.view(table) {
+            println!("View on {}, pipeline:", view.view_on);
+            for stage in &view.pipeline {
+                println!("  {}", stage);
+            }
+        }
+        Ok(())
+    }
+
+    /// The definition of `table` if it's a MongoDB view.
+    pub fn view(&self, table: &str) -> Option<&View> {
+        self.target(table).and_then(|t| t.view.as_ref())
+    }
+
+    // the MongoDB collection behind table, from any attached cluster
+    fn target(&self, table: &str) -> Option<&Target> {
+        self.schema_dir
+            .iter()
+            .chain(&self.attached)
+            .find_map(|s| s.target(table))
+    }
+
+    pub fn tables(&self) -> &BTreeMap<String, SchemaRef> {
+        &self.tables
+    }
+
+    /// Runs `sql`, reconnecting and running it again once if it failed
+    /// because the connection to MongoDB was lost.
+    #[tracing::instrument(level = "info", skip(self))]
+    pub async fn execute(&mut self, sql: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let start = Instant::now();
+        self.rows = None;
+        if let Some(ref memory_limit) = self.memory_limit {
+            memory_limit.reset();
+        }
+        self.record(|transcript| transcript.statement(sql));
+        if self.may_read_views(sql) {
+            self.refresh_materialized().await;
+        }
+        let result = self.execute_with_reconnect(sql).await.map_err(|e| {
+            if is_memory_limit_error(&*e) {
+                format!("{}, add filters or raise --max-memory", e).into()
+            } else {
+                e
+            }
+        });
+        if let Some(ref mut log) = self.query_log {
+            let error = result.as_ref().err().map(|e| e.to_string());
+            if let Err(e) = log.record(sql, start.elapsed(), self.rows, error.as_deref()) {
+                tracing::error!(error = %e, "failed to write query log");
+            }
+        }
+        if let Err(ref e) = result {
+            self.record(|transcript| transcript.message(&format!("Error: {}", e)));
+        }
+        if result.is_ok() && self.server_stats && !self.dry_run && !self.quiet {
+            self.report_server_work(sql).await;
+        }
+        result
+    }
+
+    /// Records a statement cancelled before it finished, which `execute`
+    /// can't as it's dropped part way through.
+    pub fn log_cancelled(&mut self, sql: &str, elapsed: Duration) {
+        if let Some(ref mut log) = self.query_log {
+            if let Err(e) = log.record(sql, elapsed, None, Some("cancelled")) {
+                tracing::error!(error = %e, "failed to write query log");
+            }
+        }
+        self.record(|transcript| transcript.message("Cancelled"));
+    }
+
+    // explains the MongoDB commands `sql` sends, which runs them again, and
+    // writes the work each took to stderr
+    async fn report_server_work(&self, sql: &str) {
+        let query = match dry_run_query(sql) {
+            Some(Ok(query)) => query,
+            _ => return,
+        };
+        let is_mongodb = |table: &str| self.target(table).is_some();
+        // statements that aren't queries, such as SET, don't plan
+        let queries = match explain::mongodb_queries(&self.context, query, is_mongodb) {
+            Ok(queries) => queries,
+            Err(e) => {
+                tracing::debug!(error = %e, "no MongoDB queries to explain");
+                return;
+            }
+        };
+        for (table, command) in queries {
+            let db = match self.target(&table) {
+                Some(target) => target.database(),
+                None => continue,
+            };
+            progress::clear();
+            match explain::server_work(&db, command).await {
+                Ok(work) => eprintln!("Server: {}: {}", table, work),
+                Err(e) => eprintln!("Server: {}: can't explain: {}", table, e),
+            }
+        }
+    }
+
+    // false for statements that can't read a materialized view, and dry
+    // runs, which mustn't run anything, so views aren't refreshed for them
+    fn may_read_views(&self, sql: &str) -> bool {
+        if self.dry_run || set::parse(sql).is_some() {
+            return false;
+        }
+        !matches!(
+            prepare::parse(sql),
+            Some(Ok(Prepared::Prepare { .. })) | Some(Ok(Prepared::Deallocate { .. }))
+        )
+    }
+
+    // runs the queries of materialized views due a refresh, a view that
+    // fails to refresh keeps its last result
+    async fn refresh_materialized(&mut self) {
+        let due = self
+            .materialized
+            .iter()
+            .filter(|(_, view)| view.is_due())
+            .map(|(name, view)| (name.clone(), view.query.clone()))
+            .collect::<Vec<_>>();
+        for (name, query) in due {
+            match self.materialize(&name, &query).await {
+                Ok(rows) => tracing::info!(view = %name, rows, "refreshed materialized view"),
+                Err(e) => {
+                    progress::clear();
+                    eprintln!("failed to refresh {}: {}", name, e);
+                }
+            }
+            if let Some(view) = self.materialized.get_mut(&name) {
+                view.refreshed = Instant::now();
+            }
+        }
+    }
+
+    // registers the result of query as the table name, returning the
+    // number of rows
+    async fn materialize(
+        &mut self,
+        name: &str,
+        query: &str,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let df = self.context.sql(query)?;
+        let schema = df.to_logical_plan().schema().clone();
+        let batches = df.collect().await?;
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        let table = MemTable::try_new(schema, vec![batches])?;
+        self.register_table(name, Box::new(table));
+        Ok(rows)
+    }
+
+    // writes to the transcript if recording, a failure to write is logged
+    // rather than failing the statement
+    fn record<F, E>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Transcript) -> Result<(), E>,
+        E: std::fmt::Display,
+    {
+        if let Some(ref mut transcript) = self.transcript {
+            if let Err(e) = f(transcript) {
+                tracing::error!(error = %e, "failed to write transcript");
+            }
+        }
+    }
+
+    async fn execute_with_reconnect(
+        &mut self,
+        sql: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self.execute_once(sql).await {
+            Err(e) if schema_dir::is_connection_error(&*e) => {
+                if self.schema_dir.is_none() && self.attached.is_empty() {
+                    return Err(e);
+                }
+                eprintln!("connection lost, reconnecting");
+                // it's not known which cluster failed, so reconnect them all
+                for schema_dir in self.schema_dir.iter().chain(&self.attached) {
+                    schema_dir.reconnect().await?;
+                }
+                // a write may have partly applied before the connection was
+                // lost, so running it again could duplicate data
+                if self.is_write(sql) {
+                    return Err(e);
+                }
+                self.execute_once(sql).await
+            }
+            result => result,
+        }
+    }
+
+    // true if sql, or the prepared statement it executes, writes data
+    fn is_write(&self, sql: &str) -> bool {
+        match prepare::parse(sql) {
+            Some(Ok(Prepared::Execute { name, .. })) => self
+                .prepared
+                .get(name)
+                .map_or(false, |query| statements::is_write(query)),
+            _ => statements::is_write(sql),
+        }
+    }
+
+    async fn execute_once(&mut self, sql: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let start = Instant::now();
+
+        let bound;
+        let sql = match prepare::parse(sql) {
+            Some(Ok(Prepared::Prepare { name, query })) => {
+                self.prepared.insert(name.to_owned(), query.to_owned());
+                self.status("PREPARE");
+                return Ok(());
+            }
+            Some(Ok(Prepared::Deallocate { name })) => {
+                self.prepared
+                    .remove(name)
+                    .ok_or_else(|| format!("no prepared statement named {:?}", name))?;
+                self.status("DEALLOCATE");
+                return Ok(());
+            }
+            Some(Ok(Prepared::Execute { name, params })) => {
+                let query = self
+                    .prepared
+                    .get(name)
+                    .ok_or_else(|| format!("no prepared statement named {:?}", name))?;
+                bound = prepare::bind(query, &params)?;
+                bound.as_str()
+            }
+            Some(Err(e)) => return Err(e.into()),
+            None => sql,
+        };
+
+        if self.readonly && statements::is_write(sql) {
+            return Err("can't write in read only mode".into());
+        }
+
+        if let Some(set) = set::parse(sql) {
+            let set = set?;
+            if set.name.eq_ignore_ascii_case("snapshot_time") {
+                self.set_snapshot_time(&set.value).await?;
+            } else {
+                self.set(set.name, &set.value)?;
+            }
+            self.status("SET");
+            return Ok(());
+        }
+
+        if self.dry_run {
+            if let Some(query) = dry_run_query(sql) {
+                let explain = explain::Explain {
+                    analyze: false,
+                    query: query?,
+                };
+                return self.explain(&explain).await;
+            }
+        }
+
+        if let Some(create) = ddl::parse_temp_table(sql) {
+            let create = create?;
+            let rows = self.materialize(create.name, create.query).await?;
+            self.rows = Some(rows);
+            self.status(&format!("SELECT {}", rows));
+            return Ok(());
+        }
+
+        if let Some(create) = ddl::parse_materialized_view(sql) {
+            let create = create?;
+            let rows = self.materialize(create.name, create.query).await?;
+            self.materialized.insert(
+                create.name.to_owned(),
+                Materialized {
+                    query: create.query.to_owned(),
+                    refresh: create.refresh,
+                    refreshed: Instant::now(),
+                },
+            );
+            self.rows = Some(rows);
+            self.status(&format!("SELECT {}", rows));
+            return Ok(());
+        }
+
+        if let Some(name) = ddl::parse_drop_materialized_view(sql) {
+            let name = name?;
+            self.materialized
+                .remove(name)
+                .ok_or_else(|| format!("no materialized view named {:?}", name))?;
+            self.deregister_table(name);
+            self.status("DROP MATERIALIZED VIEW");
+            return Ok(());
+        }
+
+        if let Some(insert) = insert::parse(sql) {
+            let insert = insert?;
+            let batches = self.context.sql(insert.query)?.collect().await?;
+            let inserted = self.insert(insert.table, insert.columns, &batches).await?;
+            self.clear_result_cache();
+            self.rows = Some(inserted);
+            self.status(&format!("INSERT 0 {}", inserted));
+            return Ok(());
+        }
+
+        if let Some(create) = ddl::parse(sql) {
+            let create = create?;
+            let schema_dir = self
+                .schema_dir
+                .as_mut()
+                .ok_or("can't create MongoDB tables without a connection")?;
+            let table = schema_dir.table(create.name, create.db.as_deref(), create.schema)?;
+            self.register_table(create.name, Box::new(table));
+            self.status("CREATE EXTERNAL TABLE");
+            return Ok(());
+        }
+
+        if let Some(describe) = describe::parse(sql) {
+            let batch = self.describe(describe?)?;
+            self.display_batches(&[batch])?;
+            return Ok(());
+        }
+
+        if let Some(explain) = explain::parse(sql) {
+            return self.explain(&explain?).await;
+        }
+
+        if let Some(copy) = copy::parse(sql) {
+            let copy = copy?;
+            let df = self.context.sql(copy.query)?;
+            let schema = df.to_logical_plan().schema().clone();
+            let batches = df.collect().await?;
+            output::write_batches(copy.format, &schema, &batches, File::create(&copy.path)?)?;
+            let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+            self.status(&format!("COPY {}", rows));
+            self.report(start.elapsed(), rows, batches.len());
+            return Ok(());
+        }
+
+        let tables = match self.result_cache {
+            Some(_) => match self.context.create_logical_plan(sql)? {
+                // run for its side effect of registering a table
+                LogicalPlan::CreateExternalTable { .. } => None,
+                plan => Some(result_cache::tables(&plan)),
+            },
+            None => None,
+        };
+        if let Some(ref tables) = tables {
+            let versions = self.table_versions(tables);
+            let cached = self
+                .result_cache
+                .as_mut()
+                .and_then(|cache| cache.get(sql, &versions));
+            if let Some(batches) = cached {
+                tracing::debug!("result cache hit");
+                self.display_batches(&batches)?;
+                let rows = batches.iter().map(|b| b.num_rows()).sum();
+                self.report(start.elapsed(), rows, batches.len());
+                if !self.quiet {
+                    eprintln!("(cached result)");
+                }
+                return Ok(());
+            }
+        }
+
+        if output::is_streamable(self.format, &self.display) {
+            return self.stream_query(sql, start).await;
+        }
+
+        let batches = self.context.sql(sql)?.collect().await?;
+        let elapsed = start.elapsed();
+        tracing::debug!(?elapsed, batches = batches.len(), "query executed");
+        self.display_batches(&batches)?;
+        let rows = batches.iter().map(|b| b.num_rows()).sum();
+        self.report(elapsed, rows, batches.len());
+        if let Some(ref tables) = tables {
+            // taken after running, as the query may have loaded its tables
+            let versions = self.table_versions(tables);
+            if let Some(ref mut cache) = self.result_cache {
+                cache.insert(sql, batches, versions);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn clear_result_cache(&mut self) {
+        if let Some(ref mut cache) = self.result_cache {
+            cache.clear();
+        }
+    }
+
+    // tables other than MongoDB's only change by being registered again,
+    // which clears the result cache
+    fn table_versions(&self, tables: &[String]) -> Vec<(String, u64)> {
+        tables
+            .iter()
+            .map(|t| (t.clone(), self.target(t).map_or(0, |t| t.version())))
+            .collect()
+    }
+
+    async fn explain(
+        &mut self,
+        explain: &explain::Explain<'_>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let session = &*self;
+        let is_mongodb = |table: &str| session.target(table).is_some();
+        let text = explain::run(&self.context, explain, is_mongodb, self.display.color).await?;
+        progress::clear();
+        match self.output {
+            Some(ref mut file) => file.write_all(text.as_bytes())?,
+            None => print!("{}", text),
+        }
+        Ok(())
+    }
+
+    // writes results to the output file, or the terminal limited to max_rows
+    fn display_batches(
+        &mut self,
+        batches: &[RecordBatch],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        progress::clear();
+        self.pages = None;
+        self.record(|transcript| transcript.result(batches));
+        match self.output {
+            Some(ref mut file) => output::write_display(self.format, &self.display, batches, file),
+            None => {
+                check_terminal(self.format)?;
+                let stdout = io::stdout();
+                match self.limit() {
+                    Some(max_rows) => {
+                        let (shown, omitted) = output::truncate(batches, max_rows)?;
+                        output::write_display(self.format, &self.display, &shown, stdout.lock())?;
+                        if omitted > 0 {
+                            println!("\u{2026} and {} more rows, \\next to show them", omitted);
+                            self.pages = Some(Pages::new(batches.to_vec(), max_rows));
+                        }
+                        Ok(())
+                    }
+                    None => {
+                        output::write_display(self.format, &self.display, batches, stdout.lock())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Shows the next page of the last result too long to show at once.
+    pub fn next_page(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.turn_page(true)
+    }
+
+    /// Shows the previous page of the last result too long to show at once.
+    pub fn prev_page(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.turn_page(false)
+    }
+
+    fn turn_page(&mut self, forward: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let pages = self.pages.as_mut().ok_or("no result to page through")?;
+        let page = if forward {
+            pages.page + 1
+        } else {
+            pages
+                .page
+                .checked_sub(1)
+                .ok_or("already at the first page")?
+        };
+        if page >= pages.count() && pages.rows < pages.total {
+            return Err(format!(
+                "only the first {} of {} rows are kept, use LIMIT and OFFSET to see more",
+                pages.rows, pages.total
+            )
+            .into());
+        }
+        if page >= pages.count() {
+            return Err("already at the last page".into());
+        }
+        pages.page = page;
+        let batches = output::slice(&pages.batches, page * pages.page_size, pages.page_size)?;
+        progress::clear();
+        let stdout = io::stdout();
+        output::write_display(self.format, &self.display, &batches, stdout.lock())?;
+        println!("(page {} of {}, \\next or \\prev)", page + 1, pages.count());
+        Ok(())
+    }
+
+    // the columns of table, with their MongoDB field and whether filters on
+    // them can be sent to MongoDB
+    fn describe(&self, table: &str) -> Result<RecordBatch, Box<dyn std::error::Error>> {
+        let schema = self
+            .tables
+            .get(table)
+            .ok_or_else(|| format!("no table named {:?}", table))?;
+        let target = self.target(table);
+
+        let mut columns = StringBuilder::new(schema.fields().len());
+        let mut types = StringBuilder::new(schema.fields().len());
+        let mut nullable = BooleanBuilder::new(schema.fields().len());
+        let mut mongodb_fields = StringBuilder::new(schema.fields().len());
+        let mut pushdown = BooleanBuilder::new(schema.fields().len());
+        for field in schema.fields() {
+            columns.append_value(field.name())?;
+            types.append_value(&field.data_type().to_string())?;
+            nullable.append_value(field.is_nullable())?;
+            let mapped =
+                target.and_then(|t| t.schema.fields().iter().find(|f| f.name() == field.name()));
+            match mapped {
+                Some(mapped) => {
+                    mongodb_fields.append_value(mapped.mongodb_field())?;
+                    pushdown.append_value(
+                        mapped.transforms().is_empty()
+                            && mongodb_datafusion::supports_pushdown(field.data_type()),
+                    )?;
+                }
+                None => {
+                    mongodb_fields.append_null()?;
+                    pushdown.append_value(false)?;
+                }
+            }
+        }
+
+        let schema = Schema::new(vec![
+            Field::new("column", DataType::Utf8, false),
+            Field::new("type", DataType::Utf8, false),
+            Field::new("nullable", DataType::Boolean, false),
+            Field::new("mongodb_field", DataType::Utf8, true),
+            Field::new("pushdown", DataType::Boolean, false),
+        ]);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(columns.finish()),
+            Arc::new(types.finish()),
+            Arc::new(nullable.finish()),
+            Arc::new(mongodb_fields.finish()),
+            Arc::new(pushdown.finish()),
+        ];
+        Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+    }
+
+    // runs a query, writing each batch as it arrives rather than waiting
+    // for the whole result
+    async fn stream_query(
+        &mut self,
+        sql: &str,
+        start: Instant,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let plan = self.context.sql(sql)?.to_logical_plan();
+        let plan = self.context.optimize(&plan)?;
+        let mut plan = self.context.create_physical_plan(&plan)?;
+        if plan.output_partitioning().partition_count() > 1 {
+            plan = Arc::new(MergeExec::new(plan));
+        }
+        let mut stream = plan.execute(0).await?;
+
+        self.pages = None;
+        let recording = self.transcript.is_some();
+        let stdout = io::stdout();
+        let (out, limit): (Box<dyn Write + '_>, _) = match self.output {
+            Some(ref mut file) => (Box::new(file), None),
+            None => {
+                check_terminal(self.format)?;
+                (Box::new(stdout.lock()), self.limit())
+            }
+        };
+        let schema = plan.schema();
+        let mut writer = output::BatchWriter::new(self.format, &self.display, &schema, out)?;
+        let (mut rows, mut batches, mut omitted) = (0, 0, 0);
+        // the start of the result when limited or recording, to page through
+        // or record afterwards, capped so a long result isn't held in memory
+        let keep = match limit {
+            Some(limit) => limit.saturating_mul(MAX_KEPT_PAGES),
+            None if recording => transcript::MAX_ROWS,
+            None => 0,
+        };
+        let (mut kept, mut kept_rows) = (Vec::new(), 0);
+        while let Some(batch) = stream.next().await {
+            let batch = batch?;
+            batches += 1;
+            if kept_rows < keep {
+                let take = batch.num_rows().min(keep - kept_rows);
+                let (head, _) = output::truncate(&[batch.clone()], take)?;
+                kept.extend(head);
+                kept_rows += take;
+            }
+            // keep reading past the limit, to count the rows not shown
+            let shown = match limit {
+                Some(limit) => batch.num_rows().min(limit.saturating_sub(rows)),
+                None => batch.num_rows(),
+            };
+            if shown == batch.num_rows() {
+                writer.write(&batch)?;
+            } else if shown > 0 {
+                let (head, _) = output::truncate(&[batch.clone()], shown)?;
+                head.iter().try_for_each(|b| writer.write(b))?;
+            }
+            omitted += batch.num_rows() - shown;
+            rows += batch.num_rows();
+        }
+        writer.finish()?;
+        self.record(|transcript| transcript.partial_result(&kept, rows));
+        if omitted > 0 {
+            println!("\u{2026} and {} more rows, \\next to show them", omitted);
+            self.pages = limit.map(|limit| Pages::new_partial(kept, rows, limit));
+        }
+        let elapsed = start.elapsed();
+        tracing::debug!(?elapsed, batches, "query executed");
+        self.report(elapsed, rows, batches);
+        Ok(())
+    }
+
+    // writes batches to the collection behind table, returning the number of
+    // documents inserted
+    async fn insert(
+        &self,
+        table: &str,
+        columns: Option<Vec<&str>>,
+        batches: &[RecordBatch],
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let target = self
+            .target(table)
+            .ok_or_else(|| format!("{} isn't a MongoDB table", table))?;
+        if schema_dir::is_wildcard(target.schema.mongodb_collection()) {
+            return Err(format!(
+                "{} reads the collections matching {}, and can't be inserted in to",
+                table,
+                target.schema.mongodb_collection()
+            )
+            .into());
+        }
+        if let Some(ref view) = target.view {
+            return Err(format!(
+                "{} is a view on {}, and can't be inserted in to",
+                table, view.view_on
+            )
+            .into());
+        }
+
+        let fields = match columns {
+            Some(columns) => columns
+                .into_iter()
+                .map(|c| {
+                    target
+                        .schema
+                        .fields()
+                        .iter()
+                        .find(|f| f.name() == c)
+                        .cloned()
+                        .ok_or_else(|| format!("no column {} in {}", c, table))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => target.schema.fields().clone(),
+        };
+        // the transformed value would be written in place of the stored one
+        if let Some(field) = fields.iter().find(|f| !f.transforms().is_empty()) {
+            return Err(format!(
+                "column {} of {} is transformed when read, and can't be inserted in to",
+                field.name(),
+                table
+            )
+            .into());
+        }
+
+        let mut inserted = 0;
+        for batch in batches {
+            let documents = mongodb_arrow::record_batch_to_documents(batch, &fields)?;
+            if documents.is_empty() {
+                continue;
+            }
+            let result = match target.collection().insert_many(documents, None).await {
+                Ok(result) => result,
+                Err(e) => {
+                    if inserted > 0 {
+                        target.invalidation.invalidate().await;
+                    }
+                    return Err(format!("{}, after inserting {} documents", e, inserted).into());
+                }
+            };
+            inserted += result.inserted_ids.len();
+        }
+        if inserted > 0 {
+            target.invalidation.invalidate().await;
+        }
+        Ok(inserted)
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match name.to_ascii_lowercase().as_str() {
+            "batch_size" => {
+                let batch_size = value.parse()?;
+                if batch_size < 1 {
+                    return Err("batch_size must be at least 1".into());
+                }
+                self.context
+                    .state
+                    .lock()
+                    .expect("execution context state mutex poisoned")
+                    .config
+                    .batch_size = batch_size;
+            }
+            "max_rows" if value.eq_ignore_ascii_case("off") => self.max_rows = None,
+            "max_rows" => self.max_rows = Some(value.parse()?),
+            "error_policy" => {
+                let error_policy = value.parse()?;
+                self.schema_dir
+                    .as_ref()
+                    .ok_or("no MongoDB connection")?
+                    .set_error_policy(error_policy);
+                for schema_dir in &self.attached {
+                    schema_dir.set_error_policy(error_policy);
+                }
+                // results read under another policy may differ
+                self.clear_result_cache();
+            }
+            "format" => self.format = value.parse()?,
+            "null" => self.display.null = value.to_owned(),
+            _ => return Err(format!("unknown setting {:?}", name).into()),
+        }
+        Ok(())
+    }
+
+    // all MongoDB tables read at the same cluster time, or the latest data
+    // if 'off'
+    async fn set_snapshot_time(&mut self, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let time = if value.eq_ignore_ascii_case("off") {
+            None
+        } else {
+            Some(parse_cluster_time(value)?)
+        };
+        self.schema_dir
+            .as_ref()
+            .ok_or("no MongoDB connection")?
+            .set_snapshot_time(time)
+            .await;
+        for schema_dir in &self.attached {
+            schema_dir.set_snapshot_time(time).await;
+        }
+        // results cached at another time no longer apply
+        self.clear_result_cache();
+        Ok(())
+    }
+
+    // the status line for statements that don't return results, moved to
+    // stderr when stdout is binary results
+    fn status(&mut self, message: &str) {
+        self.record(|transcript| transcript.message(message));
+        if !self.quiet {
+            progress::clear();
+            if self.format.is_binary() && self.output.is_none() {
+                eprintln!("{}", message);
+            } else {
+                println!("{}", message);
+            }
+        }
+    }
+
+    // binary results written to stdout are always written in full
+    fn limit(&self) -> Option<usize> {
+        if self.format.is_binary() {
+            None
+        } else {
+            self.max_rows
+        }
+    }
+
+    // written to stderr to keep stdout clean for the results
+    fn report(&mut self, elapsed: Duration, rows: usize, batches: usize) {
+        self.rows = Some(rows);
+        if self.quiet {
+            return;
+        }
+        progress::clear();
+        eprintln!(
+            "({} {}, {} {})",
+            rows,
+            if rows == 1 { "row" } else { "rows" },
+            batches,
+            if batches == 1 { "batch" } else { "batches" },
+        );
+        if let Some(size) = self.sample {
+            eprintln!("Sampled: at most {} documents per MongoDB table", size);
+        }
+        if self.timing {
+            eprintln!("Time: {:.3}ms", elapsed.as_secs_f64() * 1000.0);
+        }
+    }
+}
+
+// binary output is only written to stdout if it's redirected
+fn check_terminal(format: Format) -> Result<(), String> {
+    if format.is_binary() && atty::is(atty::Stream::Stdout) {
+        return Err(format!(
+            "not writing {} output to a terminal, redirect it or use \\o FILE",
+            format
+        ));
+    }
+    Ok(())
+}
+
+// the query planned in place of running sql in a dry run, None for
+// statements that only change the session, which are run as normal
+fn dry_run_query(sql: &str) -> Option<Result<&str, String>> {
+    if let Some(create) = ddl::parse_temp_table(sql) {
+        return Some(create.map(|c| c.query));
+    }
+    if let Some(create) = ddl::parse_materialized_view(sql) {
+        return Some(create.map(|c| c.query));
+    }
+    if ddl::parse_drop_materialized_view(sql).is_some() {
+        return None;
+    }
+    if let Some(insert) = insert::parse(sql) {
+        return Some(insert.map(|i| i.query));
+    }
+    if let Some(copy) = copy::parse(sql) {
+        return Some(copy.map(|c| c.query));
+    }
+    match explain::parse(sql) {
+        Some(Ok(explain)) if explain.analyze => return Some(Ok(explain.query)),
+        Some(Ok(_)) => return None,
+        Some(Err(e)) => return Some(Err(e)),
+        None => (),
+    }
+    if ddl::parse(sql).is_some() || describe::parse(sql).is_some() {
+        return None;
+    }
+    Some(Ok(sql))
+}
+
+// whether e is a load going over the memory limit, which may have been
+// carried through DataFusion and Arrow errors
+fn is_memory_limit_error(e: &(dyn std::error::Error + 'static)) -> bool {
+    if e.is::<MemoryLimitExceeded>() {
+        return true;
+    }
+    match e.downcast_ref::<DataFusionError>() {
+        Some(DataFusionError::External(e)) => return is_memory_limit_error(&**e),
+        Some(DataFusionError::ArrowError(e)) => return is_memory_limit_error(e),
+        _ => (),
+    }
+    match e.downcast_ref::<ArrowError>() {
+        Some(ArrowError::ExternalError(e)) => is_memory_limit_error(&**e),
+        _ => false,
+    }
+}
+
+// a cluster time as seconds, optionally with an increment after a '.' or
+// ',', or an RFC 3339 date and time
+fn parse_cluster_time(value: &str) -> Result<Timestamp, Box<dyn std::error::Error>> {
+    let mut parts = value.splitn(2, |c| c == '.' || c == ',');
+    if let (Ok(time), increment) = (parts.next().unwrap_or_default().parse(), parts.next()) {
+        let increment = increment.map(str::parse).transpose()?.unwrap_or(0);
+        return Ok(Timestamp { time, increment });
+    }
+    let time = DateTime::parse_from_rfc3339(value)
+        .map_err(|e| format!("invalid snapshot_time {:?}: {}", value, e))?;
+    let time = u32::try_from(time.timestamp())
+        .map_err(|_| format!("snapshot_time {:?} is out of range", value))?;
+    Ok(Timestamp { time, increment: 0 })
+}
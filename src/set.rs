@@ -0,0 +1,32 @@
+use crate::statements::{identifier, keyword, quoted};
+
+/// A parsed `SET name = value` or `SET name TO value` statement.
+#[derive(Debug, PartialEq)]
+pub struct Set<'a> {
+    pub name: &'a str,
+    /// the value, with any quotes removed
+    pub value: String,
+}
+
+/// Returns None if `sql` isn't a SET statement, or an error if it is but
+/// it's malformed.
+pub fn parse(sql: &str) -> Option<Result<Set<'_>, String>> {
+    let rest = keyword(sql.trim(), "set")?;
+    Some(parse_set(rest))
+}
+
+fn parse_set(sql: &str) -> Result<Set<'_>, String> {
+    let (name, rest) = identifier(sql).ok_or("expected SET name = value")?;
+    let rest = rest.trim_start();
+    let rest = match rest.strip_prefix('=') {
+        Some(rest) => rest,
+        None => keyword(rest, "to").ok_or("expected = or TO after SET name")?,
+    };
+    let value = match quoted(rest) {
+        Some((value, rest)) if rest.trim().is_empty() => value,
+        Some(_) => return Err(format!("unexpected {:?} after SET value", rest.trim())),
+        None if rest.trim().is_empty() => return Err("expected a value for SET".to_owned()),
+        None => rest.trim().to_owned(),
+    };
+    Ok(Set { name, value })
+}
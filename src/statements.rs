@@ -0,0 +1,168 @@
+/// Splits `input` into `;` terminated statements, ignoring any `;` in quotes
+/// or comments.
+///
+/// Returns the complete statements, without the `;`, and whatever follows the
+/// last `;`, which will be empty or whitespace if input ended with a complete
+/// statement.
+pub fn split(input: &str) -> (Vec<&str>, &str) {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match quote {
+            // a doubled quote to escape a quote is handled by closing and
+            // immediately reopening the quoted section
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '-' if chars.peek().map(|&(_, c)| c) == Some('-') => {
+                    while let Some(&(_, c)) = chars.peek() {
+                        if c == '\n' {
+                            break;
+                        }
+                        chars.next();
+                    }
+                }
+                ';' => {
+                    statements.push(input[start..i].trim());
+                    start = i + 1;
+                }
+                _ => (),
+            },
+        }
+    }
+
+    statements.retain(|s| !s.is_empty());
+    (statements, &input[start..])
+}
+
+/// Returns true if `sql` is a statement that writes data, rather than only
+/// reading it.
+pub fn is_write(sql: &str) -> bool {
+    let sql = sql.trim();
+    if ["insert", "update", "delete"]
+        .iter()
+        .any(|k| keyword(sql, k).is_some())
+    {
+        return true;
+    }
+    // CREATE [TEMP | TEMPORARY] TABLE name AS query
+    keyword(sql, "create")
+        .map(|rest| {
+            keyword(rest, "temp")
+                .or_else(|| keyword(rest, "temporary"))
+                .unwrap_or(rest)
+        })
+        .and_then(|rest| keyword(rest, "table"))
+        .and_then(identifier)
+        .and_then(|(_, rest)| keyword(rest, "as"))
+        .is_some()
+}
+
+/// Splits `"(inner) rest"` in to `("inner", " rest")`, allowing for nested
+/// parentheses and quoted strings.
+pub fn parenthesised(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    if !s.starts_with('(') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((&s[1..i], &s[i + 1..]));
+                    }
+                }
+                _ => (),
+            },
+        }
+    }
+    None
+}
+
+/// Strips a leading case insensitive keyword.
+pub fn keyword<'a>(s: &'a str, keyword: &str) -> Option<&'a str> {
+    let s = s.trim_start();
+    let word = s.get(..keyword.len())?;
+    let rest = &s[keyword.len()..];
+    if word.eq_ignore_ascii_case(keyword)
+        && (rest.is_empty() || !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+    {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+/// Splits `"'quoted' rest"` in to `("quoted", " rest")`, unescaping `''`.
+pub fn quoted(s: &str) -> Option<(String, &str)> {
+    let s = s.trim_start();
+    if !s.starts_with('\'') {
+        return None;
+    }
+    let mut value = String::new();
+    let mut chars = s.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\'' {
+            if let Some(&(_, '\'')) = chars.peek() {
+                value.push('\'');
+                chars.next();
+            } else {
+                return Some((value, &s[i + 1..]));
+            }
+        } else {
+            value.push(c);
+        }
+    }
+    None
+}
+
+/// Splits `"name rest"` in to `("name", " rest")`.
+pub fn identifier(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    let end = s
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or_else(|| s.len());
+    if end == 0 {
+        None
+    } else {
+        Some((&s[..end], &s[end..]))
+    }
+}
+
+/// Splits a comma separated list, ignoring commas in quotes or parentheses.
+pub fn split_list(s: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    items.push(s[start..i].trim());
+                    start = i + 1;
+                }
+                _ => (),
+            },
+        }
+    }
+    items.push(s[start..].trim());
+    items
+}
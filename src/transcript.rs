@@ -0,0 +1,68 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use arrow::record_batch::RecordBatch;
+
+use crate::output::{self, Format};
+
+// results are cut short, a transcript is for reading rather than data
+pub const MAX_ROWS: usize = 20;
+
+/// Statements and their results recorded to a Markdown file, so findings
+/// can be shared as text rather than screenshots.
+pub struct Transcript {
+    file: File,
+    path: PathBuf,
+}
+
+impl Transcript {
+    /// Appends to the file at `path`, creating it if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self { file, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn statement(&mut self, sql: &str) -> io::Result<()> {
+        writeln!(self.file, "```sql\n{}\n```\n", sql.trim())
+    }
+
+    /// Records the first rows of a result as a table.
+    pub fn result(&mut self, batches: &[RecordBatch]) -> Result<(), Box<dyn std::error::Error>> {
+        let rows = batches.iter().map(|b| b.num_rows()).sum();
+        self.partial_result(batches, rows)
+    }
+
+    /// Records the first rows of a result of `rows` rows, of which
+    /// `batches` may be only the start.
+    pub fn partial_result(
+        &mut self,
+        batches: &[RecordBatch],
+        rows: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (shown, _) = output::truncate(batches, MAX_ROWS)?;
+        let omitted = rows - shown.iter().map(|b| b.num_rows()).sum::<usize>();
+        if shown.iter().all(|b| b.num_rows() == 0) {
+            writeln!(self.file, "_no rows_\n")?;
+            return Ok(());
+        }
+        output::write_batches(Format::Markdown, &shown[0].schema(), &shown, &mut self.file)?;
+        if omitted > 0 {
+            writeln!(self.file, "\n_\u{2026} and {} more rows_", omitted)?;
+        }
+        writeln!(self.file)?;
+        Ok(())
+    }
+
+    /// Records a status message or error.
+    pub fn message(&mut self, message: &str) -> io::Result<()> {
+        writeln!(self.file, "> {}\n", message)
+    }
+}
@@ -0,0 +1,306 @@
+use std::sync::Arc;
+
+use arrow::{
+    array::{
+        Array, ArrayRef, BinaryArray, BinaryBuilder, BooleanArray, Float64Array, Int64Array,
+        StringArray, StringBuilder, TimestampMillisecondBuilder,
+    },
+    datatypes::{DataType, TimeUnit},
+};
+use datafusion::{
+    error::{DataFusionError, Result},
+    execution::context::ExecutionContext,
+    physical_plan::functions::make_scalar_function,
+    prelude::create_udf,
+};
+use mongodb_datafusion::regexp;
+use serde_json::Value;
+
+/// Registers the MongoDB helper functions:
+///
+/// * `objectid_timestamp(id)`: the creation time embedded in a hex ObjectId
+/// * `objectid_hex(bytes)`: the hex string for a 12 byte binary ObjectId
+/// * `objectid_binary(id)`: the 12 byte binary for a hex ObjectId
+/// * `bson_type(json)`: the BSON type name of a JSON value
+/// * `json_extract(json, path)`: the value at a path like `$.a.b[0]`, as
+///   text, with `json_extract_int`, `json_extract_float`, and
+///   `json_extract_bool` variants for typed values
+/// * `regexp_match(string, pattern, options)`: MongoDB `$regex` matching
+///
+/// Invalid input gives null rather than an error, as Mongo data is rarely
+/// uniform.
+pub fn register(context: &mut ExecutionContext) {
+    context.register_udf(create_udf(
+        "objectid_timestamp",
+        vec![DataType::Utf8],
+        Arc::new(DataType::Timestamp(TimeUnit::Millisecond, None)),
+        make_scalar_function(objectid_timestamp),
+    ));
+    context.register_udf(create_udf(
+        "objectid_hex",
+        vec![DataType::Binary],
+        Arc::new(DataType::Utf8),
+        make_scalar_function(objectid_hex),
+    ));
+    context.register_udf(create_udf(
+        "objectid_binary",
+        vec![DataType::Utf8],
+        Arc::new(DataType::Binary),
+        make_scalar_function(objectid_binary),
+    ));
+    context.register_udf(create_udf(
+        "bson_type",
+        vec![DataType::Utf8],
+        Arc::new(DataType::Utf8),
+        make_scalar_function(bson_type),
+    ));
+    context.register_udf(regexp::regexp_match());
+    context.register_udf(create_udf(
+        "json_extract",
+        vec![DataType::Utf8, DataType::Utf8],
+        Arc::new(DataType::Utf8),
+        make_scalar_function(json_extract),
+    ));
+    context.register_udf(create_udf(
+        "json_extract_int",
+        vec![DataType::Utf8, DataType::Utf8],
+        Arc::new(DataType::Int64),
+        make_scalar_function(json_extract_int),
+    ));
+    context.register_udf(create_udf(
+        "json_extract_float",
+        vec![DataType::Utf8, DataType::Utf8],
+        Arc::new(DataType::Float64),
+        make_scalar_function(json_extract_float),
+    ));
+    context.register_udf(create_udf(
+        "json_extract_bool",
+        vec![DataType::Utf8, DataType::Utf8],
+        Arc::new(DataType::Boolean),
+        make_scalar_function(json_extract_bool),
+    ));
+}
+
+fn objectid_timestamp(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let ids = string_arg(args)?;
+    let mut builder = TimestampMillisecondBuilder::new(ids.len());
+    for i in 0..ids.len() {
+        match decode_objectid(ids, i) {
+            Some(bytes) => {
+                // the first 4 bytes are seconds since the epoch, big endian
+                let secs = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                builder.append_value(i64::from(secs) * 1_000)?;
+            }
+            None => builder.append_null()?,
+        }
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+fn objectid_hex(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let ids = args[0]
+        .as_any()
+        .downcast_ref::<BinaryArray>()
+        .ok_or_else(|| DataFusionError::Execution("expected a binary argument".to_owned()))?;
+    let mut builder = StringBuilder::new(ids.len());
+    for i in 0..ids.len() {
+        if ids.is_null(i) || ids.value(i).len() != 12 {
+            builder.append_null()?;
+        } else {
+            let hex = ids
+                .value(i)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            builder.append_value(&hex)?;
+        }
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+fn objectid_binary(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let ids = string_arg(args)?;
+    let mut builder = BinaryBuilder::new(ids.len());
+    for i in 0..ids.len() {
+        match decode_objectid(ids, i) {
+            Some(bytes) => builder.append_value(&bytes)?,
+            None => builder.append_null()?,
+        }
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+fn bson_type(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let values = string_arg(args)?;
+    let mut builder = StringBuilder::new(values.len());
+    for i in 0..values.len() {
+        if values.is_null(i) {
+            builder.append_null()?;
+            continue;
+        }
+        let name = match serde_json::from_str(values.value(i)) {
+            Ok(Value::Null) => "null",
+            Ok(Value::Bool(_)) => "bool",
+            Ok(Value::Number(n)) if n.is_f64() => "double",
+            Ok(Value::Number(n)) => match n.as_i64() {
+                Some(n) if i32::MIN as i64 <= n && n <= i32::MAX as i64 => "int",
+                _ => "long",
+            },
+            Ok(Value::String(_)) => "string",
+            Ok(Value::Array(_)) => "array",
+            Ok(Value::Object(_)) => "object",
+            Err(_) => {
+                builder.append_null()?;
+                continue;
+            }
+        };
+        builder.append_value(name)?;
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+fn json_extract(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let mut builder = StringBuilder::new(args[0].len());
+    for value in extract_values(args)? {
+        match value {
+            Some(Value::Null) | None => builder.append_null()?,
+            Some(Value::String(s)) => builder.append_value(&s)?,
+            Some(value) => builder.append_value(&value.to_string())?,
+        }
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+fn json_extract_int(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let values = extract_values(args)?;
+    let array = values
+        .iter()
+        .map(|v| v.as_ref().and_then(Value::as_i64))
+        .collect::<Int64Array>();
+    Ok(Arc::new(array))
+}
+
+fn json_extract_float(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let values = extract_values(args)?;
+    let array = values
+        .iter()
+        .map(|v| v.as_ref().and_then(Value::as_f64))
+        .collect::<Float64Array>();
+    Ok(Arc::new(array))
+}
+
+fn json_extract_bool(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let values = extract_values(args)?;
+    let array = values
+        .iter()
+        .map(|v| v.as_ref().and_then(Value::as_bool))
+        .collect::<BooleanArray>();
+    Ok(Arc::new(array))
+}
+
+// the value at the path for each row, None where the JSON is invalid or
+// has nothing at the path
+fn extract_values(args: &[ArrayRef]) -> Result<Vec<Option<Value>>> {
+    let json = string_arg(args)?;
+    let paths = args[1]
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .ok_or_else(|| DataFusionError::Execution("expected a string path".to_owned()))?;
+
+    // the path is almost always a literal, so only parse it when it changes
+    let mut parsed_path = None;
+    let mut elements = Vec::new();
+    let mut values = Vec::with_capacity(json.len());
+    for i in 0..json.len() {
+        if json.is_null(i) || paths.is_null(i) {
+            values.push(None);
+            continue;
+        }
+        let path = paths.value(i);
+        if parsed_path != Some(path) {
+            elements = parse_path(path).ok_or_else(|| {
+                DataFusionError::Execution(format!("invalid JSON path {:?}", path))
+            })?;
+            parsed_path = Some(path);
+        }
+        let value = serde_json::from_str::<Value>(json.value(i))
+            .ok()
+            .and_then(|v| lookup(v, &elements));
+        values.push(value);
+    }
+    Ok(values)
+}
+
+enum PathElement {
+    Key(String),
+    Index(usize),
+}
+
+// parses paths like $.a.b[0] and $["a"]
+fn parse_path(path: &str) -> Option<Vec<PathElement>> {
+    let mut rest = path.strip_prefix('$')?;
+    let mut elements = Vec::new();
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(|c| c == '.' || c == '[').unwrap_or_else(|| r.len());
+            if end == 0 {
+                return None;
+            }
+            elements.push(PathElement::Key(r[..end].to_owned()));
+            rest = &r[end..];
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']')?;
+            let inner = r[..end].trim();
+            let element = if let Ok(index) = inner.parse() {
+                PathElement::Index(index)
+            } else {
+                let key = inner
+                    .strip_prefix('"')
+                    .and_then(|k| k.strip_suffix('"'))
+                    .or_else(|| inner.strip_prefix('\'').and_then(|k| k.strip_suffix('\'')))?;
+                PathElement::Key(key.to_owned())
+            };
+            elements.push(element);
+            rest = &r[end + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some(elements)
+}
+
+fn lookup(mut value: Value, path: &[PathElement]) -> Option<Value> {
+    for element in path {
+        value = match (element, value) {
+            (PathElement::Key(key), Value::Object(mut map)) => map.remove(key)?,
+            (PathElement::Index(index), Value::Array(mut array)) if *index < array.len() => {
+                array.swap_remove(*index)
+            }
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+fn string_arg(args: &[ArrayRef]) -> Result<&StringArray> {
+    args[0]
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .ok_or_else(|| DataFusionError::Execution("expected a string argument".to_owned()))
+}
+
+// the 12 bytes of the hex ObjectId at row i, or None if it's null or invalid
+fn decode_objectid(ids: &StringArray, i: usize) -> Option<[u8; 12]> {
+    if ids.is_null(i) {
+        return None;
+    }
+    let hex = ids.value(i);
+    if hex.len() != 24 || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; 12];
+    for (j, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[j * 2..j * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}